@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::item::ItemStack;
+use crate::world::registry::BlockRegistry;
+use crate::world::BlockType;
+
+/// Recipes the game ships with, used when there is no recipe file
+const BUILTIN_RECIPES: &str = include_str!("../assets/recipes.ron");
+
+/// Largest grid and pattern side
+pub const MAX_GRID_SIZE: usize = 3;
+
+/// One entry of `recipes.ron`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RecipeDefinition {
+    /// Rows top down, a character per cell, space for an empty one
+    pub pattern: Vec<String>,
+    /// Block names of the pattern characters
+    pub ingredients: HashMap<char, String>,
+    pub result: String,
+    #[serde(default = "one")]
+    pub count: u32,
+}
+
+fn one() -> u32 {
+    1
+}
+
+/// Cells cut down to the rows and columns with something in them, row by row
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Shape {
+    width: usize,
+    cells: Vec<Option<BlockType>>,
+}
+
+impl Shape {
+    /// `cells` are rows of `width`
+    fn trimmed(width: usize, cells: &[Option<BlockType>]) -> Self {
+        let filled = |idx: usize| cells[idx].is_some();
+        let rows = cells.len() / width;
+        let row_filled = |row: usize| (0..width).any(|col| filled(row * width + col));
+        let col_filled = |col: usize| (0..rows).any(|row| filled(row * width + col));
+        let (Some(top), Some(left)) = ((0..rows).find(|&row| row_filled(row)), (0..width).find(|&col| col_filled(col))) else {
+            return Self { width: 0, cells: Vec::new() };
+        };
+        let bottom = (0..rows).rfind(|&row| row_filled(row)).unwrap_or(top);
+        let right = (0..width).rfind(|&col| col_filled(col)).unwrap_or(left);
+        let cells = (top..=bottom)
+            .flat_map(|row| (left..=right).map(move |col| row * width + col))
+            .map(|idx| cells[idx])
+            .collect();
+        Self { width: right - left + 1, cells }
+    }
+
+    fn height(&self) -> usize {
+        self.cells.len().checked_div(self.width).unwrap_or(0)
+    }
+}
+
+/// Blocks laid out in the grid the way the pattern shows give the `result`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recipe {
+    shape: Shape,
+    pub result: ItemStack,
+}
+
+#[allow(dead_code)]
+impl Recipe {
+    /// (width, height) of the pattern without empty border rows and columns
+    pub fn size(&self) -> (usize, usize) {
+        (self.shape.width, self.shape.height())
+    }
+}
+
+/// All known recipes, with block names looked up in the `BlockRegistry`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RecipeBook {
+    recipes: Vec<Recipe>,
+}
+
+#[allow(dead_code)]
+impl RecipeBook {
+    pub fn parse(text: &str, registry: &BlockRegistry) -> io::Result<Self> {
+        let definitions: Vec<RecipeDefinition> = ron::from_str(text).map_err(|err| invalid_data(err.to_string()))?;
+        Self::from_definitions(definitions, registry)
+    }
+
+    pub fn load(path: &Path, registry: &BlockRegistry) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?, registry)
+    }
+
+    /// The recipes the game ships with, fails if `registry` misses their blocks
+    pub fn builtin(registry: &BlockRegistry) -> io::Result<Self> {
+        Self::parse(BUILTIN_RECIPES, registry)
+    }
+
+    /// Checks pattern sizes, pattern characters, block names and counts
+    pub fn from_definitions(definitions: Vec<RecipeDefinition>, registry: &BlockRegistry) -> io::Result<Self> {
+        let block = |name: &str| {
+            registry.by_name(name).ok_or_else(|| invalid_data(format!("unknown block {:?}", name)))
+        };
+        let mut recipes = Vec::new();
+        for definition in definitions {
+            let name = &definition.result;
+            let result = block(name)?;
+            let width = definition.pattern.first().map_or(0, |row| row.chars().count());
+            if definition.pattern.len() > MAX_GRID_SIZE || width > MAX_GRID_SIZE {
+                return Err(invalid_data(format!("{}: pattern is over {}x{}", name, MAX_GRID_SIZE, MAX_GRID_SIZE)));
+            }
+            if definition.pattern.iter().any(|row| row.chars().count() != width) {
+                return Err(invalid_data(format!("{}: pattern rows differ in length", name)));
+            }
+            if definition.count == 0 || definition.count > ItemStack::MAX_SIZE {
+                return Err(invalid_data(format!("{}: count is not 1..={}", name, ItemStack::MAX_SIZE)));
+            }
+            let mut cells = Vec::new();
+            for symbol in definition.pattern.iter().flat_map(|row| row.chars()) {
+                let cell = match symbol {
+                    ' ' => None,
+                    _ => {
+                        let ingredient = definition.ingredients.get(&symbol).ok_or_else(|| {
+                            invalid_data(format!("{}: {:?} is not in the ingredients", name, symbol))
+                        })?;
+                        Some(block(ingredient)?)
+                    }
+                };
+                cells.push(cell);
+            }
+            let shape = Shape::trimmed(width.max(1), &cells);
+            if shape.cells.is_empty() {
+                return Err(invalid_data(format!("{}: pattern is empty", name)));
+            }
+            recipes.push(Recipe { shape, result: ItemStack::new(result, definition.count) });
+        }
+        Ok(Self { recipes })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Recipe> {
+        self.recipes.iter()
+    }
+
+    /// The recipe the blocks in `grid` make, the first one in the file if several do
+    pub fn find(&self, grid: &CraftingGrid) -> Option<&Recipe> {
+        let shape = grid.shape();
+        self.recipes.iter().find(|recipe| recipe.shape == shape)
+    }
+}
+
+/// Square grid of crafting slots, 2x2 in the inventory or 3x3 at a crafting table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CraftingGrid {
+    size: usize,
+    slots: [Option<ItemStack>; MAX_GRID_SIZE * MAX_GRID_SIZE],
+}
+
+#[allow(dead_code)]
+impl CraftingGrid {
+    /// The grid of the inventory screen
+    pub const INVENTORY_SIZE: usize = 2;
+
+    /// Panics if `size` is not 1..=`MAX_GRID_SIZE`
+    pub fn new(size: usize) -> Self {
+        assert!((1..=MAX_GRID_SIZE).contains(&size), "crafting grid size {}", size);
+        Self { size, slots: Default::default() }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Row by row, `size` squared of them
+    pub fn slots(&self) -> &[Option<ItemStack>] {
+        &self.slots[..self.size * self.size]
+    }
+
+    pub fn slot_mut(&mut self, idx: usize) -> &mut Option<ItemStack> {
+        &mut self.slots[..self.size * self.size][idx]
+    }
+
+    /// What crafting gives now
+    pub fn result(&self, recipes: &RecipeBook) -> Option<ItemStack> {
+        Some(recipes.find(self)?.result)
+    }
+
+    /// Uses up one block of every filled slot for the result
+    pub fn craft(&mut self, recipes: &RecipeBook) -> Option<ItemStack> {
+        let result = self.result(recipes)?;
+        for slot in self.slots.iter_mut() {
+            if let Some(stack) = slot {
+                stack.count -= 1;
+                if stack.count == 0 {
+                    *slot = None;
+                }
+            }
+        }
+        Some(result)
+    }
+
+    /// Empties the grid, like when its screen is closed
+    pub fn take_all(&mut self) -> Vec<ItemStack> {
+        self.slots.iter_mut().filter_map(Option::take).collect()
+    }
+
+    fn shape(&self) -> Shape {
+        let cells: Vec<_> = self.slots().iter().map(|slot| slot.map(|stack| stack.block)).collect();
+        Shape::trimmed(self.size, &cells)
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(name: &str) -> BlockType {
+        BlockRegistry::global().by_name(name).unwrap()
+    }
+
+    fn parse(text: &str) -> io::Result<RecipeBook> {
+        RecipeBook::parse(text, BlockRegistry::global())
+    }
+
+    fn builtin() -> RecipeBook {
+        RecipeBook::builtin(BlockRegistry::global()).unwrap()
+    }
+
+    /// `cells` are (slot, block, count)
+    fn grid(size: usize, cells: &[(usize, &str, u32)]) -> CraftingGrid {
+        let mut grid = CraftingGrid::new(size);
+        for &(idx, name, count) in cells {
+            *grid.slot_mut(idx) = Some(ItemStack::new(block(name), count));
+        }
+        grid
+    }
+
+    #[test]
+    fn builtin_recipes_load() {
+        let recipes = builtin();
+        let planks = recipes.iter().find(|recipe| recipe.result.block == block("planks")).unwrap();
+        assert_eq!(planks.result.count, 4);
+        assert_eq!(planks.size(), (1, 1));
+        let table = recipes.iter().find(|recipe| recipe.result.block == block("crafting_table")).unwrap();
+        assert_eq!(table.result.count, 1);
+        assert_eq!(table.size(), (2, 2));
+    }
+
+    #[test]
+    fn patterns_match_anywhere_in_the_grid() {
+        let recipes = builtin();
+        let planks = Some(ItemStack::new(block("planks"), 4));
+        for idx in 0..4 {
+            assert_eq!(grid(2, &[(idx, "log", 1)]).result(&recipes), planks);
+        }
+        for idx in 0..9 {
+            assert_eq!(grid(3, &[(idx, "log", 1)]).result(&recipes), planks);
+        }
+        let table = Some(ItemStack::new(block("crafting_table"), 1));
+        assert_eq!(grid(2, &[(0, "planks", 1), (1, "planks", 1), (2, "planks", 1), (3, "planks", 1)]).result(&recipes), table);
+        // the lower right corner of the big grid
+        assert_eq!(grid(3, &[(4, "planks", 1), (5, "planks", 1), (7, "planks", 1), (8, "planks", 1)]).result(&recipes), table);
+    }
+
+    #[test]
+    fn shape_and_blocks_must_match() {
+        let recipes = builtin();
+        assert_eq!(CraftingGrid::new(3).result(&recipes), None);
+        // three planks are no table
+        assert_eq!(grid(2, &[(0, "planks", 1), (1, "planks", 1), (2, "planks", 1)]).result(&recipes), None);
+        // neither are four in a row
+        assert_eq!(grid(3, &[(0, "planks", 1), (1, "planks", 1), (2, "planks", 1), (3, "planks", 1)]).result(&recipes), None);
+        // an extra block spoils the recipe
+        assert_eq!(grid(3, &[(0, "log", 1), (8, "dirt", 1)]).result(&recipes), None);
+        assert_eq!(grid(2, &[(0, "planks", 1), (1, "planks", 1), (2, "planks", 1), (3, "stone", 1)]).result(&recipes), None);
+    }
+
+    #[test]
+    fn big_patterns_need_the_big_grid() {
+        let recipes = parse(
+            "[(pattern: [\"S S\", \" S \", \"S S\"], ingredients: {'S': \"sand\"}, result: \"glass\", count: 3)]",
+        )
+        .unwrap();
+        assert_eq!(recipes.iter().next().unwrap().size(), (3, 3));
+        let cross = [(0, "sand", 1), (2, "sand", 1), (4, "sand", 1), (6, "sand", 1), (8, "sand", 1)];
+        assert_eq!(grid(3, &cross).result(&recipes), Some(ItemStack::new(block("glass"), 3)));
+        let corners = [(0, "sand", 1), (1, "sand", 1), (2, "sand", 1), (3, "sand", 1)];
+        assert_eq!(grid(2, &corners).result(&recipes), None);
+        // empty cells of the pattern must stay empty
+        let filled = [(0, "sand", 1), (1, "sand", 1), (2, "sand", 1), (4, "sand", 1), (6, "sand", 1), (8, "sand", 1)];
+        assert_eq!(grid(3, &filled).result(&recipes), None);
+    }
+
+    #[test]
+    fn padded_patterns_are_trimmed() {
+        let recipes = parse("[(pattern: [\"   \", \" L \", \"   \"], ingredients: {'L': \"log\"}, result: \"dirt\")]").unwrap();
+        assert_eq!(recipes.iter().next().unwrap().size(), (1, 1));
+        assert_eq!(grid(2, &[(3, "log", 1)]).result(&recipes), Some(ItemStack::new(BlockType::DIRT, 1)));
+    }
+
+    #[test]
+    fn crafting_uses_one_block_per_slot() {
+        let recipes = builtin();
+        let mut grid = grid(2, &[(0, "planks", 3), (1, "planks", 1), (2, "planks", 2), (3, "planks", 1)]);
+        assert_eq!(grid.craft(&recipes), Some(ItemStack::new(block("crafting_table"), 1)));
+        assert_eq!(grid.slots()[0], Some(ItemStack::new(block("planks"), 2)));
+        assert_eq!(grid.slots()[1], None);
+        assert_eq!(grid.slots()[2], Some(ItemStack::new(block("planks"), 1)));
+        // what is left makes no table
+        let before = grid.clone();
+        assert_eq!(grid.craft(&recipes), None);
+        assert_eq!(grid, before);
+        assert_eq!(grid.take_all(), vec![ItemStack::new(block("planks"), 2), ItemStack::new(block("planks"), 1)]);
+        assert!(grid.slots().iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn bad_recipes_are_rejected() {
+        let with = |pattern: &str, ingredients: &str, result: &str, count: u32| {
+            parse(&format!("[(pattern: {}, ingredients: {}, result: {:?}, count: {})]", pattern, ingredients, result, count))
+        };
+        assert!(with("[\"L\"]", "{'L': \"log\"}", "planks", 4).is_ok());
+        assert!(with("[\"L\"]", "{'L': \"granite\"}", "planks", 4).is_err());
+        assert!(with("[\"L\"]", "{'L': \"log\"}", "granite", 4).is_err());
+        assert!(with("[\"X\"]", "{'L': \"log\"}", "planks", 4).is_err());
+        assert!(with("[\"LLLL\"]", "{'L': \"log\"}", "planks", 4).is_err());
+        assert!(with("[\"L\", \"L\", \"L\", \"L\"]", "{'L': \"log\"}", "planks", 4).is_err());
+        assert!(with("[\"LL\", \"L\"]", "{'L': \"log\"}", "planks", 4).is_err());
+        assert!(with("[\"  \"]", "{}", "planks", 4).is_err());
+        assert!(with("[]", "{}", "planks", 4).is_err());
+        assert!(with("[\"L\"]", "{'L': \"log\"}", "planks", 0).is_err());
+        assert!(with("[\"L\"]", "{'L': \"log\"}", "planks", ItemStack::MAX_SIZE + 1).is_err());
+        assert_eq!(parse("[(pattern: [\"L\"]").unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,199 @@
+use macroquad::prelude::*;
+
+use crate::inventory::Inventory;
+use crate::item::ItemStack;
+use crate::physics::{Aabb, GRAVITY, MAX_FALL_SPEED};
+use crate::world::render::mesh::build_block_mesh;
+use crate::world::render::FaceShading;
+use crate::world::{BlockPos, BlockSource, World};
+
+/// Side of the cube a dropped item is drawn as
+pub const ITEM_SIZE: f32 = 0.25;
+/// Upward speed of a drop popping out of a broken block
+const POP_SPEED: f32 = 4.0;
+/// Seconds before a new drop can be picked up, so it is seen popping out
+const PICKUP_DELAY: f32 = 0.4;
+/// How close to the player box a drop gets picked up
+const PICKUP_REACH: f32 = 1.0;
+/// Seconds a drop lies around before it is gone
+const DESPAWN_AGE: f32 = 300.0;
+/// Height and radians per second of the bobbing of a resting drop
+const BOB_HEIGHT: f32 = 0.06;
+const BOB_SPEED: f32 = 2.5;
+
+/// Blocks lying in the world to be picked up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DroppedItem {
+    pub stack: ItemStack,
+    /// Middle of the item cube
+    pub pos: Vec3,
+    /// Drops only fall, blocks per second
+    pub vertical_speed: f32,
+    /// Seconds since it was dropped
+    pub age: f32,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct DroppedItems(Vec<DroppedItem>);
+
+#[allow(dead_code)]
+impl DroppedItems {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pops `stack` out of the middle of `pos`, like from a broken block
+    pub fn spawn_from_block(&mut self, pos: BlockPos, stack: ItemStack) {
+        let center = vec3(pos.x as f32, pos.y as f32, pos.z as f32) + Vec3::splat(0.5);
+        self.0.push(DroppedItem { stack, pos: center, vertical_speed: POP_SPEED, age: 0.0 });
+    }
+
+    /// Puts `stack` down at `pos` without popping it
+    pub fn spawn(&mut self, pos: Vec3, stack: ItemStack) {
+        self.0.push(DroppedItem { stack, pos, vertical_speed: 0.0, age: 0.0 });
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &DroppedItem> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Drops fall until they rest on a solid block, old ones are removed.
+    /// `dt` must be short enough for a drop to fall at most one block per step
+    pub fn step(&mut self, world: &impl BlockSource, dt: f32) {
+        for item in &mut self.0 {
+            item.age += dt;
+            item.vertical_speed = (item.vertical_speed - GRAVITY * dt).max(-MAX_FALL_SPEED);
+            let half = ITEM_SIZE / 2.0;
+            let bottom = item.pos.y - half + item.vertical_speed * dt;
+            let below = BlockPos::from(vec3(item.pos.x, bottom, item.pos.z));
+            if item.vertical_speed < 0.0 && world.get_block(below).block_type.is_solid() {
+                item.pos.y = below.y as f32 + 1.0 + half;
+                item.vertical_speed = 0.0;
+            } else {
+                item.pos.y = bottom + half;
+            }
+        }
+        self.0.retain(|item| item.age < DESPAWN_AGE);
+    }
+
+    /// Moves drops close to `player` into the inventory, what does not fit stays lying.
+    /// Returns true if anything was picked up
+    pub fn pick_up(&mut self, player: &Aabb, inventory: &mut Inventory) -> bool {
+        let mut picked = false;
+        for item in &mut self.0 {
+            let nearest = item.pos.clamp(player.min, player.max);
+            if item.age < PICKUP_DELAY || nearest.distance(item.pos) > PICKUP_REACH {
+                continue;
+            }
+            let left = inventory.add(item.stack);
+            picked |= left != Some(item.stack);
+            item.stack.count = left.map_or(0, |left| left.count);
+        }
+        self.0.retain(|item| item.stack.count > 0);
+        picked
+    }
+}
+
+/// Small cubes of the dropped blocks, lit like the block they are in.
+/// Meant for the opaque chunk material
+pub fn draw_dropped_items(items: &DroppedItems, world: &World, atlas: &Texture2D, shading: &FaceShading) {
+    for item in items.iter() {
+        let bob = if item.vertical_speed == 0.0 { (item.age * BOB_SPEED).sin() * BOB_HEIGHT + BOB_HEIGHT } else { 0.0 };
+        let origin = item.pos - Vec3::splat(ITEM_SIZE / 2.0) + vec3(0.0, bob, 0.0);
+        let light = world.get_light(BlockPos::from(item.pos));
+        let buffers = build_block_mesh(item.stack.block, origin, ITEM_SIZE, light, shading);
+        draw_mesh(&Mesh { vertices: buffers.vertices, indices: buffers.indices, texture: Some(atlas.clone()) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hotbar::Hotbar;
+    use crate::world::test_world::stone_floor;
+    use crate::world::BlockType;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    fn run(items: &mut DroppedItems, world: &World, seconds: f32) {
+        for _ in 0..(seconds / DT) as usize {
+            items.step(world, DT);
+        }
+    }
+
+    #[test]
+    fn drops_pop_up_and_land_on_the_floor() {
+        let world = stone_floor(-2..2, -2..2);
+        let mut items = DroppedItems::new();
+        items.spawn_from_block(BlockPos::new(0, 1, 0), ItemStack::new(BlockType::DIRT, 1));
+        items.step(&world, DT);
+        let item = *items.iter().next().unwrap();
+        assert!(item.pos.y > 1.5);
+        run(&mut items, &world, 2.0);
+        let item = *items.iter().next().unwrap();
+        assert_eq!(item.pos.y, 1.0 + ITEM_SIZE / 2.0);
+        assert_eq!(item.vertical_speed, 0.0);
+    }
+
+    #[test]
+    fn fast_drops_do_not_fall_through() {
+        let world = stone_floor(-2..2, -2..2);
+        let mut items = DroppedItems::new();
+        items.spawn(vec3(0.5, 40.0, 0.5), ItemStack::new(BlockType::DIRT, 1));
+        run(&mut items, &world, 5.0);
+        assert_eq!(items.iter().next().unwrap().pos.y, 1.0 + ITEM_SIZE / 2.0);
+    }
+
+    #[test]
+    fn old_drops_despawn() {
+        let world = stone_floor(-2..2, -2..2);
+        let mut items = DroppedItems::new();
+        items.spawn(vec3(0.5, 1.2, 0.5), ItemStack::new(BlockType::DIRT, 1));
+        items.step(&world, DESPAWN_AGE - 1.0);
+        assert_eq!(items.len(), 1);
+        items.step(&world, 1.0);
+        assert!(items.is_empty());
+    }
+
+    #[test]
+    fn pickup_waits_and_needs_the_player_close() {
+        let mut items = DroppedItems::new();
+        let mut inventory = Inventory::default();
+        let player = Aabb::player(vec3(0.5, 1.0, 0.5));
+        items.spawn(vec3(0.5, 1.1, 1.5), ItemStack::new(BlockType::SAND, 3));
+        items.spawn(vec3(5.5, 1.1, 0.5), ItemStack::new(BlockType::DIRT, 1));
+        assert!(!items.pick_up(&player, &mut inventory));
+
+        run(&mut items, &stone_floor(-2..2, -2..2), PICKUP_DELAY + 0.1);
+        assert!(items.pick_up(&player, &mut inventory));
+        assert_eq!(inventory.count(BlockType::SAND), 3);
+        assert_eq!(inventory.count(BlockType::DIRT), 0);
+        assert_eq!(items.len(), 1);
+    }
+
+    #[test]
+    fn full_inventory_leaves_the_rest_lying() {
+        let mut items = DroppedItems::new();
+        let mut inventory = Inventory::new(Hotbar::with_blocks([BlockType::STONE]));
+        for idx in 1..crate::inventory::INVENTORY_SLOTS {
+            *inventory.slot_mut(idx) = Some(ItemStack::new(BlockType::STONE, ItemStack::MAX_SIZE - 1));
+        }
+        let player = Aabb::player(vec3(0.5, 1.0, 0.5));
+        items.spawn(vec3(0.5, 1.5, 0.5), ItemStack::new(BlockType::STONE, 50));
+        items.spawn(vec3(0.5, 1.5, 0.5), ItemStack::new(BlockType::DIRT, 1));
+        run(&mut items, &stone_floor(-2..2, -2..2), PICKUP_DELAY + 0.1);
+        assert!(items.pick_up(&player, &mut inventory));
+        assert_eq!(items.len(), 2);
+        let left: Vec<_> = items.iter().map(|item| item.stack).collect();
+        assert_eq!(left, vec![ItemStack::new(BlockType::STONE, 15), ItemStack::new(BlockType::DIRT, 1)]);
+        assert!(!items.pick_up(&player, &mut inventory));
+    }
+}
@@ -7,6 +7,7 @@ const DEFAULT_GRABBED: bool = true;
 pub struct Grabbed(pub bool);
 
 impl Grabbed {
+    #[allow(dead_code)]
     pub const fn new(grabbed: bool) -> Self {
         Self(grabbed)
     }
@@ -36,6 +37,13 @@ pub fn update_grabbed_state_and_cursor_on_tab_press(grabbed: &mut Grabbed) {
     }
 }
 
+/// Grabbed hides the cursor, like for looking around; released shows it, like on a screen
+pub fn set_grabbed_state_and_cursor(grabbed: &mut Grabbed, grab: bool) {
+    grabbed.0 = grab;
+    set_cursor_grab(grab);
+    show_mouse(!grab);
+}
+
 pub fn setup_mouse_cursor() {
     set_cursor_grab(DEFAULT_GRABBED);
     show_mouse(!DEFAULT_GRABBED);
@@ -0,0 +1,155 @@
+use macroquad::prelude::*;
+
+use crate::item::{insert_into_slots, ItemStack};
+use crate::world::BlockType;
+
+pub const HOTBAR_SLOTS: usize = 9;
+
+const SLOT_KEYS: [KeyCode; HOTBAR_SLOTS] = [
+    KeyCode::Key1, KeyCode::Key2, KeyCode::Key3,
+    KeyCode::Key4, KeyCode::Key5, KeyCode::Key6,
+    KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+];
+
+/// The blocks at hand, the selected one is what gets placed
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Hotbar {
+    slots: [Option<ItemStack>; HOTBAR_SLOTS],
+    selected: usize,
+}
+
+#[allow(dead_code)]
+impl Hotbar {
+    /// Full stacks of `blocks` from the first slot on, blocks over the slot count are left out
+    pub fn with_blocks(blocks: impl IntoIterator<Item = BlockType>) -> Self {
+        let mut hotbar = Self::default();
+        for (slot, block) in hotbar.slots.iter_mut().zip(blocks) {
+            *slot = Some(ItemStack::full(block));
+        }
+        hotbar
+    }
+
+    pub fn slots(&self) -> &[Option<ItemStack>; HOTBAR_SLOTS] {
+        &self.slots
+    }
+
+    pub fn slots_mut(&mut self) -> &mut [Option<ItemStack>; HOTBAR_SLOTS] {
+        &mut self.slots
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Slots out of the hotbar are ignored
+    pub fn select(&mut self, slot: usize) {
+        if slot < HOTBAR_SLOTS {
+            self.selected = slot;
+        }
+    }
+
+    /// Moves the selection by `steps` slots to the right, around the ends
+    pub fn scroll(&mut self, steps: i32) {
+        self.selected = (self.selected as i32 + steps).rem_euclid(HOTBAR_SLOTS as i32) as usize;
+    }
+
+    pub fn selected_stack(&self) -> Option<ItemStack> {
+        self.slots[self.selected]
+    }
+
+    pub fn selected_block(&self) -> Option<BlockType> {
+        self.selected_stack().map(|stack| stack.block)
+    }
+
+    /// One block of the selected stack was placed, an emptied slot is cleared
+    pub fn take_selected(&mut self) -> Option<BlockType> {
+        let slot = &mut self.slots[self.selected];
+        let stack = slot.as_mut()?;
+        let block = stack.block;
+        stack.count -= 1;
+        if stack.count == 0 {
+            *slot = None;
+        }
+        Some(block)
+    }
+
+    /// Puts the block onto a stack of it with room, else into the first empty slot.
+    /// False if there is no place for it
+    pub fn add(&mut self, block: BlockType) -> bool {
+        insert_into_slots(&mut self.slots, ItemStack::new(block, 1)).is_none()
+    }
+}
+
+/// Number keys pick a slot, the mouse wheel goes through them
+pub fn update_hotbar_on_input(hotbar: &mut Hotbar) {
+    if let Some(slot) = SLOT_KEYS.iter().position(|&key| is_key_pressed(key)) {
+        hotbar.select(slot);
+    }
+    let (_, wheel) = mouse_wheel();
+    if wheel != 0.0 {
+        // wheel down goes right
+        hotbar.scroll(-wheel.signum() as i32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn starts_with_full_stacks() {
+        let hotbar = Hotbar::with_blocks([BlockType::GRASS, BlockType::STONE]);
+        assert_eq!(hotbar.selected(), 0);
+        assert_eq!(hotbar.selected_stack(), Some(ItemStack::full(BlockType::GRASS)));
+        assert_eq!(hotbar.slots()[1], Some(ItemStack::full(BlockType::STONE)));
+        assert!(hotbar.slots()[2..].iter().all(Option::is_none));
+
+        let too_many = Hotbar::with_blocks([BlockType::SAND; HOTBAR_SLOTS + 3]);
+        assert!(too_many.slots().iter().all(Option::is_some));
+    }
+
+    #[test]
+    fn selection_wraps_around() {
+        let mut hotbar = Hotbar::default();
+        hotbar.select(4);
+        assert_eq!(hotbar.selected(), 4);
+        hotbar.select(HOTBAR_SLOTS);
+        assert_eq!(hotbar.selected(), 4);
+        hotbar.scroll(5);
+        assert_eq!(hotbar.selected(), 0);
+        hotbar.scroll(-1);
+        assert_eq!(hotbar.selected(), HOTBAR_SLOTS - 1);
+        hotbar.scroll(-3 * HOTBAR_SLOTS as i32 - 2);
+        assert_eq!(hotbar.selected(), HOTBAR_SLOTS - 3);
+    }
+
+    #[test]
+    fn placing_takes_from_selected_stack() {
+        let mut hotbar = Hotbar::default();
+        assert!(hotbar.add(BlockType::SAND));
+        assert!(hotbar.add(BlockType::SAND));
+        assert_eq!(hotbar.selected_stack(), Some(ItemStack::new(BlockType::SAND, 2)));
+        assert_eq!(hotbar.take_selected(), Some(BlockType::SAND));
+        assert_eq!(hotbar.take_selected(), Some(BlockType::SAND));
+        // the emptied slot has nothing to place
+        assert_eq!(hotbar.selected_block(), None);
+        assert_eq!(hotbar.take_selected(), None);
+    }
+
+    #[test]
+    fn added_blocks_fill_stacks_then_empty_slots() {
+        let mut hotbar = Hotbar::with_blocks([BlockType::STONE]);
+        // the full stone stack has no room
+        assert!(hotbar.add(BlockType::STONE));
+        assert_eq!(hotbar.slots()[1], Some(ItemStack::new(BlockType::STONE, 1)));
+        assert!(hotbar.add(BlockType::DIRT));
+        assert_eq!(hotbar.slots()[2], Some(ItemStack::new(BlockType::DIRT, 1)));
+
+        let mut full = Hotbar::with_blocks([BlockType::SAND; HOTBAR_SLOTS]);
+        assert!(!full.add(BlockType::SAND));
+        assert!(!full.add(BlockType::DIRT));
+        full.take_selected();
+        assert!(full.add(BlockType::SAND));
+        assert_eq!(full.selected_stack(), Some(ItemStack::full(BlockType::SAND)));
+    }
+}
@@ -0,0 +1,227 @@
+use macroquad::prelude::*;
+
+use crate::crafting::RecipeBook;
+use crate::hotbar::{Hotbar, HOTBAR_SLOTS};
+use crate::inventory::{Inventory, InventoryScreen, ScreenSlot, INVENTORY_SLOTS};
+use crate::item::ItemStack;
+use crate::world::raycast::RaycastHit;
+use crate::world::render::atlas::TextureAtlas;
+use crate::world::render::BlockSide;
+use crate::world::BlockPos;
+
+/// Hotbar slot size in pixels
+const SLOT_SIZE: f32 = 48.0;
+/// Between the slot border and the block icon
+const ICON_INSET: f32 = 6.0;
+const HOTBAR_MARGIN: f32 = 12.0;
+const SLOT_COLOR: Color = Color { r: 0.0, g: 0.0, b: 0.0, a: 0.45 };
+const SLOT_BORDER_COLOR: Color = Color { r: 0.25, g: 0.25, b: 0.25, a: 0.9 };
+/// Gap between the slot groups of the inventory screen
+const SCREEN_GAP: f32 = 24.0;
+const SCREEN_BACKGROUND: Color = Color { r: 0.0, g: 0.0, b: 0.0, a: 0.5 };
+/// Half the crosshair line length, pixels
+const CROSSHAIR_SIZE: f32 = 10.0;
+const CROSSHAIR_THICKNESS: f32 = 2.0;
+/// The target outline stands this far off the block, so the faces don't hide it
+const OUTLINE_MARGIN: f32 = 0.005;
+const OUTLINE_COLOR: Color = Color { r: 0.0, g: 0.0, b: 0.0, a: 0.8 };
+const TARGET_FACE_COLOR: Color = Color { r: 1.0, g: 1.0, b: 1.0, a: 0.25 };
+
+/// Slots at the bottom of the screen with the block icons from the atlas and their counts
+pub fn draw_hotbar(hotbar: &Hotbar, atlas: &Texture2D, texture_atlas: &TextureAtlas) {
+    let left = (screen_width() - SLOT_SIZE * HOTBAR_SLOTS as f32) / 2.0;
+    let top = screen_height() - SLOT_SIZE - HOTBAR_MARGIN;
+    for (idx, slot) in hotbar.slots().iter().enumerate() {
+        let x = left + SLOT_SIZE * idx as f32;
+        draw_slot(Rect::new(x, top, SLOT_SIZE, SLOT_SIZE), slot, atlas, texture_atlas);
+    }
+    let selected = left + SLOT_SIZE * hotbar.selected() as f32;
+    draw_rectangle_lines(selected - 2.0, top - 2.0, SLOT_SIZE + 4.0, SLOT_SIZE + 4.0, 4.0, WHITE);
+}
+
+/// Cross in the screen center, where the camera looks
+pub fn draw_crosshair() {
+    let (x, y) = (screen_width() / 2.0, screen_height() / 2.0);
+    draw_line(x - CROSSHAIR_SIZE, y, x + CROSSHAIR_SIZE, y, CROSSHAIR_THICKNESS, WHITE);
+    draw_line(x, y - CROSSHAIR_SIZE, x, y + CROSSHAIR_SIZE, CROSSHAIR_THICKNESS, WHITE);
+}
+
+/// The 12 edges of the block cube, grown by `OUTLINE_MARGIN`
+pub fn block_outline_edges(pos: BlockPos) -> [(Vec3, Vec3); 12] {
+    let min = vec3(pos.x as f32, pos.y as f32, pos.z as f32) - Vec3::splat(OUTLINE_MARGIN);
+    let max = min + Vec3::splat(1.0 + 2.0 * OUTLINE_MARGIN);
+    std::array::from_fn(|idx| {
+        // 4 edges along each axis, at the min or max of the two other axes
+        let (axis, corner) = (idx / 4, idx % 4);
+        let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+        let mut start = min;
+        start[a] = if corner & 1 == 0 { min[a] } else { max[a] };
+        start[b] = if corner & 2 == 0 { min[b] } else { max[b] };
+        let mut end = start;
+        end[axis] = max[axis];
+        (start, end)
+    })
+}
+
+/// Corners of the `side` face of the block, just in front of it
+pub fn target_face_corners(pos: BlockPos, side: BlockSide) -> [Vec3; 4] {
+    let geometry = side.geometry();
+    let origin = vec3(pos.x as f32, pos.y as f32, pos.z as f32) + Vec3::from(geometry.normal) * OUTLINE_MARGIN;
+    geometry.corners.map(|corner| origin + Vec3::from(corner))
+}
+
+/// Wireframe around the targeted block, drawn with the 3d camera after the chunks.
+/// `highlight_face` tints the face a placed block goes against
+pub fn draw_target_outline(hit: &RaycastHit, highlight_face: bool) {
+    for (start, end) in block_outline_edges(hit.block) {
+        draw_line_3d(start, end, OUTLINE_COLOR);
+    }
+    if highlight_face {
+        let corners = target_face_corners(hit.block, hit.side);
+        let vertices = corners.map(|corner| Vertex::new(corner.x, corner.y, corner.z, 0.0, 0.0, TARGET_FACE_COLOR)).to_vec();
+        draw_mesh(&Mesh { vertices, indices: vec![0, 1, 2, 0, 2, 3], texture: None });
+    }
+}
+
+/// Screen rects of the inventory screen slots, centered: the crafting grid with its result on top,
+/// the storage rows below it and the hotbar row at the bottom
+pub fn inventory_screen_layout(grid_size: usize) -> Vec<(ScreenSlot, Rect)> {
+    let columns = HOTBAR_SLOTS as f32;
+    let storage_rows = ((INVENTORY_SLOTS - HOTBAR_SLOTS) / HOTBAR_SLOTS) as f32;
+    let height = SLOT_SIZE * (grid_size as f32 + storage_rows + 1.0) + SCREEN_GAP * 2.0;
+    let left = (screen_width() - SLOT_SIZE * columns) / 2.0;
+    let top = (screen_height() - height) / 2.0;
+    let rect = |x: f32, y: f32| Rect::new(x, y, SLOT_SIZE, SLOT_SIZE);
+
+    let mut layout = Vec::new();
+    // the grid with the result two slots to its right
+    let grid_left = left + SLOT_SIZE * 2.0;
+    for idx in 0..grid_size * grid_size {
+        let (row, col) = ((idx / grid_size) as f32, (idx % grid_size) as f32);
+        layout.push((ScreenSlot::Grid(idx), rect(grid_left + SLOT_SIZE * col, top + SLOT_SIZE * row)));
+    }
+    let result_x = grid_left + SLOT_SIZE * (grid_size as f32 + 1.0);
+    let result_y = top + SLOT_SIZE * (grid_size as f32 - 1.0) / 2.0;
+    layout.push((ScreenSlot::Result, rect(result_x, result_y)));
+
+    let storage_top = top + SLOT_SIZE * grid_size as f32 + SCREEN_GAP;
+    for idx in HOTBAR_SLOTS..INVENTORY_SLOTS {
+        let (row, col) = (((idx - HOTBAR_SLOTS) / HOTBAR_SLOTS) as f32, (idx % HOTBAR_SLOTS) as f32);
+        layout.push((ScreenSlot::Inventory(idx), rect(left + SLOT_SIZE * col, storage_top + SLOT_SIZE * row)));
+    }
+    let hotbar_top = storage_top + SLOT_SIZE * storage_rows + SCREEN_GAP;
+    for idx in 0..HOTBAR_SLOTS {
+        layout.push((ScreenSlot::Inventory(idx), rect(left + SLOT_SIZE * idx as f32, hotbar_top)));
+    }
+    layout
+}
+
+/// The slot of the inventory screen under `point`
+pub fn inventory_screen_slot_at(grid_size: usize, point: Vec2) -> Option<ScreenSlot> {
+    let layout = inventory_screen_layout(grid_size);
+    layout.into_iter().find(|(_, rect)| rect.contains(point)).map(|(slot, _)| slot)
+}
+
+/// Left and right clicks on the slots of the open screen
+pub fn update_inventory_screen_on_click(screen: &mut InventoryScreen, inventory: &mut Inventory, recipes: &RecipeBook) {
+    for (button, right) in [(MouseButton::Left, false), (MouseButton::Right, true)] {
+        if !is_mouse_button_pressed(button) {
+            continue;
+        }
+        if let Some(slot) = inventory_screen_slot_at(screen.grid.size(), mouse_position().into()) {
+            screen.click(slot, right, inventory, recipes);
+        }
+    }
+}
+
+/// Every inventory slot, the crafting grid with what it makes and the held stack at the mouse
+pub fn draw_inventory_screen(
+    screen: &InventoryScreen,
+    inventory: &Inventory,
+    recipes: &RecipeBook,
+    atlas: &Texture2D,
+    texture_atlas: &TextureAtlas,
+) {
+    draw_rectangle(0.0, 0.0, screen_width(), screen_height(), SCREEN_BACKGROUND);
+    let result = screen.grid.result(recipes);
+    for (slot, rect) in inventory_screen_layout(screen.grid.size()) {
+        let stack = match slot {
+            ScreenSlot::Inventory(idx) => inventory.slot(idx),
+            ScreenSlot::Grid(idx) => &screen.grid.slots()[idx],
+            ScreenSlot::Result => &result,
+        };
+        draw_slot(rect, stack, atlas, texture_atlas);
+        if slot == ScreenSlot::Result {
+            let arrow_y = rect.y + SLOT_SIZE / 2.0;
+            draw_line(rect.x - SLOT_SIZE + 8.0, arrow_y, rect.x - 8.0, arrow_y, 4.0, WHITE);
+        }
+    }
+    if let Some(held) = screen.cursor {
+        let (x, y) = mouse_position();
+        let rect = Rect::new(x - SLOT_SIZE / 2.0, y - SLOT_SIZE / 2.0, SLOT_SIZE, SLOT_SIZE);
+        draw_stack(rect, held, atlas, texture_atlas);
+    }
+}
+
+fn draw_slot(rect: Rect, slot: &Option<ItemStack>, atlas: &Texture2D, texture_atlas: &TextureAtlas) {
+    draw_rectangle(rect.x, rect.y, rect.w, rect.h, SLOT_COLOR);
+    draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 2.0, SLOT_BORDER_COLOR);
+    if let Some(stack) = slot {
+        draw_stack(rect, *stack, atlas, texture_atlas);
+    }
+}
+
+/// Block icon with the count when there is more than one
+fn draw_stack(rect: Rect, stack: ItemStack, atlas: &Texture2D, texture_atlas: &TextureAtlas) {
+    if let Some(icon) = stack.block.icon() {
+        let uv = texture_atlas.uv_rect(icon);
+        let (width, height) = (atlas.width(), atlas.height());
+        let source = Rect::new(uv.x * width, uv.y * height, uv.w * width, uv.h * height);
+        let size = rect.w - 2.0 * ICON_INSET;
+        draw_texture_ex(atlas, rect.x + ICON_INSET, rect.y + ICON_INSET, WHITE, DrawTextureParams {
+            dest_size: Some(vec2(size, size)),
+            source: Some(source),
+            ..Default::default()
+        });
+    }
+    if stack.count > 1 {
+        let text = stack.count.to_string();
+        let dimensions = measure_text(&text, None, 20, 1.0);
+        draw_text(&text, rect.x + rect.w - dimensions.width - 4.0, rect.y + rect.h - 5.0, 20.0, WHITE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outline_edges_frame_the_block() {
+        let pos = BlockPos::new(3, -2, 7);
+        let edges = block_outline_edges(pos);
+        let min = vec3(3.0, -2.0, 7.0) - Vec3::splat(OUTLINE_MARGIN);
+        let max = vec3(4.0, -1.0, 8.0) + Vec3::splat(OUTLINE_MARGIN);
+        for (idx, (start, end)) in edges.iter().enumerate() {
+            assert!((start.distance(*end) - (1.0 + 2.0 * OUTLINE_MARGIN)).abs() < 1e-5, "{:?}", (start, end));
+            // along one axis, on the box corners in the others
+            assert_eq!((*end - *start).cmpgt(Vec3::splat(0.5)).bitmask().count_ones(), 1);
+            for point in [start, end] {
+                let near = |a: f32, b: f32| (a - b).abs() < 1e-5;
+                let on_box = (0..3).filter(|&axis| near(point[axis], min[axis]) || near(point[axis], max[axis])).count();
+                assert_eq!(on_box, 3, "{:?}", point);
+            }
+            assert!(!edges[idx + 1..].contains(&(*start, *end)));
+        }
+    }
+
+    #[test]
+    fn target_face_is_just_in_front_of_the_side() {
+        let pos = BlockPos::new(0, 5, 0);
+        let top = target_face_corners(pos, BlockSide::Py);
+        assert!(top.iter().all(|corner| corner.y == 6.0 + OUTLINE_MARGIN));
+        let nx = target_face_corners(pos, BlockSide::Nx);
+        assert!(nx.iter().all(|corner| corner.x == -OUTLINE_MARGIN));
+        let center = nx.iter().sum::<Vec3>() / 4.0;
+        assert_eq!(center.yz(), vec2(5.5, 0.5));
+    }
+}
@@ -0,0 +1,229 @@
+use crate::crafting::{CraftingGrid, RecipeBook};
+use crate::hotbar::{Hotbar, HOTBAR_SLOTS};
+use crate::item::{click_slot, fill_empty_slots, right_click_slot, top_up_slots, ItemStack};
+use crate::world::BlockType;
+
+/// Slots of the player inventory, the hotbar ones included
+pub const INVENTORY_SLOTS: usize = 36;
+const STORAGE_SLOTS: usize = INVENTORY_SLOTS - HOTBAR_SLOTS;
+
+/// Everything the player carries: the hotbar and the slots only seen on the inventory screen
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Inventory {
+    pub hotbar: Hotbar,
+    storage: [Option<ItemStack>; STORAGE_SLOTS],
+}
+
+#[allow(dead_code)]
+impl Inventory {
+    pub fn new(hotbar: Hotbar) -> Self {
+        Self { hotbar, storage: Default::default() }
+    }
+
+    /// Slots `0..HOTBAR_SLOTS` are the hotbar, the storage ones follow
+    pub fn slot(&self, idx: usize) -> &Option<ItemStack> {
+        match idx.checked_sub(HOTBAR_SLOTS) {
+            None => &self.hotbar.slots()[idx],
+            Some(idx) => &self.storage[idx],
+        }
+    }
+
+    pub fn slot_mut(&mut self, idx: usize) -> &mut Option<ItemStack> {
+        match idx.checked_sub(HOTBAR_SLOTS) {
+            None => &mut self.hotbar.slots_mut()[idx],
+            Some(idx) => &mut self.storage[idx],
+        }
+    }
+
+    /// In slot order
+    pub fn slots(&self) -> impl Iterator<Item = &Option<ItemStack>> {
+        self.hotbar.slots().iter().chain(&self.storage)
+    }
+
+    /// Tops up stacks of the block anywhere, then takes empty slots, the hotbar ones first.
+    /// Returns what did not fit
+    pub fn add(&mut self, mut stack: ItemStack) -> Option<ItemStack> {
+        top_up_slots(self.hotbar.slots_mut(), &mut stack);
+        top_up_slots(&mut self.storage, &mut stack);
+        fill_empty_slots(self.hotbar.slots_mut(), &mut stack);
+        fill_empty_slots(&mut self.storage, &mut stack);
+        (stack.count > 0).then_some(stack)
+    }
+
+    /// Blocks of `block` over all slots
+    pub fn count(&self, block: BlockType) -> u32 {
+        self.slots().flatten().filter(|stack| stack.block == block).map(|stack| stack.count).sum()
+    }
+}
+
+/// A slot of the inventory screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenSlot {
+    /// Index as in `Inventory::slot`
+    Inventory(usize),
+    /// Index as in `CraftingGrid::slots`
+    Grid(usize),
+    /// What the grid crafts, it can only be taken
+    Result,
+}
+
+/// The open inventory screen: a crafting grid and the stack held by the mouse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InventoryScreen {
+    pub grid: CraftingGrid,
+    pub cursor: Option<ItemStack>,
+}
+
+#[allow(dead_code)]
+impl InventoryScreen {
+    /// 2x2 grid from the inventory, 3x3 at a crafting table
+    pub fn new(grid_size: usize) -> Self {
+        Self { grid: CraftingGrid::new(grid_size), cursor: None }
+    }
+
+    /// Moves stacks between the slot and the cursor, see `item::click_slot` and `item::right_click_slot`.
+    /// Clicking the result crafts once if the cursor can take it
+    pub fn click(&mut self, slot: ScreenSlot, right: bool, inventory: &mut Inventory, recipes: &RecipeBook) {
+        let slot = match slot {
+            ScreenSlot::Inventory(idx) => inventory.slot_mut(idx),
+            ScreenSlot::Grid(idx) => self.grid.slot_mut(idx),
+            ScreenSlot::Result => {
+                self.take_result(recipes);
+                return;
+            }
+        };
+        if right {
+            right_click_slot(slot, &mut self.cursor);
+        } else {
+            click_slot(slot, &mut self.cursor);
+        }
+    }
+
+    fn take_result(&mut self, recipes: &RecipeBook) {
+        let Some(mut result) = self.grid.result(recipes) else {
+            return;
+        };
+        match &mut self.cursor {
+            None => self.cursor = Some(result),
+            Some(held) if held.block == result.block && held.room() >= result.count => {
+                held.merge(&mut result);
+            }
+            Some(_) => return,
+        }
+        self.grid.craft(recipes);
+    }
+
+    /// Puts the grid and the held stack back into the inventory, returns what did not fit
+    pub fn close(mut self, inventory: &mut Inventory) -> Vec<ItemStack> {
+        let stacks = self.grid.take_all().into_iter().chain(self.cursor);
+        stacks.filter_map(|stack| inventory.add(stack)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::registry::BlockRegistry;
+
+    fn block(name: &str) -> BlockType {
+        BlockRegistry::global().by_name(name).unwrap()
+    }
+
+    #[test]
+    fn slots_run_through_hotbar_then_storage() {
+        let mut inventory = Inventory::new(Hotbar::with_blocks([BlockType::DIRT]));
+        assert_eq!(inventory.slots().count(), INVENTORY_SLOTS);
+        assert_eq!(*inventory.slot(0), Some(ItemStack::full(BlockType::DIRT)));
+        *inventory.slot_mut(HOTBAR_SLOTS) = Some(ItemStack::new(BlockType::SAND, 3));
+        *inventory.slot_mut(INVENTORY_SLOTS - 1) = Some(ItemStack::new(BlockType::SAND, 2));
+        assert_eq!(inventory.slots().flatten().count(), 3);
+        assert_eq!(inventory.count(BlockType::SAND), 5);
+        assert!(inventory.hotbar.slots()[1..].iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn added_stacks_top_up_before_taking_empty_slots() {
+        let mut inventory = Inventory::default();
+        *inventory.slot_mut(20) = Some(ItemStack::new(BlockType::STONE, 60));
+        assert_eq!(inventory.add(ItemStack::new(BlockType::STONE, 10)), None);
+        assert_eq!(*inventory.slot(20), Some(ItemStack::full(BlockType::STONE)));
+        // the rest goes to the first empty hotbar slot, not next to the storage stack
+        assert_eq!(*inventory.slot(0), Some(ItemStack::new(BlockType::STONE, 6)));
+
+        assert_eq!(inventory.add(ItemStack::new(BlockType::DIRT, 1)), None);
+        assert_eq!(*inventory.slot(1), Some(ItemStack::new(BlockType::DIRT, 1)));
+        assert_eq!(inventory.hotbar.selected_block(), Some(BlockType::STONE));
+    }
+
+    #[test]
+    fn full_inventory_returns_the_rest() {
+        let mut inventory = Inventory::default();
+        for idx in 0..INVENTORY_SLOTS - 1 {
+            *inventory.slot_mut(idx) = Some(ItemStack::full(BlockType::DIRT));
+        }
+        let left = inventory.add(ItemStack::new(BlockType::SAND, 100));
+        assert_eq!(left, Some(ItemStack::new(BlockType::SAND, 100 - ItemStack::MAX_SIZE)));
+        assert_eq!(*inventory.slot(INVENTORY_SLOTS - 1), Some(ItemStack::full(BlockType::SAND)));
+        assert_eq!(inventory.add(ItemStack::new(BlockType::DIRT, 1)), Some(ItemStack::new(BlockType::DIRT, 1)));
+        assert_eq!(inventory.count(BlockType::DIRT), (INVENTORY_SLOTS as u32 - 1) * ItemStack::MAX_SIZE);
+    }
+
+    #[test]
+    fn screen_moves_stacks_into_the_grid_and_crafts() {
+        let recipes = RecipeBook::builtin(BlockRegistry::global()).unwrap();
+        let mut inventory = Inventory::default();
+        inventory.add(ItemStack::new(block("log"), 2));
+        let mut screen = InventoryScreen::new(CraftingGrid::INVENTORY_SIZE);
+
+        screen.click(ScreenSlot::Inventory(0), false, &mut inventory, &recipes);
+        assert_eq!(screen.cursor, Some(ItemStack::new(block("log"), 2)));
+        assert_eq!(*inventory.slot(0), None);
+        // one log into the grid, the other back
+        screen.click(ScreenSlot::Grid(3), true, &mut inventory, &recipes);
+        screen.click(ScreenSlot::Inventory(5), false, &mut inventory, &recipes);
+        assert_eq!(screen.cursor, None);
+
+        let planks = ItemStack::new(block("planks"), 4);
+        screen.click(ScreenSlot::Result, false, &mut inventory, &recipes);
+        assert_eq!(screen.cursor, Some(planks));
+        assert!(screen.grid.slots().iter().all(Option::is_none));
+        // nothing left to craft
+        screen.click(ScreenSlot::Result, false, &mut inventory, &recipes);
+        assert_eq!(screen.cursor, Some(planks));
+    }
+
+    #[test]
+    fn result_is_only_taken_when_the_cursor_has_room() {
+        let recipes = RecipeBook::builtin(BlockRegistry::global()).unwrap();
+        let mut inventory = Inventory::default();
+        let mut screen = InventoryScreen::new(3);
+        *screen.grid.slot_mut(4) = Some(ItemStack::new(block("log"), 3));
+
+        screen.cursor = Some(ItemStack::new(block("planks"), 58));
+        screen.click(ScreenSlot::Result, false, &mut inventory, &recipes);
+        assert_eq!(screen.cursor, Some(ItemStack::new(block("planks"), 62)));
+        // 4 more don't fit onto 62
+        screen.click(ScreenSlot::Result, false, &mut inventory, &recipes);
+        assert_eq!(screen.cursor, Some(ItemStack::new(block("planks"), 62)));
+        assert_eq!(screen.grid.slots()[4], Some(ItemStack::new(block("log"), 2)));
+
+        screen.cursor = Some(ItemStack::new(BlockType::DIRT, 1));
+        screen.click(ScreenSlot::Result, false, &mut inventory, &recipes);
+        assert_eq!(screen.cursor, Some(ItemStack::new(BlockType::DIRT, 1)));
+    }
+
+    #[test]
+    fn closing_returns_everything_to_the_inventory() {
+        let mut inventory = Inventory::default();
+        for idx in 1..INVENTORY_SLOTS {
+            *inventory.slot_mut(idx) = Some(ItemStack::full(BlockType::STONE));
+        }
+        let mut screen = InventoryScreen::new(2);
+        *screen.grid.slot_mut(0) = Some(ItemStack::new(BlockType::SAND, 5));
+        *screen.grid.slot_mut(2) = Some(ItemStack::new(BlockType::DIRT, 2));
+        screen.cursor = Some(ItemStack::new(BlockType::SAND, 1));
+        let left = screen.close(&mut inventory);
+        assert_eq!(*inventory.slot(0), Some(ItemStack::new(BlockType::SAND, 6)));
+        assert_eq!(left, vec![ItemStack::new(BlockType::DIRT, 2)]);
+    }
+}
@@ -0,0 +1,214 @@
+use crate::world::BlockType;
+
+/// Blocks of one type held in one slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItemStack {
+    pub block: BlockType,
+    pub count: u32,
+}
+
+#[allow(dead_code)]
+impl ItemStack {
+    /// Most blocks one slot holds
+    pub const MAX_SIZE: u32 = 64;
+
+    pub const fn new(block: BlockType, count: u32) -> Self {
+        Self { block, count }
+    }
+
+    pub const fn full(block: BlockType) -> Self {
+        Self::new(block, Self::MAX_SIZE)
+    }
+
+    /// How many more blocks fit
+    pub const fn room(&self) -> u32 {
+        Self::MAX_SIZE.saturating_sub(self.count)
+    }
+
+    /// Moves as many blocks of `other` onto this stack as there is room for, `other` keeps the rest.
+    /// Stacks of different blocks don't mix. Returns the moved count
+    pub fn merge(&mut self, other: &mut ItemStack) -> u32 {
+        if self.block != other.block {
+            return 0;
+        }
+        let moved = self.room().min(other.count);
+        self.count += moved;
+        other.count -= moved;
+        moved
+    }
+
+    /// Takes `count` blocks off into a new stack, at most the whole stack
+    pub fn split(&mut self, count: u32) -> ItemStack {
+        let count = count.min(self.count);
+        self.count -= count;
+        ItemStack::new(self.block, count)
+    }
+}
+
+/// Moves `stack` onto the stacks of its block in `slots` with room, in slot order
+pub fn top_up_slots(slots: &mut [Option<ItemStack>], stack: &mut ItemStack) {
+    for slot in slots.iter_mut().flatten() {
+        if stack.count == 0 {
+            return;
+        }
+        slot.merge(stack);
+    }
+}
+
+/// Moves `stack` into the empty `slots`, full stacks at most
+pub fn fill_empty_slots(slots: &mut [Option<ItemStack>], stack: &mut ItemStack) {
+    for slot in slots.iter_mut().filter(|slot| slot.is_none()) {
+        if stack.count == 0 {
+            return;
+        }
+        *slot = Some(stack.split(ItemStack::MAX_SIZE));
+    }
+}
+
+/// Tops up stacks of the same block first, then takes empty slots.
+/// Returns what did not fit
+pub fn insert_into_slots(slots: &mut [Option<ItemStack>], mut stack: ItemStack) -> Option<ItemStack> {
+    top_up_slots(slots, &mut stack);
+    fill_empty_slots(slots, &mut stack);
+    (stack.count > 0).then_some(stack)
+}
+
+/// Left click on a slot while holding `cursor`: picks the stack up, puts the held one down,
+/// adds the held blocks onto a stack of the same block, or swaps the two
+pub fn click_slot(slot: &mut Option<ItemStack>, cursor: &mut Option<ItemStack>) {
+    match (slot.as_mut(), cursor.as_mut()) {
+        (Some(stack), Some(held)) if stack.block == held.block => {
+            stack.merge(held);
+            if held.count == 0 {
+                *cursor = None;
+            }
+        }
+        _ => std::mem::swap(slot, cursor),
+    }
+}
+
+/// Right click on a slot while holding `cursor`: picks up half the stack, rounded up,
+/// or puts one held block down
+pub fn right_click_slot(slot: &mut Option<ItemStack>, cursor: &mut Option<ItemStack>) {
+    let Some(held) = cursor.as_mut() else {
+        if let Some(stack) = slot.as_mut() {
+            let half = stack.split(stack.count.div_ceil(2));
+            *cursor = Some(half);
+            if stack.count == 0 {
+                *slot = None;
+            }
+        }
+        return;
+    };
+    match slot {
+        None => *slot = Some(held.split(1)),
+        Some(stack) if stack.block == held.block && stack.room() > 0 => {
+            stack.count += 1;
+            held.count -= 1;
+        }
+        Some(_) => return,
+    }
+    if held.count == 0 {
+        *cursor = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_fills_up_to_max_size() {
+        let mut stack = ItemStack::new(BlockType::STONE, 60);
+        let mut other = ItemStack::new(BlockType::STONE, 10);
+        assert_eq!(stack.merge(&mut other), 4);
+        assert_eq!(stack, ItemStack::full(BlockType::STONE));
+        assert_eq!(other.count, 6);
+
+        let mut dirt = ItemStack::new(BlockType::DIRT, 1);
+        assert_eq!(dirt.merge(&mut other), 0);
+        assert_eq!((dirt.count, other.count), (1, 6));
+    }
+
+    #[test]
+    fn split_takes_at_most_the_stack() {
+        let mut stack = ItemStack::new(BlockType::SAND, 5);
+        assert_eq!(stack.split(2), ItemStack::new(BlockType::SAND, 2));
+        assert_eq!(stack.split(10), ItemStack::new(BlockType::SAND, 3));
+        assert_eq!(stack.count, 0);
+    }
+
+    #[test]
+    fn insert_tops_up_before_empty_slots() {
+        let mut slots = [None, Some(ItemStack::new(BlockType::DIRT, 60)), Some(ItemStack::new(BlockType::SAND, 1))];
+        assert_eq!(insert_into_slots(&mut slots, ItemStack::new(BlockType::DIRT, 10)), None);
+        assert_eq!(slots[1], Some(ItemStack::full(BlockType::DIRT)));
+        assert_eq!(slots[0], Some(ItemStack::new(BlockType::DIRT, 6)));
+
+        let left = insert_into_slots(&mut slots, ItemStack::new(BlockType::SAND, 100));
+        assert_eq!(slots[2], Some(ItemStack::full(BlockType::SAND)));
+        assert_eq!(left, Some(ItemStack::new(BlockType::SAND, 37)));
+    }
+
+    #[test]
+    fn insert_splits_over_max_size() {
+        let mut slots = [None; 3];
+        assert_eq!(insert_into_slots(&mut slots, ItemStack::new(BlockType::STONE, 100)), None);
+        assert_eq!(slots, [Some(ItemStack::full(BlockType::STONE)), Some(ItemStack::new(BlockType::STONE, 36)), None]);
+    }
+
+    #[test]
+    fn left_click_picks_puts_merges_and_swaps() {
+        let stone = ItemStack::new(BlockType::STONE, 10);
+        let mut slot = Some(stone);
+        let mut cursor = None;
+        click_slot(&mut slot, &mut cursor);
+        assert_eq!((slot, cursor), (None, Some(stone)));
+        click_slot(&mut slot, &mut cursor);
+        assert_eq!((slot, cursor), (Some(stone), None));
+
+        let mut cursor = Some(ItemStack::new(BlockType::STONE, 60));
+        click_slot(&mut slot, &mut cursor);
+        assert_eq!(slot, Some(ItemStack::full(BlockType::STONE)));
+        assert_eq!(cursor, Some(ItemStack::new(BlockType::STONE, 6)));
+
+        let dirt = ItemStack::new(BlockType::DIRT, 2);
+        let mut cursor = Some(dirt);
+        click_slot(&mut slot, &mut cursor);
+        assert_eq!((slot, cursor), (Some(dirt), Some(ItemStack::full(BlockType::STONE))));
+
+        let mut cursor = Some(ItemStack::new(BlockType::DIRT, 3));
+        click_slot(&mut slot, &mut cursor);
+        assert_eq!((slot, cursor), (Some(ItemStack::new(BlockType::DIRT, 5)), None));
+    }
+
+    #[test]
+    fn right_click_halves_and_puts_one() {
+        let mut slot = Some(ItemStack::new(BlockType::SAND, 5));
+        let mut cursor = None;
+        right_click_slot(&mut slot, &mut cursor);
+        assert_eq!(slot, Some(ItemStack::new(BlockType::SAND, 2)));
+        assert_eq!(cursor, Some(ItemStack::new(BlockType::SAND, 3)));
+
+        right_click_slot(&mut slot, &mut cursor);
+        assert_eq!(slot, Some(ItemStack::new(BlockType::SAND, 3)));
+        let mut empty = None;
+        right_click_slot(&mut empty, &mut cursor);
+        assert_eq!(empty, Some(ItemStack::new(BlockType::SAND, 1)));
+        right_click_slot(&mut empty, &mut cursor);
+        assert_eq!(empty, Some(ItemStack::new(BlockType::SAND, 2)));
+        assert_eq!(cursor, None);
+
+        // a different block stays where it is
+        let mut cursor = Some(ItemStack::new(BlockType::DIRT, 1));
+        right_click_slot(&mut slot, &mut cursor);
+        assert_eq!(slot, Some(ItemStack::new(BlockType::SAND, 3)));
+        assert_eq!(cursor, Some(ItemStack::new(BlockType::DIRT, 1)));
+
+        // a single block is picked up whole
+        let mut one = Some(ItemStack::new(BlockType::DIRT, 1));
+        let mut cursor = None;
+        right_click_slot(&mut one, &mut cursor);
+        assert_eq!((one, cursor), (None, Some(ItemStack::new(BlockType::DIRT, 1))));
+    }
+}
@@ -1,26 +1,100 @@
-#![feature(const_fn_floating_point_arithmetic)]
+#![allow(clippy::needless_range_loop)]
+#![cfg_attr(test, feature(test))]
+
+#[cfg(test)]
+extern crate test;
 
 use macroquad::prelude::*;
-use std::time::Instant;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+mod crafting;
+use crafting::{CraftingGrid, RecipeBook, MAX_GRID_SIZE};
+
+mod drops;
+use drops::{draw_dropped_items, DroppedItems};
 
 mod grab;
 use grab::*;
 
+mod hotbar;
+use hotbar::update_hotbar_on_input;
+
+mod hud;
+use hud::{draw_crosshair, draw_hotbar, draw_inventory_screen, draw_target_outline, update_inventory_screen_on_click};
+
+mod inventory;
+use inventory::{Inventory, InventoryScreen};
+
+mod item;
+use item::ItemStack;
+
+mod physics;
+use physics::Body;
+
 mod player;
 use player::*;
 
+mod timestep;
+use timestep::FixedTimestep;
+
 mod world;
-use crate::world::render::mesh::build_model_meshes;
-use crate::world::render::model::{build_chunk_model, update_chunk_model};
+use crate::world::edit::{edit_targeted_block, BlockAction};
+use crate::world::render::atlas::TextureAtlas;
+use crate::world::render::frustum::Frustum;
+use crate::world::render::material::{load_chunk_material, load_translucent_chunk_material};
+use crate::world::render::mesh::ChunkDrawMeshes;
+use crate::world::render::model::MissingNeighbour;
+use crate::world::render::FaceShading;
 use world::*;
-use crate::world::render::WorldPos;
-
+use crate::world::raycast::raycast;
+use crate::world::registry::BlockRegistry;
+use crate::world::streaming::ChunkStreamer;
+use crate::world::workers::{ChunkSource, ChunkWorkers};
+use crate::world::terrain::TerrainGenerator;
+pub use crate::world::render::mesh::{MESH_INDEX_LIMIT, MESH_VERTEX_LIMIT};
+
+const WORLD_SEED: u64 = 1;
+/// Block definitions, the builtin ones are used if the file is missing
+const BLOCKS_FILE: &str = "assets/blocks.ron";
+/// Block textures packed into the atlas at startup, the builtin ones are used if it is missing
+const TEXTURES_DIR: &str = "assets/textures";
+/// Crafting recipes, the builtin ones are used if the file is missing
+const RECIPES_FILE: &str = "assets/recipes.ron";
+/// Right clicking it opens the big crafting grid
+const CRAFTING_TABLE: &str = "crafting_table";
+/// Region files of changed chunks, relative to the working directory
+const SAVE_DIR: &str = "saves/world";
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(60);
+/// How far the player can reach blocks
+const REACH_DISTANCE: f32 = 6.0;
+/// Chunks loaded around the player, across
+const VIEW_DISTANCE: i32 = 12;
+/// Chunk jobs in flight and finished chunks uploaded per frame at most
+const CHUNK_JOBS_PER_FRAME: usize = 16;
+/// Brightness of the block sides by the way they face
+const FACE_SHADING: FaceShading = FaceShading::DEFAULT;
+/// Tints the face of the targeted block a placed block goes against
+const HIGHLIGHT_TARGET_FACE: bool = true;
+/// Starts with a stack of every block instead of an empty inventory, for trying blocks out
+const CREATIVE_INVENTORY: bool = false;
+/// How chunk borders facing unloaded chunks are meshed
+const MISSING_NEIGHBOUR: MissingNeighbour = MissingNeighbour::Air;
+/// Movement and physics ticks, independent from the frame rate
+const TICKS_PER_SECOND: f32 = 60.0;
 const SKY_COLOR: Color = Color { r: 0.3, g: 0.3, b: 0.5, a: 1.0 };
 
 pub async fn run_client() {
-    let atlas_data = include_bytes!("../assets/atlas.png");
-    let atlas: Texture2D = Texture2D::from_file_with_format(atlas_data, Some(ImageFormat::Png));
+    let texture_atlas = load_texture_atlas(Path::new(TEXTURES_DIR));
+    install_block_registry(Path::new(BLOCKS_FILE), &texture_atlas);
+    let recipes = load_recipes(Path::new(RECIPES_FILE));
+    let crafting_table = BlockRegistry::global().by_name(CRAFTING_TABLE);
+
+    let atlas = Texture2D::from_image(&texture_atlas.image);
     atlas.set_filter(FilterMode::Nearest);
+    let chunk_material = load_chunk_material(&texture_atlas).unwrap();
+    let translucent_chunk_material = load_translucent_chunk_material(&texture_atlas).unwrap();
 
     let mut yaw = Yaw::default();
     let mut pitch = Pitch::default();
@@ -35,142 +109,30 @@ pub async fn run_client() {
     let mut current_mouse_pos: CurrentMousePos = mouse_position().into();
 
     let mut grabbed = Grabbed::default();
-
-    let mut chunks = vec![];
-    let material = vec![BlockState::GRASS, BlockState::STONE, BlockState::SAND, BlockState::DIRT];
-
-
-    let chunk_count = 144;
-    // for i in 0..chunk_count {
-    //     let mut chunk = Chunk::EMPTY;
-    //     let mut dec = 0;
-    //     for y in 0..CHUNK_SIZE_16 {
-    //         for x in 0..CHUNK_SIZE_16 {
-    //             for z in 0..CHUNK_SIZE_16 {
-    //                 let r = dec..CHUNK_SIZE_16 - dec;
-    //                 *chunk.get_mut(x, y, z) = if !r.contains(&x) || !r.contains(&z) {
-    //                     BlockState::EMPTY
-    //                 } else {
-    //                     material[i % 4].clone()
-    //                 };
-    //             }
-    //         }
-    //         if y % 2 != 0 { dec += 1 };
-    //     }
-    //     chunks.push(chunk);
-    // }
-    //
-    //
-    //
-    // let size = CHUNK_SIZE_16 as f32;
-    // let mut y = 0.0 - size;
-    // let mut x = 0.0;
-    // let mut z = 0.0;
-    // let mut i = 0;
-    // if chunk_count >= 36 {
-    //     while i < 36 {
-    //         chunks[i].set_pos(x, y, z);
-    //         chunks[i + 1].set_pos(x, y, 0.0 - size);
-    //         chunks[i + 2].set_pos(x, y, size);
-    //
-    //         chunks[i + 3].set_pos(size, y, z);
-    //         chunks[i + 4].set_pos(0.0 - size, y, z);
-    //         chunks[i + 5].set_pos(size, y, size);
-    //
-    //         chunks[i + 6].set_pos(size, y, 0.0 - size);
-    //         chunks[i + 7].set_pos(0.0 - size, y, 0.0 - size);
-    //         chunks[i + 8].set_pos(0.0 - size, y, size);
-    //
-    //         i += 9;
-    //         y += size;
-    //     }
-    // }
-    //
-    //
-    // if chunk_count >= 72 {
-    //     x = size * 3.0;
-    //     y = 0.0 - size;
-    //     while i < 72 {
-    //         chunks[i].set_pos(x, y, z);
-    //         chunks[i + 1].set_pos(x, y, z + 0.0 - size);
-    //         chunks[i + 2].set_pos(x, y, z + size);
-    //
-    //         chunks[i + 3].set_pos(x + size, y, z);
-    //         chunks[i + 4].set_pos(x + 0.0 - size, y, z);
-    //         chunks[i + 5].set_pos(x + size, y, z + size);
-    //
-    //         chunks[i + 6].set_pos(x + size, y, 0.0 - size);
-    //         chunks[i + 7].set_pos(x + 0.0 - size, y, z + 0.0 - size);
-    //         chunks[i + 8].set_pos(x + 0.0 - size, y, z + size);
-    //
-    //         i += 9;
-    //         y += size;
-    //     }
-    // }
-    //
-    //
-    // if chunk_count >= 108 {
-    //     z = size * 3.0;
-    //     x = 0.0;
-    //     y = 0.0 - size;
-    //     while i < 108 {
-    //         chunks[i].set_pos(x, y, z);
-    //         chunks[i + 1].set_pos(x, y, z + 0.0 - size);
-    //         chunks[i + 2].set_pos(x, y, z + size);
-    //
-    //         chunks[i + 3].set_pos(x + size, y, z);
-    //         chunks[i + 4].set_pos(x + 0.0 - size, y, z);
-    //         chunks[i + 5].set_pos(x + size, y, z + size);
-    //
-    //         chunks[i + 6].set_pos(x + size, y, z + 0.0 - size);
-    //         chunks[i + 7].set_pos(x + 0.0 - size, y, z + 0.0 - size);
-    //         chunks[i + 8].set_pos(x + 0.0 - size, y, z + size);
-    //
-    //         i += 9;
-    //         y += size;
-    //     }
-    // }
-    //
-    //
-    // if chunk_count >= 144 {
-    //     x = size * 3.0;
-    //     y = 0.0 - size;
-    //     while i < 144 {
-    //         chunks[i].set_pos(x, y, z);
-    //         chunks[i + 1].set_pos(x, y, z + 0.0 - size);
-    //         chunks[i + 2].set_pos(x, y, z + size);
-    //
-    //         chunks[i + 3].set_pos(x + size, y, z);
-    //         chunks[i + 4].set_pos(x + 0.0 - size, y, z);
-    //         chunks[i + 5].set_pos(x + size, y, z + size);
-    //
-    //         chunks[i + 6].set_pos(x + size, y, z + 0.0 - size);
-    //         chunks[i + 7].set_pos(x + 0.0 - size, y, z + 0.0 - size);
-    //         chunks[i + 8].set_pos(x + 0.0 - size, y, z + size);
-    //
-    //         i += 9;
-    //         y += size;
-    //     }
-    // }
-
-    let mut chunk_meshes = vec![];
-    for x_ch in -20..20 {
-        for z_ch in -20..20 {
-            let mut chunk = Chunk::EMPTY;
-            for x in 0..CHUNK_SIZE_16 {
-                for z in 0..CHUNK_SIZE_16 {
-                    *chunk.get_mut(x, 1, z) = BlockState::TILE
-                }
-            }
-            build_chunk_model(&mut chunk);
-            chunk.set_pos(x_ch as f32  * CHUNK_SIZE_16 as f32, 0.0, z_ch as f32 * CHUNK_SIZE_16 as f32);
-            let mesh= build_model_meshes(&chunk.model, atlas.clone(), chunk.get_pos());
-            // println!("{}",mesh.len());
-            chunk_meshes.extend(mesh);
-            chunks.push(chunk);
-
+    let mut movement_mode = MovementMode::default();
+    let mut inventory = Inventory::default();
+    if CREATIVE_INVENTORY {
+        // every block with something to show, in id order, the ones past the hotbar in the storage
+        for (block, _) in BlockRegistry::global().iter().filter(|(block, _)| block.icon().is_some()) {
+            inventory.add(ItemStack::full(block));
         }
     }
+    let mut inventory_screen: Option<InventoryScreen> = None;
+    let mut drops = DroppedItems::new();
+
+    let generator = TerrainGenerator::new(WORLD_SEED);
+    let mut world = World::new();
+    let source = ChunkSource { generator, save_dir: Some(PathBuf::from(SAVE_DIR)), shading: FACE_SHADING };
+    let mut streamer = ChunkStreamer::new(ChunkWorkers::with_available_threads(source), VIEW_DISTANCE, CHUNK_JOBS_PER_FRAME);
+    let mut last_save = Instant::now();
+    let mut chunk_meshes = HashMap::new();
+    // the block the translucent faces were last sorted from
+    let mut sorted_from = None;
+
+    let mut body = Body::new(vec3(0.5, generator.height_at(0, 0) as f32 + 1.0, 0.5));
+    let mut previous_body = body;
+    let mut timestep = FixedTimestep::new(TICKS_PER_SECOND);
+    player_pos.0 = body.eye_pos();
 
     setup_mouse_cursor();
     let mut fps_mean = vec![];
@@ -186,11 +148,37 @@ pub async fn run_client() {
     //     build_chunk_model(&mut chunk);
     // }
 
-    let mut frame_scip = 0;
+    // let mut frame_scip = 0;
     loop {
-        if is_key_pressed(KeyCode::Escape) { break; }
+        if is_key_pressed(KeyCode::Escape) {
+            save_world(&mut world);
+            break;
+        }
+        if last_save.elapsed() >= AUTOSAVE_INTERVAL {
+            save_world(&mut world);
+            last_save = Instant::now();
+        }
 
-        update_grabbed_state_and_cursor_on_tab_press(&mut grabbed);
+        if is_key_pressed(KeyCode::E) {
+            match inventory_screen.take() {
+                Some(screen) => {
+                    for stack in screen.close(&mut inventory) {
+                        drops.spawn(body.eye_pos(), stack);
+                    }
+                    set_grabbed_state_and_cursor(&mut grabbed, true);
+                }
+                None => {
+                    inventory_screen = Some(InventoryScreen::new(CraftingGrid::INVENTORY_SIZE));
+                    set_grabbed_state_and_cursor(&mut grabbed, false);
+                }
+            }
+        }
+        if let Some(screen) = &mut inventory_screen {
+            update_inventory_screen_on_click(screen, &mut inventory, &recipes);
+        } else {
+            update_grabbed_state_and_cursor_on_tab_press(&mut grabbed);
+        }
+        update_movement_mode_on_f_press(&mut movement_mode);
 
         *current_mouse_pos = mouse_position().into();
 
@@ -202,8 +190,71 @@ pub async fn run_client() {
 
         if *grabbed {
             update_yaw_pitch_after_mouse_pos_changed(&current_mouse_pos, &last_mouse_pos, &mut yaw, &mut pitch);
-            update_player_pos_after_front_right_up_changed(&mut player_pos, front, right);
             update_front_right_up_vecs_after_yaw_pitch_changed(&yaw, &pitch, &mut front, &mut right, &mut up);
+
+            update_hotbar_on_input(&mut inventory.hotbar);
+            let target = raycast(&world, *player_pos, *front, REACH_DISTANCE);
+            let targeted = target.map(|hit| world.get_block(hit.block).block_type);
+            if is_mouse_button_pressed(MouseButton::Right) && crafting_table.is_some_and(|table| targeted == Some(table)) {
+                inventory_screen = Some(InventoryScreen::new(MAX_GRID_SIZE));
+                set_grabbed_state_and_cursor(&mut grabbed, false);
+            } else if let Some(action) = block_action_on_mouse_press(inventory.hotbar.selected_block()) {
+                // a block placed inside the player would trap it
+                let inside_player = movement_mode == MovementMode::Walk
+                    && matches!(action, BlockAction::Place(_))
+                    && target.is_some_and(|hit| body.aabb().overlaps_block(hit.adjacent));
+                let edited = if inside_player {
+                    None
+                } else {
+                    edit_targeted_block(&mut world, *player_pos, *front, REACH_DISTANCE, action, MISSING_NEIGHBOUR)
+                };
+                match (action, edited) {
+                    (BlockAction::Place(_), Some(_)) => {
+                        inventory.hotbar.take_selected();
+                    }
+                    (BlockAction::Break, Some(pos)) => {
+                        if let Some(dropped) = targeted.and_then(|block| block.drops()) {
+                            drops.spawn_from_block(pos, ItemStack::new(dropped, 1));
+                        }
+                    }
+                    (_, None) => {}
+                }
+            }
+        }
+
+        let update = streamer.update(&mut world, *player_pos, MISSING_NEIGHBOUR);
+        for pos in update.unloaded {
+            chunk_meshes.remove(&pos);
+        }
+        for (pos, mesh) in update.meshed {
+            let mut meshes = mesh.to_meshes(&atlas);
+            meshes.sort_translucent(*player_pos);
+            chunk_meshes.insert(pos, meshes);
+        }
+
+        let input = match (movement_mode, *grabbed) {
+            (_, false) => Default::default(),
+            (MovementMode::Walk, true) => walk_input(front, right),
+            (MovementMode::Fly, true) => fly_input(front, right),
+        };
+        for _ in 0..timestep.advance(get_frame_time()) {
+            previous_body = body;
+            body = match movement_mode {
+                // until its chunks arrive the body would fall through the air of the missing ones
+                MovementMode::Walk if !world.is_ground_loaded(BlockPos::from(body.pos)) => body,
+                MovementMode::Walk => physics::step(&world, body, &input, timestep.tick()),
+                MovementMode::Fly => physics::fly(body, &input, timestep.tick()),
+            };
+            drops.step(&world, timestep.tick());
+        }
+        drops.pick_up(&body.aabb(), &mut inventory);
+        player_pos.0 = previous_body.eye_pos().lerp(body.eye_pos(), timestep.alpha());
+        let camera_block = BlockPos::from(*player_pos);
+        if sorted_from != Some(camera_block) {
+            for meshes in chunk_meshes.values_mut() {
+                meshes.sort_translucent(*player_pos);
+            }
+            sorted_from = Some(camera_block);
         }
 
 
@@ -211,37 +262,52 @@ pub async fn run_client() {
 
         clear_background(SKY_COLOR);
 
-        set_camera(&Camera3D {
+        let camera = Camera3D {
             position: *player_pos,
             up: *up,
             target: *player_pos + *front,
             ..Default::default()
-        });
+        };
+        set_camera(&camera);
+        let frustum = Frustum::from_view_projection(camera.matrix());
 
         draw_grid(20, 1., BLACK, GRAY);
 
-        // let mut mesh_time = 0;
-        // let mut draw_time = 0;
         let now = Instant::now();
-        // for mut chunk in chunks.iter_mut() {
-        //     chunk.check_visibility(player_pos.0, front.0);
-        //     if chunk.is_visible {
-        //         update_chunk_model(&mut chunk, player_pos.0);
-        //         // let t2 = Instant::now();
-        //         let chunk_meshes = build_model_meshes(&chunk.model, atlas.clone(), chunk.get_pos());
-        //         // mesh_time += t2.elapsed().as_micros();
-        //         // println!("mesh_time {}", mesh_time);
-        //
-        //         // let t3 = Instant::now();
-        //         for chunk_mesh in &chunk_meshes {
-        //             draw_mesh(&chunk_mesh);
-        //         }
-        //         // draw_time += t3.elapsed().as_micros();
-        //     }
-        // }
 
-        for chunk_mesh in &chunk_meshes {
-            draw_mesh(&chunk_mesh);
+        gl_use_material(&chunk_material);
+        let mut culled_chunks = 0;
+        let mut translucent_chunks = vec![];
+        for (&pos, meshes) in &chunk_meshes {
+            if !frustum.intersects_chunk(pos) {
+                culled_chunks += 1;
+                continue;
+            }
+            for chunk_mesh in meshes.opaque_facing(pos, *player_pos) {
+                draw_mesh(chunk_mesh);
+            }
+            if !meshes.translucent.is_empty() {
+                translucent_chunks.push((pos, meshes));
+            }
+        }
+        draw_dropped_items(&drops, &world, &atlas, &FACE_SHADING);
+        // back to front by chunk, the faces inside one chunk are sorted as the camera moves
+        translucent_chunks.sort_by(|(a, _), (b, _)| {
+            let distance = |pos: &ChunkPos| pos.center().distance_squared(*player_pos);
+            distance(b).total_cmp(&distance(a))
+        });
+        gl_use_material(&translucent_chunk_material);
+        for (_, meshes) in translucent_chunks {
+            for chunk_mesh in &meshes.translucent {
+                draw_mesh(chunk_mesh);
+            }
+        }
+        gl_use_default_material();
+
+        let target = raycast(&world, *player_pos, *front, REACH_DISTANCE);
+        let screen_open = inventory_screen.is_some();
+        if let Some(hit) = target.filter(|_| !screen_open) {
+            draw_target_outline(&hit, HIGHLIGHT_TARGET_FACE && inventory.hotbar.selected_block().is_some());
         }
 
         let math = now.elapsed().as_micros() as usize;
@@ -253,12 +319,35 @@ pub async fn run_client() {
         // let mean_math = push_to_mean(&mut math_mean, math);
         let info_str = format!("X: {:.2} Y: {:.2} Z: {:.2}", player_pos.x, player_pos.y, player_pos.z);
         let fps_frame_str = format!("FPS: {} Math: {} mcs", mean_fps, math);
+        let (mesh_count, vertex_count, index_count) = chunk_meshes
+            .values()
+            .flat_map(ChunkDrawMeshes::iter)
+            .fold((0, 0, 0), |(meshes, vert, ind), mesh| {
+                (meshes + 1, vert + mesh.vertices.len(), ind + mesh.indices.len())
+            });
+        let mesh_str = format!(
+            "Chunks: {} (+{}) Culled: {} Meshes: {} Quads: {} Vert: {} Ind: {}",
+            world.chunk_count(), streamer.pending_count(), culled_chunks, mesh_count, vertex_count / 4, vertex_count, index_count
+        );
+        let target_str = match target {
+            Some(hit) => format!("Target: {} {} {} {:?}", hit.block.x, hit.block.y, hit.block.z, hit.side),
+            None => String::from("Target: -"),
+        };
         // let fps_str = format!("FPS: {}", mean_fps);
         // let time_str = format!("mesh {} draw {}", mesh_time, draw_time);
         render_text_overlay(info_str.as_str(), 1);
         // render_text_overlay(fps_str.as_str(), 2);
         render_text_overlay(fps_frame_str.as_str(), 2);
+        render_text_overlay(mesh_str.as_str(), 3);
+        render_text_overlay(target_str.as_str(), 4);
         // render_text_overlay(time_str.as_str(), 3);
+        if !screen_open {
+            draw_crosshair();
+        }
+        draw_hotbar(&inventory.hotbar, &atlas, &texture_atlas);
+        if let Some(screen) = &inventory_screen {
+            draw_inventory_screen(screen, &inventory, &recipes, &atlas, &texture_atlas);
+        }
         last_mouse_pos.0 = mouse_position().into();
 
         // if frame_scip % 50000 == 0 {
@@ -272,10 +361,47 @@ pub async fn run_client() {
     }
 }
 
+/// Panics on broken textures, running with other textures than the modder made helps no one
+fn load_texture_atlas(dir: &Path) -> TextureAtlas {
+    match TextureAtlas::load_dir(dir) {
+        Ok(atlas) => atlas,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => TextureAtlas::builtin(),
+        Err(err) => panic!("{}: {}", dir.display(), err),
+    }
+}
+
+/// Panics on a broken file, like `load_texture_atlas`
+fn install_block_registry(path: &Path, atlas: &TextureAtlas) {
+    let registry = match BlockRegistry::load(path, atlas) {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => BlockRegistry::builtin(atlas),
+        loaded => loaded,
+    };
+    let registry = registry.unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+    if registry.install().is_err() {
+        eprintln!("block registry was installed already");
+    }
+}
+
+/// Panics on a broken file, like `load_texture_atlas`
+fn load_recipes(path: &Path) -> RecipeBook {
+    let registry = BlockRegistry::global();
+    let recipes = match RecipeBook::load(path, registry) {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => RecipeBook::builtin(registry),
+        loaded => loaded,
+    };
+    recipes.unwrap_or_else(|err| panic!("{}: {}", path.display(), err))
+}
+
+fn save_world(world: &mut World) {
+    if let Err(err) = world.save(Path::new(SAVE_DIR)) {
+        eprintln!("Failed to save the world to {}: {}", SAVE_DIR, err);
+    }
+}
+
 #[allow(dead_code)]
-fn print_n_meshes(chunk_meshes: &Vec<Mesh>) {
+fn print_n_meshes(chunk_meshes: &[Mesh]) {
     let y = 40.0 + 40.0 * 2.0;
-    for (n, mesh) in chunk_meshes.into_iter().enumerate() {
+    for (n, mesh) in chunk_meshes.iter().enumerate() {
         draw_text(
             format!("Mesh#{}, IND ({})", n, mesh.indices.len()).as_str(),
             10.0,
@@ -1,12 +1,18 @@
 use macroquad::prelude::*;
 
-fn conf() -> Conf {
-    Conf {
-        window_title: String::from("CubicGame"),
-        platform: miniquad::conf::Platform {
-            // swap_interval: Some(0),
+fn conf() -> macroquad::conf::Conf {
+    macroquad::conf::Conf {
+        miniquad_conf: Conf {
+            window_title: String::from("CubicGame"),
+            platform: miniquad::conf::Platform {
+                // swap_interval: Some(0),
+                ..Default::default()
+            },
             ..Default::default()
         },
+        // a whole batched chunk mesh fits into one draw call
+        draw_call_vertex_capacity: cubic_game::MESH_VERTEX_LIMIT,
+        draw_call_index_capacity: cubic_game::MESH_INDEX_LIMIT,
         ..Default::default()
     }
 }
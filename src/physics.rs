@@ -0,0 +1,258 @@
+use macroquad::math::{vec3, Vec3};
+
+use crate::world::{BlockPos, BlockSource};
+
+/// Player bounding box, `Body::pos` is the middle of its bottom face
+pub const PLAYER_WIDTH: f32 = 0.6;
+pub const PLAYER_HEIGHT: f32 = 1.8;
+/// Camera height above the feet
+pub const EYE_HEIGHT: f32 = 1.62;
+
+/// Blocks per second
+const WALK_SPEED: f32 = 4.3;
+const FLY_SPEED: f32 = 12.0;
+const JUMP_SPEED: f32 = 8.4;
+pub const MAX_FALL_SPEED: f32 = 60.0;
+/// Blocks per second squared
+pub const GRAVITY: f32 = 28.0;
+/// Moves longer than this are split, so the box can't skip over a block
+const MAX_MOVE_STEP: f32 = 0.5;
+/// Gap left between the box and a block it was pushed out of
+const SKIN: f32 = 0.001;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn player(feet: Vec3) -> Self {
+        let half = PLAYER_WIDTH / 2.0;
+        Self {
+            min: feet - vec3(half, 0.0, half),
+            max: feet + vec3(half, PLAYER_HEIGHT, half),
+        }
+    }
+
+    /// Blocks the box overlaps, touching a block face is not overlapping
+    pub fn blocks(&self) -> impl Iterator<Item = BlockPos> {
+        let min = self.min.floor().as_ivec3();
+        let max = self.max.ceil().as_ivec3() - 1;
+        (min.x..=max.x).flat_map(move |x| {
+            (min.y..=max.y).flat_map(move |y| (min.z..=max.z).map(move |z| BlockPos::new(x, y, z)))
+        })
+    }
+
+    pub fn overlaps_block(&self, pos: BlockPos) -> bool {
+        self.blocks().any(|block| block == pos)
+    }
+
+    pub fn collides(&self, world: &impl BlockSource) -> bool {
+        self.blocks().any(|pos| world.get_block(pos).block_type.is_solid())
+    }
+}
+
+/// What the player wants to do this step
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct MoveInput {
+    /// Move direction, zero to stand still. Walking ignores y
+    pub direction: Vec3,
+    pub jump: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Body {
+    pub pos: Vec3,
+    pub velocity: Vec3,
+    /// Standing on a block, only then jumping works
+    pub grounded: bool,
+}
+
+impl Body {
+    pub const fn new(pos: Vec3) -> Self {
+        Self { pos, velocity: Vec3::ZERO, grounded: false }
+    }
+
+    pub fn eye_pos(&self) -> Vec3 {
+        self.pos + vec3(0.0, EYE_HEIGHT, 0.0)
+    }
+
+    pub fn aabb(&self) -> Aabb {
+        Aabb::player(self.pos)
+    }
+}
+
+/// Applies input and gravity for `dt` seconds, then moves the body
+/// one axis at a time (y, x, z), stopping it at solid blocks
+pub fn step(world: &impl BlockSource, body: Body, input: &MoveInput, dt: f32) -> Body {
+    let mut velocity = body.velocity;
+    let walk = vec3(input.direction.x, 0.0, input.direction.z).normalize_or_zero() * WALK_SPEED;
+    velocity.x = walk.x;
+    velocity.z = walk.z;
+    if input.jump && body.grounded {
+        velocity.y = JUMP_SPEED;
+    }
+    velocity.y = (velocity.y - GRAVITY * dt).max(-MAX_FALL_SPEED);
+
+    let mut pos = body.pos;
+    let mut grounded = false;
+    for axis in [1, 0, 2] {
+        let delta = velocity[axis] * dt;
+        if move_axis(world, &mut pos, axis, delta) {
+            grounded |= axis == 1 && delta < 0.0;
+            velocity[axis] = 0.0;
+        }
+    }
+    Body { pos, velocity, grounded }
+}
+
+/// Moves the body along `input.direction` through blocks, without gravity
+pub fn fly(body: Body, input: &MoveInput, dt: f32) -> Body {
+    Body::new(body.pos + input.direction.normalize_or_zero() * FLY_SPEED * dt)
+}
+
+/// Moves `pos` by `delta` along `axis`, returns true if a block was hit
+fn move_axis(world: &impl BlockSource, pos: &mut Vec3, axis: usize, delta: f32) -> bool {
+    let steps = (delta.abs() / MAX_MOVE_STEP).ceil() as usize;
+    for _ in 0..steps {
+        pos[axis] += delta / steps as f32;
+        let aabb = Aabb::player(*pos);
+        if aabb.collides(world) {
+            // back against the face of the block that was entered
+            pos[axis] = if delta > 0.0 {
+                pos[axis] + aabb.max[axis].ceil() - 1.0 - aabb.max[axis] - SKIN
+            } else {
+                pos[axis] + aabb.min[axis].floor() + 1.0 - aabb.min[axis] + SKIN
+            };
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::test_world::stone_floor;
+    use crate::world::{BlockState, World};
+
+    const DT: f32 = 1.0 / 60.0;
+
+    fn run(world: &World, mut body: Body, input: MoveInput, steps: usize) -> Body {
+        for _ in 0..steps {
+            body = step(world, body, &input, DT);
+        }
+        body
+    }
+
+    #[test]
+    fn aabb_blocks_exclude_touched_faces() {
+        let aabb = Aabb::player(vec3(0.5, 1.0, 0.5));
+        let blocks: Vec<_> = aabb.blocks().collect();
+        assert_eq!(blocks, vec![BlockPos::new(0, 1, 0), BlockPos::new(0, 2, 0)]);
+
+        let aabb = Aabb::player(vec3(1.0, 1.0, 0.5));
+        assert_eq!(aabb.blocks().count(), 4);
+        assert!(aabb.overlaps_block(BlockPos::new(0, 2, 0)));
+        assert!(!aabb.overlaps_block(BlockPos::new(0, 3, 0)));
+        assert!(!aabb.overlaps_block(BlockPos::new(1, 1, 1)));
+    }
+
+    #[test]
+    fn falls_and_lands_on_floor() {
+        let world = stone_floor(-8..8, -8..8);
+        let body = run(&world, Body::new(vec3(0.5, 5.0, 0.5)), MoveInput::default(), 120);
+        assert!(body.grounded);
+        assert!((body.pos.y - 1.0).abs() < 0.01, "{}", body.pos.y);
+        assert_eq!(body.velocity.y, 0.0);
+        assert!(!body.aabb().collides(&world));
+    }
+
+    #[test]
+    fn falls_without_ground() {
+        let world = World::new();
+        let body = run(&world, Body::new(vec3(0.5, 5.0, 0.5)), MoveInput::default(), 60);
+        assert!(!body.grounded);
+        assert!(body.pos.y < -5.0);
+    }
+
+    #[test]
+    fn fast_fall_does_not_pass_through_floor() {
+        let world = stone_floor(-8..8, -8..8);
+        let mut body = Body::new(vec3(0.5, 3.0, 0.5));
+        body.velocity.y = -MAX_FALL_SPEED;
+        let body = step(&world, body, &MoveInput::default(), 0.1);
+        assert!(body.grounded);
+        assert!(body.pos.y >= 1.0);
+    }
+
+    #[test]
+    fn jumps_only_when_grounded() {
+        let world = stone_floor(-8..8, -8..8);
+        let jump = MoveInput { jump: true, ..Default::default() };
+
+        let airborne = Body::new(vec3(0.5, 3.0, 0.5));
+        assert!(step(&world, airborne, &jump, DT).velocity.y < 0.0);
+
+        let standing = run(&world, Body::new(vec3(0.5, 1.5, 0.5)), MoveInput::default(), 30);
+        assert!(standing.grounded);
+        let jumped = step(&world, standing, &jump, DT);
+        assert!(jumped.velocity.y > 0.0);
+        assert!(!jumped.grounded);
+
+        let top = run(&world, jumped, MoveInput::default(), 10);
+        assert!(top.pos.y > 2.0, "{}", top.pos.y);
+    }
+
+    #[test]
+    fn walks_on_floor() {
+        let world = stone_floor(-8..8, -8..8);
+        let walk = MoveInput { direction: vec3(1.0, 0.0, 0.0), jump: false };
+        let start = run(&world, Body::new(vec3(0.5, 1.0, 0.5)), MoveInput::default(), 5);
+        let body = run(&world, start, walk, 60);
+        assert!((body.pos.x - (0.5 + WALK_SPEED)).abs() < 0.01, "{}", body.pos.x);
+        assert!(body.grounded);
+    }
+
+    #[test]
+    fn wall_stops_movement() {
+        let mut world = stone_floor(-8..8, -8..8);
+        for y in 1..3 {
+            for z in -8..8 {
+                world.set_block(BlockPos::new(3, y, z), BlockState::STONE);
+            }
+        }
+        let walk = MoveInput { direction: vec3(1.0, 0.0, 0.0), jump: false };
+        let body = run(&world, Body::new(vec3(0.5, 1.0, 0.5)), walk, 120);
+        assert!((body.aabb().max.x - 3.0).abs() < 0.01, "{}", body.aabb().max.x);
+        assert!(!body.aabb().collides(&world));
+
+        // sliding along the wall still works
+        let slide = MoveInput { direction: vec3(1.0, 0.0, -1.0), jump: false };
+        let slid = run(&world, body, slide, 30);
+        assert!(slid.pos.z < -1.0);
+        assert!(slid.pos.x < 3.0);
+    }
+
+    #[test]
+    fn flying_ignores_blocks_and_gravity() {
+        let world = stone_floor(-8..8, -8..8);
+        let down = MoveInput { direction: vec3(0.0, -1.0, 0.0), jump: false };
+        let body = (0..60).fold(Body::new(vec3(0.5, 5.0, 0.5)), |body, _| fly(body, &down, DT));
+        assert!((body.pos.y - (5.0 - FLY_SPEED)).abs() < 0.01, "{}", body.pos.y);
+        assert_eq!(fly(body, &MoveInput::default(), DT).pos, body.pos);
+        assert!(step(&world, Body::new(vec3(0.5, 5.0, 0.5)), &down, DT).pos.y < 5.0);
+    }
+
+    #[test]
+    fn ceiling_stops_jump() {
+        let mut world = stone_floor(-8..8, -8..8);
+        world.set_block(BlockPos::new(0, 3, 0), BlockState::STONE);
+        let standing = run(&world, Body::new(vec3(0.5, 1.0, 0.5)), MoveInput::default(), 5);
+        let jump = MoveInput { jump: true, ..Default::default() };
+        let body = step(&world, standing, &jump, DT);
+        let body = run(&world, body, MoveInput::default(), 10);
+        assert!(body.aabb().max.y <= 3.0);
+    }
+}
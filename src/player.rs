@@ -1,12 +1,16 @@
 use derive_more::{Deref, DerefMut};
 use macroquad::prelude::*;
 
-const LOOK_SPEED: f32 = 0.1;
-const MOVE_SPEED: f32 = 0.2;
+use crate::physics::MoveInput;
+use crate::world::edit::BlockAction;
+use crate::world::BlockType;
+
+/// Radians of yaw or pitch per pixel of mouse movement
+const MOUSE_SENSITIVITY: f32 = 0.002;
 
 const UP: Vec3 = vec3(0.0, 1.0, 0.0);
 
-/// ```
+/// ```ignore
 /// let mp: LastMousePos = mouse_position().into();
 /// ```
 #[derive(Deref, DerefMut, Clone, Copy)]
@@ -47,10 +51,9 @@ pub fn update_yaw_pitch_after_mouse_pos_changed(
     pitch: &mut Pitch,
 ) {
     let mouse_delta: Vec2 = current_mouse_pos.0 - last_mouse_pos.0;
-    let frame_time = get_frame_time();
 
-    yaw.0 += mouse_delta.x * frame_time * LOOK_SPEED;
-    pitch.0 += mouse_delta.y * frame_time * -LOOK_SPEED;
+    yaw.0 += mouse_delta.x * MOUSE_SENSITIVITY;
+    pitch.0 += mouse_delta.y * -MOUSE_SENSITIVITY;
 
     pitch.0 = if pitch.0 > 1.5 { 1.5 } else { pitch.0 };
     pitch.0 = if pitch.0 < -1.5 { -1.5 } else { pitch.0 };
@@ -59,35 +62,76 @@ pub fn update_yaw_pitch_after_mouse_pos_changed(
 #[derive(Deref, DerefMut, Clone, Copy, Default)]
 pub struct PlayerPos(pub Vec3);
 
-pub fn update_player_pos_after_front_right_up_changed(
-    player_pos: &mut PlayerPos,
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum MovementMode {
+    /// Gravity and block collision
+    #[default]
+    Walk,
+    /// Free flight through blocks
+    Fly,
+}
 
-    front: Front,
-    right: Right,
-) {
-    let mut velocity: Vec3 = Vec3::ZERO;
+impl MovementMode {
+    pub fn switch(&mut self) {
+        *self = match self {
+            Self::Walk => Self::Fly,
+            Self::Fly => Self::Walk,
+        }
+    }
+}
+
+pub fn update_movement_mode_on_f_press(mode: &mut MovementMode) {
+    if is_key_pressed(KeyCode::F) {
+        mode.switch();
+    }
+}
+
+/// WASD walks along the ground, Space jumps
+pub fn walk_input(front: Front, right: Right) -> MoveInput {
+    let mut direction: Vec3 = Vec3::ZERO;
 
     if is_key_down(KeyCode::W) {
-        velocity += zero_y_normalize(front.0);
+        direction += zero_y_normalize(front.0);
     }
     if is_key_down(KeyCode::S) {
-        velocity -= zero_y_normalize(front.0);
+        direction -= zero_y_normalize(front.0);
     }
     if is_key_down(KeyCode::A) {
-        velocity -= zero_y_normalize(right.0);
+        direction -= zero_y_normalize(right.0);
     }
     if is_key_down(KeyCode::D) {
-        velocity += zero_y_normalize(right.0);
+        direction += zero_y_normalize(right.0);
     }
+
+    MoveInput {
+        direction,
+        jump: is_key_down(KeyCode::Space),
+    }
+}
+
+/// WASD flies along the ground, Space and Shift fly up and down
+pub fn fly_input(front: Front, right: Right) -> MoveInput {
+    let mut direction = walk_input(front, right).direction;
+
     if is_key_down(KeyCode::Space) {
-        velocity += UP;
+        direction += UP;
     }
     if is_key_down(KeyCode::LeftShift) {
-        velocity -= UP;
+        direction -= UP;
     }
 
-    if velocity != Vec3::ZERO {
-        player_pos.0 += velocity.normalize() * MOVE_SPEED;
+    MoveInput { direction, jump: false }
+}
+
+/// Left click breaks the targeted block, right click places `selected` against it
+/// Nothing is placed without a `selected` block
+pub fn block_action_on_mouse_press(selected: Option<BlockType>) -> Option<BlockAction> {
+    if is_mouse_button_pressed(MouseButton::Left) {
+        Some(BlockAction::Break)
+    } else if is_mouse_button_pressed(MouseButton::Right) {
+        selected.map(BlockAction::Place)
+    } else {
+        None
     }
 }
 
@@ -132,6 +176,7 @@ impl Front {
         )
     }
 
+    #[allow(dead_code)]
     pub fn update(&mut self, yaw: Yaw, pitch: Pitch) {
         *self = Self::new(yaw, pitch);
     }
@@ -145,6 +190,7 @@ impl Right {
         Self(front.cross(UP).normalize())
     }
 
+    #[allow(dead_code)]
     pub fn update(&mut self, front: Front) {
         *self = Self::new(front);
     }
@@ -158,12 +204,13 @@ impl Up {
         Self(right.cross(front.0).normalize())
     }
 
+    #[allow(dead_code)]
     pub fn update(&mut self, right: Right, front: Front) {
         *self = Self::new(right, front);
     }
 }
 
-/// ```
+/// ```ignore
 /// let yaw = Yaw::default();
 /// ```
 #[derive(Debug, Deref, DerefMut, Clone, Copy)]
@@ -175,7 +222,7 @@ impl Default for Yaw {
     }
 }
 
-/// ```
+/// ```ignore
 /// let pitch = Pitch::default();
 /// ```
 #[derive(Debug, Deref, DerefMut, Clone, Copy)]
@@ -0,0 +1,73 @@
+/// Longest frame time that is simulated, longer frames (a dragged window, a breakpoint)
+/// slow the game down instead of running hundreds of ticks at once
+const MAX_FRAME_TIME: f32 = 0.25;
+
+/// Turns variable frame times into a whole number of fixed length simulation ticks
+#[derive(Debug, Clone, Copy)]
+pub struct FixedTimestep {
+    tick: f32,
+    accumulator: f32,
+}
+
+impl FixedTimestep {
+    pub const fn new(ticks_per_second: f32) -> Self {
+        Self { tick: 1.0 / ticks_per_second, accumulator: 0.0 }
+    }
+
+    /// Seconds of one tick
+    pub const fn tick(&self) -> f32 {
+        self.tick
+    }
+
+    /// Adds the frame time, returns how many ticks to run this frame
+    pub fn advance(&mut self, frame_time: f32) -> usize {
+        self.accumulator += frame_time.clamp(0.0, MAX_FRAME_TIME);
+        let ticks = (self.accumulator / self.tick).floor();
+        self.accumulator = (self.accumulator - ticks * self.tick).max(0.0);
+        ticks as usize
+    }
+
+    /// How far the frame is between the last tick and the next one, 0.0..1.0.
+    /// Rendering lerps the last two simulated states by it
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.tick).min(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ticks_over(frame_time: f32, frames: usize) -> usize {
+        let mut timestep = FixedTimestep::new(60.0);
+        (0..frames).map(|_| timestep.advance(frame_time)).sum()
+    }
+
+    #[test]
+    fn same_ticks_at_any_frame_rate() {
+        let at_30 = ticks_over(1.0 / 30.0, 30);
+        let at_60 = ticks_over(1.0 / 60.0, 60);
+        let at_144 = ticks_over(1.0 / 144.0, 144);
+        for ticks in [at_30, at_60, at_144] {
+            assert!((59..=60).contains(&ticks), "{}", ticks);
+        }
+    }
+
+    #[test]
+    fn short_frames_accumulate() {
+        let mut timestep = FixedTimestep::new(10.0);
+        assert_eq!(timestep.advance(0.04), 0);
+        assert!((timestep.alpha() - 0.4).abs() < 1e-5);
+        assert_eq!(timestep.advance(0.04), 0);
+        assert_eq!(timestep.advance(0.04), 1);
+        assert!((timestep.alpha() - 0.2).abs() < 1e-5);
+    }
+
+    #[test]
+    fn long_frames_are_cut() {
+        let mut timestep = FixedTimestep::new(10.0);
+        assert_eq!(timestep.advance(10.0), 2);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-5);
+        assert_eq!(timestep.advance(-1.0), 0);
+    }
+}
@@ -1,62 +1,40 @@
 use std::array::from_fn as arr_fn;
-use std::ops::Range;
-use crate::world::render::render_cube_byte::RenderCubeByte;
-use crate::world::render::{ChunkModel, UvTexture, WorldPos};
+use std::collections::HashMap;
+use std::io;
+use std::ops::{Add, Range};
+use std::path::Path;
+use crate::world::light::{ChunkLight, Light, LightLayer};
+use crate::world::palette::PalettedBlocks;
+use crate::world::registry::{BlockProperties, BlockRegistry, Transparency};
+use crate::world::render::{BlockSide, ChunkModel, UvTexture, WorldPos};
 use macroquad::math::Vec3;
 use macroquad::prelude::vec3;
 // use rand::Rng;""
 
+pub mod edit;
+pub mod light;
+pub mod palette;
+pub mod raycast;
+pub mod region;
+pub mod registry;
 pub mod render;
+pub mod streaming;
+#[cfg(test)]
+pub mod temp_dir;
+pub mod terrain;
+#[cfg(test)]
+pub mod test_world;
+pub mod workers;
 
 pub const CHUNK_SIZE_16: usize = 16;
-type TextureSet = [Option<UvTexture>; 6];
-
-const AIR_SET: TextureSet = [None; 6];
-const DIRT_SET: TextureSet = [Some(UvTexture::DIRT); 6];
-const SAND_SET: TextureSet = [Some(UvTexture::SAND); 6];
-const TILE_SET: TextureSet = [
-    None,
-    None,
-    None,
-    None,
-    None,
-    Some(UvTexture::SAND),
-];
-const STONE_SET: TextureSet = [Some(UvTexture::STONE); 6];
-const GRASS_SET: TextureSet = [
-    Some(UvTexture::GRASS_SIDE),
-    Some(UvTexture::GRASS_SIDE),
-    Some(UvTexture::GRASS_SIDE),
-    Some(UvTexture::GRASS_SIDE),
-    Some(UvTexture::DIRT),
-    Some(UvTexture::GRASS_TOP)
-];
+/// Textures of the block faces in `RenderCubeByte` bits order
+pub type TextureSet = [Option<UvTexture>; 6];
 
 #[derive(Debug, Default, PartialEq, Eq, Clone)]
 pub struct BlockState {
     pub block_type: BlockType,
 }
 
-// impl BlockState {
-//     pub fn get_texture_set(&self) -> TextureSet {
-//         let mut ts = [None; 6];
-//         match self.block_type {
-//             BlockType::Air => {}
-//             BlockType::Dirt => ts.fill(Some(UvTexture::DIRT)),
-//             BlockType::Grass => {
-//                 ts[0] = Some(UvTexture::GRASS_TOP);
-//                 ts[1] = Some(UvTexture::DIRT);
-//                 for i in 2..6 {
-//                     ts[i] = Some(UvTexture::GRASS_SIDE)
-//                 }
-//             }
-//             BlockType::Stone => ts.fill(Some(UvTexture::STONE)),
-//             BlockType::Sand => ts.fill(Some(UvTexture::SAND)),
-//         }
-//         ts.reverse(); //to byte order
-//         ts
-//     }
-// }
 impl BlockState {
     pub const fn new(block_type: BlockType) -> Self {
         Self { block_type }
@@ -69,43 +47,333 @@ impl BlockState {
 
 #[allow(dead_code)]
 impl BlockState {
-    pub const AIR: BlockState = BlockState::new(BlockType::Air);
-    pub const EMPTY: BlockState = BlockState::new(BlockType::Air);
-
-    pub const STONE: BlockState = BlockState::new(BlockType::Stone);
-    pub const DIRT: BlockState = BlockState::new(BlockType::Dirt);
-    pub const GRASS: BlockState = BlockState::new(BlockType::Grass);
-    pub const SAND: BlockState = BlockState::new(BlockType::Sand);
-    pub const TILE: BlockState = BlockState::new(BlockType::Tile);
-}
+    pub const AIR: BlockState = BlockState::new(BlockType::AIR);
+    pub const EMPTY: BlockState = BlockState::new(BlockType::AIR);
 
-#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
-#[allow(dead_code)]
-pub enum BlockType {
-    #[default] Air,
-    Dirt,
-    Grass,
-    Stone,
-    Sand,
-    Tile,
+    pub const STONE: BlockState = BlockState::new(BlockType::STONE);
+    pub const DIRT: BlockState = BlockState::new(BlockType::DIRT);
+    pub const GRASS: BlockState = BlockState::new(BlockType::GRASS);
+    pub const SAND: BlockState = BlockState::new(BlockType::SAND);
+    pub const TILE: BlockState = BlockState::new(BlockType::TILE);
 }
 
+/// Numeric id of a block in the `BlockRegistry`, the properties of the block are there
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct BlockType(u16);
+
+#[allow(dead_code)]
 impl BlockType {
+    pub const AIR: BlockType = BlockType(0);
+    pub const DIRT: BlockType = BlockType(1);
+    pub const GRASS: BlockType = BlockType(2);
+    pub const STONE: BlockType = BlockType(3);
+    pub const SAND: BlockType = BlockType(4);
+    pub const TILE: BlockType = BlockType(5);
+
+    /// Blocks the game places itself, every registry has them with these ids
+    pub const BUILTIN: [(BlockType, &'static str); 6] = [
+        (BlockType::AIR, "air"),
+        (BlockType::DIRT, "dirt"),
+        (BlockType::GRASS, "grass"),
+        (BlockType::STONE, "stone"),
+        (BlockType::SAND, "sand"),
+        (BlockType::TILE, "tile"),
+    ];
+
     pub const fn is_empty(&self) -> bool {
-        matches!(self, BlockType::Air)
+        self.0 == BlockType::AIR.0
+    }
+
+    /// Stable number of the type in save files
+    pub const fn id(&self) -> u16 {
+        self.0
+    }
+
+    /// `None` if the id is not in the registry
+    pub fn from_id(id: u16) -> Option<Self> {
+        BlockRegistry::global().contains(id).then_some(Self(id))
     }
+
+    pub fn properties(&self) -> &'static BlockProperties {
+        BlockRegistry::global().get(*self).expect("block types come from the registry")
+    }
+
+    pub fn name(&self) -> &'static str {
+        &self.properties().name
+    }
+
+    pub fn is_solid(&self) -> bool {
+        self.properties().solid
+    }
+
+    /// Rays stop at it, see `raycast`. Non solid translucent blocks like water are looked through
+    /// and can be placed into
+    pub fn is_targetable(&self) -> bool {
+        !self.is_empty() && (self.is_solid() || self.transparency() != Transparency::Translucent)
+    }
+
     pub fn get_textures(&self) -> TextureSet {
-        match self {
-            BlockType::Air => AIR_SET,
-            BlockType::Dirt => DIRT_SET,
-            BlockType::Grass => GRASS_SET,
-            BlockType::Stone => STONE_SET,
-            BlockType::Sand => SAND_SET,
-            BlockType::Tile => TILE_SET,
+        self.properties().textures
+    }
+
+    /// Texture the block is shown with in the UI: the top, else the first face that has one
+    pub fn icon(&self) -> Option<UvTexture> {
+        let textures = self.get_textures();
+        textures[5].or_else(|| textures.into_iter().flatten().next())
+    }
+
+    /// Block left to pick up when this one is broken
+    pub fn drops(&self) -> Option<BlockType> {
+        self.properties().drops
+    }
+
+    pub fn transparency(&self) -> Transparency {
+        self.properties().transparency
+    }
+
+    /// Light the block gives off, 0 for most blocks
+    pub fn light_emission(&self) -> u8 {
+        self.properties().light
+    }
+
+    /// Opaque blocks shade the face corners around them, see `render::occlusion`
+    pub fn is_opaque(&self) -> bool {
+        self.transparency() == Transparency::Opaque
+    }
+
+    /// Light spreads into non opaque blocks
+    pub fn lets_light_through(&self) -> bool {
+        !self.is_opaque()
+    }
+
+    /// True if a face of `block` touching this block can't be seen through it
+    pub fn hides_face_of(&self, block: BlockType) -> bool {
+        match self.transparency() {
+            Transparency::Opaque => true,
+            Transparency::Cutout => false,
+            Transparency::Translucent => *self == block,
         }
     }
 }
 
+/// Global position of a block
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct BlockPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl BlockPos {
+    pub const fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub const fn chunk_pos(&self) -> ChunkPos {
+        let size = CHUNK_SIZE_16 as i32;
+        ChunkPos(self.x.div_euclid(size), self.y.div_euclid(size), self.z.div_euclid(size))
+    }
+
+    /// (usize, usize, usize) - pos in chunk 0..16
+    pub const fn in_chunk(&self) -> (usize, usize, usize) {
+        let size = CHUNK_SIZE_16 as i32;
+        (
+            self.x.rem_euclid(size) as usize,
+            self.y.rem_euclid(size) as usize,
+            self.z.rem_euclid(size) as usize,
+        )
+    }
+}
+
+impl From<Vec3> for BlockPos {
+    /// The block containing the point
+    fn from(value: Vec3) -> Self {
+        let floor = value.floor();
+        BlockPos::new(floor.x as i32, floor.y as i32, floor.z as i32)
+    }
+}
+
+impl Add for BlockPos {
+    type Output = BlockPos;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        let BlockPos { x, y, z } = self;
+        BlockPos {
+            x: x + rhs.x,
+            y: y + rhs.y,
+            z: z + rhs.z,
+        }
+    }
+}
+
+/// Position of a chunk in chunks, `ChunkPos(1, 0, 0)` starts at block x = 16
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct ChunkPos(pub i32, pub i32, pub i32);
+
+impl ChunkPos {
+    /// Global position of the block (0, 0, 0) of the chunk
+    pub const fn origin(&self) -> BlockPos {
+        let size = CHUNK_SIZE_16 as i32;
+        BlockPos::new(self.0 * size, self.1 * size, self.2 * size)
+    }
+
+    pub fn center(&self) -> Vec3 {
+        let origin = self.origin();
+        let half = CHUNK_SIZE_16 as f32 / 2.0;
+        vec3(origin.x as f32 + half, origin.y as f32 + half, origin.z as f32 + half)
+    }
+
+    pub fn world_pos(&self) -> WorldPos {
+        let origin = self.origin();
+        WorldPos {
+            x: origin.x as f32,
+            y: origin.y as f32,
+            z: origin.z as f32,
+        }
+    }
+
+    pub const fn neighbour(&self, side: BlockSide) -> ChunkPos {
+        let offset = side.offset();
+        ChunkPos(self.0 + offset.x, self.1 + offset.y, self.2 + offset.z)
+    }
+}
+
+/// Anything to look blocks up in by global position
+pub trait BlockSource {
+    fn get_block(&self, pos: BlockPos) -> &BlockState;
+}
+
+impl BlockSource for World {
+    fn get_block(&self, pos: BlockPos) -> &BlockState {
+        World::get_block(self, pos)
+    }
+}
+
+/// Positions are local to the chunk, everything outside 0..16 is air
+impl BlockSource for Chunk {
+    fn get_block(&self, pos: BlockPos) -> &BlockState {
+        let range = 0..CHUNK_SIZE_16 as i32;
+        if range.contains(&pos.x) && range.contains(&pos.y) && range.contains(&pos.z) {
+            self.get(pos.x as usize, pos.y as usize, pos.z as usize)
+        } else {
+            &BlockState::AIR
+        }
+    }
+}
+
+/// All loaded chunks, the one place to ask what block is at x, y, z
+#[derive(Debug, Default)]
+pub struct World {
+    chunks: HashMap<ChunkPos, Chunk>,
+}
+
+#[allow(dead_code)]
+impl World {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_chunk(&self, pos: ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(&pos)
+    }
+
+    pub fn get_chunk_mut(&mut self, pos: ChunkPos) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&pos)
+    }
+
+    /// Creates an empty chunk at `pos` if there is none yet
+    pub fn get_or_create_chunk(&mut self, pos: ChunkPos) -> &mut Chunk {
+        self.chunks.entry(pos).or_insert_with(|| {
+            let mut chunk = Chunk::EMPTY;
+            chunk.pos = pos.world_pos();
+            chunk
+        })
+    }
+
+    /// Moves `chunk` to `pos`, returns the replaced chunk
+    pub fn insert_chunk(&mut self, pos: ChunkPos, mut chunk: Chunk) -> Option<Chunk> {
+        chunk.pos = pos.world_pos();
+        self.chunks.insert(pos, chunk)
+    }
+
+    pub fn remove_chunk(&mut self, pos: ChunkPos) -> Option<Chunk> {
+        self.chunks.remove(&pos)
+    }
+
+    pub fn chunk_positions(&self) -> impl Iterator<Item = ChunkPos> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = (&ChunkPos, &Chunk)> {
+        self.chunks.iter()
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Positions of chunks with `is_dirty` set, the flags are cleared
+    pub fn take_dirty_chunks(&mut self) -> Vec<ChunkPos> {
+        self.chunks
+            .iter_mut()
+            .filter(|(_, chunk)| chunk.is_dirty)
+            .map(|(pos, chunk)| {
+                chunk.is_dirty = false;
+                *pos
+            })
+            .collect()
+    }
+
+    /// Blocks of not loaded chunks are air
+    pub fn get_block(&self, pos: BlockPos) -> &BlockState {
+        let (x, y, z) = pos.in_chunk();
+        self.get_chunk(pos.chunk_pos())
+            .map_or(&BlockState::AIR, |chunk| chunk.get(x, y, z))
+    }
+
+    /// True if the chunk of `pos` and the one below it are loaded. Anything standing
+    /// at `pos` would fall through the air `get_block` gives for the missing ones
+    pub fn is_ground_loaded(&self, pos: BlockPos) -> bool {
+        let chunk = pos.chunk_pos();
+        self.chunks.contains_key(&chunk) && self.chunks.contains_key(&chunk.neighbour(BlockSide::Ny))
+    }
+
+    /// Not loaded chunks are open to the sky
+    pub fn get_light(&self, pos: BlockPos) -> Light {
+        let (x, y, z) = pos.in_chunk();
+        self.get_chunk(pos.chunk_pos())
+            .map_or(Light::SKY, |chunk| chunk.light.get(x, y, z))
+    }
+
+    /// Creates the chunk if it is not loaded
+    pub fn set_block(&mut self, pos: BlockPos, state: BlockState) {
+        let (x, y, z) = pos.in_chunk();
+        let chunk = self.get_or_create_chunk(pos.chunk_pos());
+        chunk.set(x, y, z, state);
+        chunk.is_modified = true;
+    }
+
+    /// Writes the modified chunks to region files in `dir`, the others are
+    /// the same as the terrain generator makes them. See `Chunk::needs_saving`
+    pub fn save(&mut self, dir: &Path) -> io::Result<()> {
+        let modified = self.chunks.iter().filter(|(_, chunk)| chunk.needs_saving());
+        region::save_chunks(dir, modified.map(|(pos, chunk)| (*pos, chunk)))?;
+        for chunk in self.chunks.values_mut() {
+            chunk.is_modified = false;
+        }
+        Ok(())
+    }
+
+    /// All chunks saved in `dir`, without models
+    pub fn load(dir: &Path) -> io::Result<World> {
+        let mut world = World::new();
+        for (pos, chunk) in region::load_all_chunks(dir)? {
+            world.insert_chunk(pos, chunk);
+        }
+        Ok(world)
+    }
+}
+
 #[derive(Debug, Default, PartialEq, Eq, Clone)]
 #[allow(dead_code)]
 pub enum Biome {
@@ -115,13 +383,36 @@ pub enum Biome {
     Jungle,
 }
 
+impl Biome {
+    pub const fn surface_block(&self) -> BlockState {
+        match self {
+            Biome::Desert => BlockState::SAND,
+            Biome::Plains | Biome::Forest | Biome::Jungle => BlockState::GRASS,
+        }
+    }
+
+    pub const fn subsurface_block(&self) -> BlockState {
+        match self {
+            Biome::Desert => BlockState::SAND,
+            Biome::Plains | Biome::Forest | Biome::Jungle => BlockState::DIRT,
+        }
+    }
+}
+
 #[derive(Debug, Default, PartialEq, Clone)]
 pub struct Chunk {
     pub biome: Biome,
-    pub blocks: [ChunkLayer; CHUNK_SIZE_16],
+    pub blocks: PalettedBlocks,
+    /// Sky and block light of every block, see `light::ChunkLight::compute`
+    pub light: ChunkLight,
     pub pos: WorldPos,
     pub model: ChunkModel,
-    pub is_visible: bool,
+    /// `model` changed since the chunk mesh was built
+    pub is_dirty: bool,
+    /// `blocks` changed since the chunk was generated, loaded or saved
+    pub is_modified: bool,
+    /// The saved chunk failed to load and was generated instead, saving it would overwrite the save
+    pub load_failed: bool,
 }
 
 #[allow(dead_code)]
@@ -129,24 +420,34 @@ impl Chunk {
     const RANGE: Range<isize> = 0..CHUNK_SIZE_16 as isize;
     /// (usize, usize, usize) - pos in chunk 0..16
     #[allow(dead_code)]
-    pub fn from_fn(mut func: impl FnMut(usize, usize, usize) -> BlockState) -> Chunk {
+    pub fn from_fn(func: impl FnMut(usize, usize, usize) -> BlockState) -> Chunk {
         Chunk {
             biome: Biome::Plains,
-            blocks: arr_fn(|y| ChunkLayer::from_fn(|x, z| func(x, y, z))),
+            blocks: PalettedBlocks::from_fn(func),
+            light: ChunkLight::DARK,
             pos: Default::default(),
             model: ChunkModel::default(),
-            is_visible: true,
+            is_dirty: false,
+            is_modified: false,
+            load_failed: false,
         }
     }
 
+    /// Modified and safe to write over what is saved
+    pub fn needs_saving(&self) -> bool {
+        self.is_modified && !self.load_failed
+    }
+
     pub fn fill(&mut self, state: BlockState) {
-        for y in 0..CHUNK_SIZE_16 {
-            self.blocks[y].fill(state.clone());
-        }
+        self.blocks = PalettedBlocks::Single(state);
     }
 
     pub fn get(&self, x: usize, y: usize, z: usize) -> &BlockState {
-        self.blocks[y].get(x, z)
+        self.blocks.get(x, y, z)
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, z: usize, state: BlockState) {
+        self.blocks.set(x, y, z, state);
     }
 
     fn calc_indexes(x: isize, y: isize, z: isize) -> [[isize; 3]; 6] {
@@ -164,61 +465,53 @@ impl Chunk {
         for i in 0..6 {
             if Self::RANGE.contains(&indexes[i][0]) &&
                 Self::RANGE.contains(&indexes[i][1]) &&
-                Self::RANGE.contains(&indexes[i][2])
+                Self::RANGE.contains(&indexes[i][2]) &&
+                !self.get(x as usize, y as usize, z as usize).is_empty()
             {
-                if !self.get(x as usize, y as usize, z as usize).is_empty() {
-                    return false;
-                }
+                return false;
             }
         }
         true
     }
-    pub fn get_neighbours(&self, x: isize, y: isize, z: isize) -> RenderCubeByte {
-        let mut ans = RenderCubeByte::ALL;
-        let indexes = Self::calc_indexes(x, y, z);
-        for i in 0..6 {
-            ans.set_bit(i, self.has_visible_block_at(indexes[i][0], indexes[i][1], indexes[i][2]));
-        }
-        ans
-    }
-    pub fn has_visible_block_at(&self, x: isize, y: isize, z: isize) -> bool {
-        if Self::RANGE.contains(&x) && Self::RANGE.contains(&y) && Self::RANGE.contains(&z) {
-            !self.get(x as usize, y as usize, z as usize).is_empty()
-        } else {
-            false
+    /// Blocks on the `side` border of the chunk, indexed as in `ConnectedChunks`
+    pub fn border_layer(&self, side: BlockSide) -> ChunkLayer {
+        const LAST: usize = CHUNK_SIZE_16 - 1;
+        match side {
+            BlockSide::Py => ChunkLayer::from_fn(|x, z| self.get(x, LAST, z).clone()),
+            BlockSide::Ny => ChunkLayer::from_fn(|x, z| self.get(x, 0, z).clone()),
+            BlockSide::Px => ChunkLayer::from_fn(|y, z| self.get(LAST, y, z).clone()),
+            BlockSide::Nx => ChunkLayer::from_fn(|y, z| self.get(0, y, z).clone()),
+            BlockSide::Pz => ChunkLayer::from_fn(|x, y| self.get(x, y, LAST).clone()),
+            BlockSide::Nz => ChunkLayer::from_fn(|x, y| self.get(x, y, 0).clone()),
         }
     }
 
-    pub fn get_block_visible_sides(block_pos: WorldPos, player_pos: Vec3) -> RenderCubeByte {
-        let block_pos = vec3(block_pos.x, block_pos.y, block_pos.z);
-
-        let (player_py, player_px, player_pz) = (
-            player_pos.y > block_pos.y, player_pos.x > block_pos.x, player_pos.z > block_pos.z
-        );
-        let mut byte = RenderCubeByte::NOTHING;
-        let y_pos = if player_py { 5 } else { 4 };
-        let x_pos = if player_px { 3 } else { 2 };
-        let z_pos = if player_pz { 1 } else { 0 };
-        byte.set_bit(y_pos, true);
-        byte.set_bit(x_pos, true);
-        byte.set_bit(z_pos, true);
-        byte
-    }
-
-    pub fn get_mut(&mut self, x: usize, y: usize, z: usize) -> &mut BlockState {
-        self.blocks[y].get_mut(x, z)
+    /// Light of the blocks on the `side` border of the chunk, indexed as in `ConnectedChunks`
+    pub fn border_light(&self, side: BlockSide) -> LightLayer {
+        const LAST: usize = CHUNK_SIZE_16 - 1;
+        match side {
+            BlockSide::Py => LightLayer::from_fn(|x, z| self.light.get(x, LAST, z)),
+            BlockSide::Ny => LightLayer::from_fn(|x, z| self.light.get(x, 0, z)),
+            BlockSide::Px => LightLayer::from_fn(|y, z| self.light.get(LAST, y, z)),
+            BlockSide::Nx => LightLayer::from_fn(|y, z| self.light.get(0, y, z)),
+            BlockSide::Pz => LightLayer::from_fn(|x, y| self.light.get(x, y, LAST)),
+            BlockSide::Nz => LightLayer::from_fn(|x, y| self.light.get(x, y, 0)),
+        }
     }
 
     pub const EMPTY: Chunk = Chunk {
         biome: Biome::Plains,
-        blocks: [ChunkLayer::EMPTY; CHUNK_SIZE_16],
+        blocks: PalettedBlocks::EMPTY,
+        light: ChunkLight::DARK,
         pos: WorldPos {
             x: 0.0,
             y: 0.0,
             z: 0.0,
         },
         model: ChunkModel::EMPTY,
-        is_visible: true,
+        is_dirty: false,
+        is_modified: false,
+        load_failed: false,
     };
     pub fn get_pos(&self) -> WorldPos {
         self.pos
@@ -228,20 +521,6 @@ impl Chunk {
         self.pos.y = y;
         self.pos.z = z;
     }
-
-    pub fn check_visibility(&mut self, player_pos: Vec3, player_front: Vec3) {
-        let chunk_pos: WorldPos = self.get_pos();
-        let chunk_center_pos: Vec3 = vec3(
-            chunk_pos.x + CHUNK_SIZE_16 as f32 / 2.0,
-            chunk_pos.y + CHUNK_SIZE_16 as f32 / 2.0,
-            chunk_pos.z + CHUNK_SIZE_16 as f32 / 2.0,
-        );
-
-        let chunk_view_vec: Vec3 = chunk_center_pos - player_pos;
-        let angle: f32 = player_front.angle_between(chunk_view_vec);
-        let distance: f32 = player_pos.distance(chunk_center_pos);
-        self.is_visible = !(angle >= 65f32.to_radians() || distance > 1.0 * CHUNK_SIZE_16 as f32);
-    }
 }
 
 #[derive(Debug, Default, PartialEq, Eq, Clone)]
@@ -280,4 +559,66 @@ impl ChunkLayer {
 
     pub const EMPTY: ChunkLayer =
         ChunkLayer([const { [BlockState::AIR; CHUNK_SIZE_16] }; CHUNK_SIZE_16]);
+    pub const SOLID: ChunkLayer =
+        ChunkLayer([const { [BlockState::STONE; CHUNK_SIZE_16] }; CHUNK_SIZE_16]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn negative_block_pos_floors_to_chunk() {
+        assert_eq!(BlockPos::new(0, 0, 0).chunk_pos(), ChunkPos(0, 0, 0));
+        assert_eq!(BlockPos::new(15, 16, -1).chunk_pos(), ChunkPos(0, 1, -1));
+        assert_eq!(BlockPos::new(-16, -17, -32).chunk_pos(), ChunkPos(-1, -2, -2));
+        assert_eq!(BlockPos::new(-1, -16, -17).in_chunk(), (15, 0, 15));
+        assert_eq!(ChunkPos(-1, 2, 0).origin(), BlockPos::new(-16, 32, 0));
+    }
+
+    #[test]
+    fn get_block_of_missing_chunk_is_air() {
+        let world = World::new();
+        assert_eq!(*world.get_block(BlockPos::new(-5, 3, 100)), BlockState::AIR);
+        assert_eq!(world.chunk_count(), 0);
+    }
+
+    #[test]
+    fn ground_needs_the_chunk_below_too() {
+        let mut world = World::new();
+        let pos = BlockPos::new(3, 17, -2);
+        assert!(!world.is_ground_loaded(pos));
+        world.get_or_create_chunk(pos.chunk_pos());
+        assert!(!world.is_ground_loaded(pos));
+        world.get_or_create_chunk(ChunkPos(0, 0, -1));
+        assert!(world.is_ground_loaded(pos));
+    }
+
+    #[test]
+    fn set_block_creates_chunk() {
+        let mut world = World::new();
+        let pos = BlockPos::new(-1, -1, 17);
+        world.set_block(pos, BlockState::STONE);
+
+        assert_eq!(*world.get_block(pos), BlockState::STONE);
+        assert_eq!(*world.get_block(pos + BlockPos::new(1, 0, 0)), BlockState::AIR);
+        assert_eq!(world.chunk_positions().collect::<Vec<_>>(), vec![ChunkPos(-1, -1, 1)]);
+
+        let chunk = world.get_chunk(ChunkPos(-1, -1, 1)).unwrap();
+        assert_eq!(*chunk.get(15, 15, 1), BlockState::STONE);
+        assert_eq!(chunk.get_pos(), WorldPos { x: -16.0, y: -16.0, z: 16.0 });
+    }
+
+    #[test]
+    fn blocks_are_kept_per_chunk() {
+        let mut world = World::new();
+        for x in -20..20 {
+            world.set_block(BlockPos::new(x, 0, x), BlockState::DIRT);
+        }
+        assert_eq!(world.chunk_count(), 4);
+        for x in -20..20 {
+            assert_eq!(*world.get_block(BlockPos::new(x, 0, x)), BlockState::DIRT);
+            assert_eq!(*world.get_block(BlockPos::new(x, 1, x)), BlockState::AIR);
+        }
+    }
 }
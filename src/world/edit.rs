@@ -0,0 +1,163 @@
+use macroquad::math::Vec3;
+
+use crate::world::light::update_block_light;
+use crate::world::raycast::{raycast, RaycastHit};
+use crate::world::render::model::{update_block_models, update_light_models, MissingNeighbour};
+use crate::world::{BlockPos, BlockState, BlockType, World};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BlockAction {
+    /// Targeted block becomes air
+    Break,
+    /// New block goes against the targeted side
+    Place(BlockType),
+}
+
+/// Changes the world, its light and the block models, returns the changed block.
+/// Nothing happens when breaking air, placing into a block other than air or a fluid,
+/// or into a chunk that is not loaded yet, the edit would be lost when the chunk arrives
+pub fn apply_block_action(
+    world: &mut World,
+    hit: &RaycastHit,
+    action: BlockAction,
+    missing: MissingNeighbour,
+) -> Option<BlockPos> {
+    let (pos, state) = match action {
+        BlockAction::Break => (hit.block, BlockState::AIR),
+        BlockAction::Place(block_type) => (hit.adjacent, BlockState::new(block_type)),
+    };
+    let (x, y, z) = pos.in_chunk();
+    let current = world.get_chunk(pos.chunk_pos())?.get(x, y, z);
+    let can_change = match action {
+        BlockAction::Break => !current.is_empty(),
+        BlockAction::Place(block_type) => !current.block_type.is_targetable() && !block_type.is_empty(),
+    };
+    if !can_change {
+        return None;
+    }
+    world.set_block(pos, state);
+    let relit = update_block_light(world, pos);
+    update_block_models(world, pos, missing);
+    update_light_models(world, &relit, missing);
+    Some(pos)
+}
+
+/// Raycasts from `origin` and applies `action` to the block hit, if any
+pub fn edit_targeted_block(
+    world: &mut World,
+    origin: Vec3,
+    direction: Vec3,
+    reach: f32,
+    action: BlockAction,
+    missing: MissingNeighbour,
+) -> Option<BlockPos> {
+    let hit = raycast(world, origin, direction, reach)?;
+    apply_block_action(world, &hit, action, missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::vec3;
+
+    use super::*;
+    use crate::world::render::model::build_world_chunk_model;
+    use crate::world::test_world::stone_floor;
+    use crate::world::ChunkPos;
+
+    const MISSING: MissingNeighbour = MissingNeighbour::Air;
+
+    /// Stone floor at y = 0 over two chunks along x, models are built
+    fn floor_world() -> World {
+        let mut world = stone_floor(-16..16, 0..16);
+        for pos in world.chunk_positions().collect::<Vec<_>>() {
+            build_world_chunk_model(&mut world, pos, MISSING);
+        }
+        world.take_dirty_chunks();
+        world
+    }
+
+    fn look_down(world: &mut World, x: f32, action: BlockAction) -> Option<BlockPos> {
+        edit_targeted_block(world, vec3(x, 3.5, 4.5), vec3(0.0, -1.0, 0.0), 6.0, action, MISSING)
+    }
+
+    #[test]
+    fn break_removes_targeted_block() {
+        let mut world = floor_world();
+        let changed = look_down(&mut world, 2.5, BlockAction::Break);
+        assert_eq!(changed, Some(BlockPos::new(2, 0, 4)));
+        assert!(world.get_block(BlockPos::new(2, 0, 4)).is_empty());
+        assert_eq!(world.take_dirty_chunks(), vec![ChunkPos(0, 0, 0)]);
+
+        let chunk = world.get_chunk(ChunkPos(0, 0, 0)).unwrap();
+        // the block next to the hole shows its side now
+        assert!(chunk.model.get_render_byte(1, 0, 4).unwrap().bool_in_pos(3));
+    }
+
+    #[test]
+    fn place_puts_block_on_targeted_side() {
+        let mut world = floor_world();
+        let changed = look_down(&mut world, -0.5, BlockAction::Place(BlockType::SAND));
+        assert_eq!(changed, Some(BlockPos::new(-1, 1, 4)));
+        assert_eq!(*world.get_block(BlockPos::new(-1, 1, 4)), BlockState::SAND);
+        let mut dirty = world.take_dirty_chunks();
+        dirty.sort_by_key(|pos| pos.0);
+        // the floor over the chunk border is shaded by the new block
+        assert_eq!(dirty, vec![ChunkPos(-1, 0, 0), ChunkPos(0, 0, 0)]);
+
+        // and the next one goes on top of it
+        let changed = look_down(&mut world, -0.5, BlockAction::Place(BlockType::DIRT));
+        assert_eq!(changed, Some(BlockPos::new(-1, 2, 4)));
+    }
+
+    #[test]
+    fn break_on_chunk_border_dirties_both_chunks() {
+        let mut world = floor_world();
+        look_down(&mut world, 0.5, BlockAction::Break);
+        let mut dirty = world.take_dirty_chunks();
+        dirty.sort_by_key(|pos| pos.0);
+        assert_eq!(dirty, vec![ChunkPos(-1, 0, 0), ChunkPos(0, 0, 0)]);
+    }
+
+    #[test]
+    fn nothing_to_target_changes_nothing() {
+        let mut world = floor_world();
+        let changed = edit_targeted_block(
+            &mut world, vec3(0.5, 3.5, 4.5), vec3(0.0, 1.0, 0.0), 6.0, BlockAction::Break, MISSING,
+        );
+        assert_eq!(changed, None);
+        assert_eq!(look_down(&mut world, 40.5, BlockAction::Break), None);
+        assert!(world.take_dirty_chunks().is_empty());
+    }
+
+    #[test]
+    fn nothing_is_placed_into_missing_chunks() {
+        let mut world = floor_world();
+        world.set_block(BlockPos::new(2, 15, 4), BlockState::STONE);
+        world.take_dirty_chunks();
+        let changed = edit_targeted_block(
+            &mut world, vec3(2.5, 18.5, 4.5), vec3(0.0, -1.0, 0.0), 6.0, BlockAction::Place(BlockType::SAND), MISSING,
+        );
+        assert_eq!(changed, None);
+        assert!(world.get_chunk(ChunkPos(0, 1, 0)).is_none());
+        assert!(world.take_dirty_chunks().is_empty());
+    }
+
+    #[test]
+    fn blocks_are_placed_into_water_under_it() {
+        let water = BlockState::new(crate::world::registry::BlockRegistry::global().by_name("water").unwrap());
+        let mut world = floor_world();
+        world.set_block(BlockPos::new(2, 1, 4), water.clone());
+        world.set_block(BlockPos::new(2, 2, 4), water);
+        // water is not broken, the floor under it is targeted
+        assert_eq!(look_down(&mut world, 2.5, BlockAction::Place(BlockType::SAND)), Some(BlockPos::new(2, 1, 4)));
+        assert_eq!(*world.get_block(BlockPos::new(2, 1, 4)), BlockState::SAND);
+        assert_eq!(look_down(&mut world, 2.5, BlockAction::Break), Some(BlockPos::new(2, 1, 4)));
+    }
+
+    #[test]
+    fn air_is_not_placed() {
+        let mut world = floor_world();
+        assert_eq!(look_down(&mut world, 2.5, BlockAction::Place(BlockType::AIR)), None);
+        assert!(world.take_dirty_chunks().is_empty());
+    }
+}
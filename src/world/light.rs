@@ -0,0 +1,655 @@
+use std::array::from_fn as arr_fn;
+use std::collections::{HashSet, VecDeque};
+
+use crate::world::palette::{block_index, PalettedBlocks, CHUNK_VOLUME};
+use crate::world::registry::BlockRegistry;
+use crate::world::render::BlockSide;
+use crate::world::{BlockPos, BlockType, Chunk, ChunkPos, World, CHUNK_SIZE_16};
+
+/// Sky and block light of one block, 0..=15 each
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct Light(u8);
+
+#[allow(dead_code)]
+impl Light {
+    pub const MAX: u8 = BlockRegistry::MAX_LIGHT;
+    pub const DARK: Light = Light(0);
+    /// Full sky light, the light of not loaded chunks
+    pub const SKY: Light = Light::new(Light::MAX, 0);
+
+    pub const fn new(sky: u8, block: u8) -> Self {
+        Self(sky << 4 | block)
+    }
+
+    pub const fn sky(&self) -> u8 {
+        self.0 >> 4
+    }
+
+    pub const fn block(&self) -> u8 {
+        self.0 & 0xf
+    }
+
+    /// The brighter of the two
+    pub fn level(&self) -> u8 {
+        self.sky().max(self.block())
+    }
+
+    fn get(&self, channel: Channel) -> u8 {
+        match channel {
+            Channel::Sky => self.sky(),
+            Channel::Block => self.block(),
+        }
+    }
+
+    fn with(self, channel: Channel, level: u8) -> Self {
+        match channel {
+            Channel::Sky => Self::new(level, self.block()),
+            Channel::Block => Self::new(self.sky(), level),
+        }
+    }
+}
+
+/// The two kinds of light are spread separately
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum Channel {
+    Sky,
+    Block,
+}
+
+impl Channel {
+    const ALL: [Channel; 2] = [Channel::Sky, Channel::Block];
+
+    /// Level the neighbour on `side` gets from a block lit with `level`.
+    /// Light fades by one per block, except full sky light going down
+    fn passed(&self, level: u8, side: BlockSide) -> u8 {
+        if *self == Channel::Sky && side == BlockSide::Ny && level == Light::MAX {
+            level
+        } else {
+            level.saturating_sub(1)
+        }
+    }
+}
+
+/// Light of every block of a chunk. A uniformly lit chunk (open air, solid stone) keeps one value
+#[derive(Debug, Clone)]
+pub enum ChunkLight {
+    Uniform(Light),
+    Levels(Box<[Light; CHUNK_VOLUME]>),
+}
+
+impl Default for ChunkLight {
+    fn default() -> Self {
+        Self::DARK
+    }
+}
+
+#[allow(dead_code)]
+impl ChunkLight {
+    pub const DARK: ChunkLight = ChunkLight::Uniform(Light::DARK);
+
+    /// Light spread from the blocks of `chunk` and from the borders of its neighbours.
+    /// Without a chunk above, the sky shines on the top of the chunk
+    pub fn compute(chunk: &Chunk, borders: &BorderLight) -> ChunkLight {
+        if let PalettedBlocks::Single(state) = &chunk.blocks {
+            if !state.block_type.lets_light_through() {
+                return ChunkLight::Uniform(Light::new(0, state.block_type.light_emission()));
+            }
+        }
+        let mut volume = ChunkVolume {
+            chunk,
+            levels: Box::new([Light::DARK; CHUNK_VOLUME]),
+            open_sky: borders[5].is_none(),
+        };
+        for channel in Channel::ALL {
+            let mut queue = VecDeque::new();
+            for (x, y, z) in chunk_cells() {
+                let pos = BlockPos::new(x as i32, y as i32, z as i32);
+                let block = chunk.get(x, y, z).block_type;
+                let level = source(&volume, pos, block, channel);
+                if level > 0 {
+                    volume.set(pos, volume.levels[block_index(x, y, z)].with(channel, level));
+                    queue.push_back(pos);
+                }
+            }
+            for side_idx in 0..6 {
+                let Some(layer) = &borders[side_idx] else { continue };
+                let side = BlockSide::from_position(side_idx);
+                for (x, y, z) in border_cells(side) {
+                    let (a, b) = border_coords(side, x, y, z);
+                    let level = channel.passed(layer.get(a, b).get(channel), side.opposite());
+                    let pos = BlockPos::new(x as i32, y as i32, z as i32);
+                    let light = volume.levels[block_index(x, y, z)];
+                    if chunk.get(x, y, z).block_type.lets_light_through() && light.get(channel) < level {
+                        volume.set(pos, light.with(channel, level));
+                        queue.push_back(pos);
+                    }
+                }
+            }
+            spread(&mut volume, channel, queue);
+        }
+        ChunkLight::from_levels(volume.levels)
+    }
+
+    fn from_levels(levels: Box<[Light; CHUNK_VOLUME]>) -> ChunkLight {
+        let first = levels[0];
+        if levels.iter().all(|light| *light == first) {
+            ChunkLight::Uniform(first)
+        } else {
+            ChunkLight::Levels(levels)
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> Light {
+        match self {
+            ChunkLight::Uniform(light) => *light,
+            ChunkLight::Levels(levels) => levels[block_index(x, y, z)],
+        }
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, z: usize, light: Light) {
+        match self {
+            ChunkLight::Uniform(uniform) if *uniform == light => {}
+            ChunkLight::Uniform(uniform) => {
+                let mut levels = Box::new([*uniform; CHUNK_VOLUME]);
+                levels[block_index(x, y, z)] = light;
+                *self = ChunkLight::Levels(levels);
+            }
+            ChunkLight::Levels(levels) => levels[block_index(x, y, z)] = light,
+        }
+    }
+
+    /// In the `palette::block_index` order
+    pub fn iter(&self) -> impl Iterator<Item = Light> + '_ {
+        (0..CHUNK_VOLUME).map(move |i| match self {
+            ChunkLight::Uniform(light) => *light,
+            ChunkLight::Levels(levels) => levels[i],
+        })
+    }
+}
+
+/// Same light in every block, however it is stored
+impl PartialEq for ChunkLight {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Uniform(a), Self::Uniform(b)) => a == b,
+            _ => self.iter().eq(other.iter()),
+        }
+    }
+}
+
+impl Eq for ChunkLight {}
+
+/// Light of the blocks on a chunk border, indexed like `ChunkLayer`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LightLayer(pub [[Light; CHUNK_SIZE_16]; CHUNK_SIZE_16]);
+
+impl LightLayer {
+    pub fn from_fn(mut func: impl FnMut(usize, usize) -> Light) -> Self {
+        Self(arr_fn(|a| arr_fn(|b| func(a, b))))
+    }
+
+    pub fn get(&self, a: usize, b: usize) -> Light {
+        self.0[a][b]
+    }
+}
+
+/// Border light of the six neighbour chunks in `RenderCubeByte` bits order, `None` if not loaded
+pub type BorderLight = [Option<LightLayer>; 6];
+
+/// No neighbours at all, the sky is open above
+#[allow(dead_code)]
+pub const NO_BORDER_LIGHT: BorderLight = [const { None }; 6];
+
+/// `neighbours` in `RenderCubeByte` bits order, like `model::neighbour_border_layers`
+pub fn neighbour_border_light(neighbours: [Option<&Chunk>; 6]) -> BorderLight {
+    arr_fn(|pos| neighbours[pos].map(|chunk| chunk.border_light(BlockSide::from_position(pos).opposite())))
+}
+
+/// Where the block at `x, y, z` on the `side` border of a chunk
+/// is in the border layer of the neighbour chunk on that side
+pub const fn border_coords(side: BlockSide, x: usize, y: usize, z: usize) -> (usize, usize) {
+    match side {
+        BlockSide::Py | BlockSide::Ny => (x, z),
+        BlockSide::Px | BlockSide::Nx => (y, z),
+        BlockSide::Pz | BlockSide::Nz => (x, y),
+    }
+}
+
+/// Fixes the light after the block at `pos` changed, returns the blocks whose light changed
+pub fn update_block_light(world: &mut World, pos: BlockPos) -> Vec<BlockPos> {
+    let mut volume = WorldVolume { world, changed: HashSet::new() };
+    for channel in Channel::ALL {
+        let Some((_, light)) = volume.get(pos) else {
+            return vec![];
+        };
+        let level = light.get(channel);
+        if level > 0 {
+            volume.set(pos, light.with(channel, 0));
+        }
+        let relight = unspread(&mut volume, channel, vec![(pos, level)]);
+        spread(&mut volume, channel, relight);
+    }
+    volume.changed.into_iter().collect()
+}
+
+/// Joins the light of the chunk at `pos`, just added to the world and lit with the `lit_with`
+/// border light, with the chunks around it. Returns the blocks whose light changed, in that chunk and in the others
+pub fn settle_chunk_light(world: &mut World, pos: ChunkPos, lit_with: &BorderLight) -> Vec<BlockPos> {
+    let neighbours = arr_fn(|side_idx| world.get_chunk(pos.neighbour(BlockSide::from_position(side_idx))));
+    let borders = neighbour_border_light(neighbours);
+    let Some(chunk) = world.get_chunk(pos) else {
+        return vec![];
+    };
+    let origin = pos.origin();
+    let mut changed = HashSet::new();
+    // neighbours changed since the chunk was lit
+    if borders != *lit_with {
+        let light = ChunkLight::compute(chunk, &borders);
+        if light != chunk.light {
+            changed.extend(chunk_cells().map(|(x, y, z)| origin + BlockPos::new(x as i32, y as i32, z as i32)));
+            world.get_chunk_mut(pos).unwrap().light = light;
+        }
+    }
+
+    // the light coming in over the borders is in the chunk light already, the light going out is not
+    let chunk = world.get_chunk(pos).unwrap();
+    let mut outgoing = Channel::ALL.map(|_| VecDeque::new());
+    for side_idx in 0..6 {
+        let Some(layer) = &borders[side_idx] else { continue };
+        let side = BlockSide::from_position(side_idx);
+        for (x, y, z) in border_cells(side) {
+            let (a, b) = border_coords(side, x, y, z);
+            let (inside, outside) = (chunk.light.get(x, y, z), layer.get(a, b));
+            for (channel, queue) in Channel::ALL.into_iter().zip(&mut outgoing) {
+                if channel.passed(inside.get(channel), side) > outside.get(channel) {
+                    queue.push_back(origin + BlockPos::new(x as i32, y as i32, z as i32));
+                }
+            }
+        }
+    }
+
+    let mut volume = WorldVolume { world, changed };
+    // the chunk below took the sky over it for open
+    let mut covered = vec![];
+    if let Some(below_top) = &borders[4] {
+        let below = pos.neighbour(BlockSide::Ny).origin();
+        for (x, _, z) in border_cells(BlockSide::Ny) {
+            let top_light = below_top.get(x, z);
+            let bottom_light = volume.get(origin + BlockPos::new(x as i32, 0, z as i32)).unwrap().1;
+            if top_light.sky() == Light::MAX && bottom_light.sky() < Light::MAX {
+                let top = below + BlockPos::new(x as i32, CHUNK_SIZE_16 as i32 - 1, z as i32);
+                volume.set(top, top_light.with(Channel::Sky, 0));
+                covered.push((top, Light::MAX));
+            }
+        }
+    }
+    let [outgoing_sky, outgoing_block] = outgoing;
+    let mut relight = unspread(&mut volume, Channel::Sky, covered);
+    relight.extend(outgoing_sky);
+    spread(&mut volume, Channel::Sky, relight);
+    spread(&mut volume, Channel::Block, outgoing_block);
+    volume.changed.into_iter().collect()
+}
+
+/// Blocks with their light the flood fill goes through
+trait LightVolume {
+    /// `None` where no light is kept: not loaded chunks, outside of the volume
+    fn get(&self, pos: BlockPos) -> Option<(BlockType, Light)>;
+    fn set(&mut self, pos: BlockPos, light: Light);
+    /// Sky light falls into `pos` from nothing above it
+    fn is_under_open_sky(&self, pos: BlockPos) -> bool;
+}
+
+/// Light the block gives by itself
+fn source(volume: &impl LightVolume, pos: BlockPos, block: BlockType, channel: Channel) -> u8 {
+    match channel {
+        Channel::Block => block.light_emission(),
+        Channel::Sky if block.lets_light_through() && volume.is_under_open_sky(pos) => Light::MAX,
+        Channel::Sky => 0,
+    }
+}
+
+/// Light of the queued blocks goes on to every darker neighbour that lets light through
+fn spread(volume: &mut impl LightVolume, channel: Channel, mut queue: VecDeque<BlockPos>) {
+    while let Some(pos) = queue.pop_front() {
+        let Some((_, light)) = volume.get(pos) else { continue };
+        let level = light.get(channel);
+        for side_idx in 0..6 {
+            let side = BlockSide::from_position(side_idx);
+            let passed = channel.passed(level, side);
+            if passed == 0 {
+                continue;
+            }
+            let next = pos + side.offset();
+            match volume.get(next) {
+                Some((block, next_light)) if block.lets_light_through() && next_light.get(channel) < passed => {
+                    volume.set(next, next_light.with(channel, passed));
+                    queue.push_back(next);
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// `seeds` lost their light, the levels they had are given and they are dark already.
+/// Darkens everything lit through them, returns the blocks to spread light from again:
+/// the lit edge of the dark area and the sources inside it
+fn unspread(volume: &mut impl LightVolume, channel: Channel, seeds: Vec<(BlockPos, u8)>) -> VecDeque<BlockPos> {
+    let mut queue = VecDeque::from(seeds);
+    let mut relight = VecDeque::new();
+    while let Some((pos, level)) = queue.pop_front() {
+        if let Some((block, light)) = volume.get(pos) {
+            let source = source(volume, pos, block, channel);
+            if source > 0 {
+                volume.set(pos, light.with(channel, source));
+                relight.push_back(pos);
+            }
+        }
+        for side_idx in 0..6 {
+            let side = BlockSide::from_position(side_idx);
+            let next = pos + side.offset();
+            let Some((_, next_light)) = volume.get(next) else { continue };
+            let next_level = next_light.get(channel);
+            if next_level == 0 {
+                continue;
+            }
+            let lit_through = next_level < level || next_level == channel.passed(level, side);
+            if lit_through {
+                volume.set(next, next_light.with(channel, 0));
+                queue.push_back((next, next_level));
+            } else {
+                relight.push_back(next);
+            }
+        }
+    }
+    relight
+}
+
+/// One chunk on its own, positions are local to it
+struct ChunkVolume<'a> {
+    chunk: &'a Chunk,
+    levels: Box<[Light; CHUNK_VOLUME]>,
+    open_sky: bool,
+}
+
+impl LightVolume for ChunkVolume<'_> {
+    fn get(&self, pos: BlockPos) -> Option<(BlockType, Light)> {
+        let range = 0..CHUNK_SIZE_16 as i32;
+        if !(range.contains(&pos.x) && range.contains(&pos.y) && range.contains(&pos.z)) {
+            return None;
+        }
+        let (x, y, z) = (pos.x as usize, pos.y as usize, pos.z as usize);
+        Some((self.chunk.get(x, y, z).block_type, self.levels[block_index(x, y, z)]))
+    }
+
+    fn set(&mut self, pos: BlockPos, light: Light) {
+        self.levels[block_index(pos.x as usize, pos.y as usize, pos.z as usize)] = light;
+    }
+
+    fn is_under_open_sky(&self, pos: BlockPos) -> bool {
+        self.open_sky && pos.y == CHUNK_SIZE_16 as i32 - 1
+    }
+}
+
+/// Loaded chunks, remembers the blocks it changed the light of
+struct WorldVolume<'a> {
+    world: &'a mut World,
+    changed: HashSet<BlockPos>,
+}
+
+impl LightVolume for WorldVolume<'_> {
+    fn get(&self, pos: BlockPos) -> Option<(BlockType, Light)> {
+        let chunk = self.world.get_chunk(pos.chunk_pos())?;
+        let (x, y, z) = pos.in_chunk();
+        Some((chunk.get(x, y, z).block_type, chunk.light.get(x, y, z)))
+    }
+
+    fn set(&mut self, pos: BlockPos, light: Light) {
+        if let Some(chunk) = self.world.get_chunk_mut(pos.chunk_pos()) {
+            let (x, y, z) = pos.in_chunk();
+            chunk.light.set(x, y, z, light);
+            self.changed.insert(pos);
+        }
+    }
+
+    fn is_under_open_sky(&self, pos: BlockPos) -> bool {
+        pos.in_chunk().1 == CHUNK_SIZE_16 - 1 && self.world.get_chunk(pos.chunk_pos().neighbour(BlockSide::Py)).is_none()
+    }
+}
+
+fn chunk_cells() -> impl Iterator<Item = (usize, usize, usize)> {
+    (0..CHUNK_VOLUME).map(|i| (i / CHUNK_SIZE_16 % CHUNK_SIZE_16, i / (CHUNK_SIZE_16 * CHUNK_SIZE_16), i % CHUNK_SIZE_16))
+}
+
+/// Blocks of a chunk on its `side` border, local to the chunk
+fn border_cells(side: BlockSide) -> impl Iterator<Item = (usize, usize, usize)> {
+    const LAST: usize = CHUNK_SIZE_16 - 1;
+    (0..CHUNK_SIZE_16 * CHUNK_SIZE_16).map(move |i| {
+        let (a, b) = (i / CHUNK_SIZE_16, i % CHUNK_SIZE_16);
+        match side {
+            BlockSide::Py => (a, LAST, b),
+            BlockSide::Ny => (a, 0, b),
+            BlockSide::Px => (LAST, a, b),
+            BlockSide::Nx => (0, a, b),
+            BlockSide::Pz => (a, b, LAST),
+            BlockSide::Nz => (a, b, 0),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ::rand::rngs::StdRng;
+    use ::rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::world::BlockState;
+
+    fn glowstone() -> BlockState {
+        BlockState::new(BlockRegistry::global().by_name("glowstone").unwrap())
+    }
+
+    /// Dark neighbours all around, no sky
+    fn dark_borders() -> BorderLight {
+        arr_fn(|_| Some(LightLayer::from_fn(|_, _| Light::DARK)))
+    }
+
+    fn local(x: i32, y: i32, z: i32) -> BlockPos {
+        BlockPos::new(x, y, z)
+    }
+
+    #[test]
+    fn light_falls_off_around_glowstone() {
+        let mut chunk = Chunk::EMPTY;
+        chunk.set(8, 8, 8, glowstone());
+        let light = ChunkLight::compute(&chunk, &dark_borders());
+        assert_eq!(light.get(8, 8, 8), Light::new(0, 15));
+        assert_eq!(light.get(9, 8, 8), Light::new(0, 14));
+        assert_eq!(light.get(8, 12, 8), Light::new(0, 11));
+        assert_eq!(light.get(12, 12, 8), Light::new(0, 7));
+        assert_eq!(light.get(3, 3, 3), Light::new(0, 0));
+        assert_eq!(light.get(0, 0, 0), Light::DARK);
+    }
+
+    #[test]
+    fn opaque_blocks_stop_light() {
+        let mut chunk = Chunk::from_fn(|x, _, _| if x == 10 { BlockState::STONE } else { BlockState::AIR });
+        chunk.set(8, 8, 8, glowstone());
+        let light = ChunkLight::compute(&chunk, &dark_borders());
+        assert_eq!(light.get(9, 8, 8).block(), 14);
+        assert_eq!(light.get(10, 8, 8).block(), 0);
+        assert_eq!(light.get(11, 8, 8).block(), 0);
+        // glass lets it through
+        chunk.set(10, 8, 8, BlockState::new(BlockRegistry::global().by_name("glass").unwrap()));
+        let light = ChunkLight::compute(&chunk, &dark_borders());
+        assert_eq!(light.get(11, 8, 8).block(), 12);
+    }
+
+    #[test]
+    fn sky_light_falls_straight_down_and_fades_under_roofs() {
+        // a roof at y = 10 over x < 8
+        let chunk = Chunk::from_fn(|x, y, _| if y == 10 && x < 8 { BlockState::STONE } else { BlockState::AIR });
+        let light = ChunkLight::compute(&chunk, &NO_BORDER_LIGHT);
+        assert_eq!(light.get(3, 12, 3), Light::SKY);
+        assert_eq!(light.get(8, 0, 3), Light::SKY);
+        assert_eq!(light.get(7, 5, 3).sky(), 14);
+        assert_eq!(light.get(3, 5, 3).sky(), 10);
+        assert_eq!(light.get(3, 10, 3), Light::DARK);
+
+        let solid = Chunk::from_fn(|_, _, _| BlockState::STONE);
+        assert_eq!(ChunkLight::compute(&solid, &NO_BORDER_LIGHT), ChunkLight::DARK);
+        assert!(matches!(ChunkLight::compute(&Chunk::EMPTY, &NO_BORDER_LIGHT), ChunkLight::Uniform(Light::SKY)));
+    }
+
+    #[test]
+    fn neighbour_border_light_comes_in() {
+        let mut borders = dark_borders();
+        // px layer is indexed (y, z)
+        borders[3] = Some(LightLayer::from_fn(|y, z| if (y, z) == (4, 6) { Light::new(0, 10) } else { Light::DARK }));
+        let light = ChunkLight::compute(&Chunk::EMPTY, &borders);
+        assert_eq!(light.get(15, 4, 6).block(), 9);
+        assert_eq!(light.get(13, 4, 6).block(), 7);
+        assert_eq!(light.get(15, 5, 6).block(), 8);
+    }
+
+    /// Stone with an air tunnel along x at y = 4, z = 4
+    fn tunnel_chunk() -> Chunk {
+        Chunk::from_fn(|_, y, z| if (y, z) == (4, 4) { BlockState::AIR } else { BlockState::STONE })
+    }
+
+    /// Lights the chunk with the current neighbours and adds it, like chunk streaming does
+    fn add_chunk(world: &mut World, pos: ChunkPos, mut chunk: Chunk) -> Vec<BlockPos> {
+        let neighbours = arr_fn(|side_idx| world.get_chunk(pos.neighbour(BlockSide::from_position(side_idx))));
+        let borders = neighbour_border_light(neighbours);
+        chunk.light = ChunkLight::compute(&chunk, &borders);
+        world.insert_chunk(pos, chunk);
+        settle_chunk_light(world, pos, &borders)
+    }
+
+    #[test]
+    fn light_crosses_chunk_borders() {
+        let mut world = World::new();
+        assert!(add_chunk(&mut world, ChunkPos(1, 0, 0), tunnel_chunk()).is_empty());
+        let mut lamp = tunnel_chunk();
+        lamp.set(12, 4, 4, glowstone());
+        let relit = add_chunk(&mut world, ChunkPos(0, 0, 0), lamp);
+        // the light goes on into the chunk that was there
+        assert!(relit.contains(&local(16, 4, 4)));
+        assert!(!relit.contains(&local(12, 4, 4)));
+        assert_eq!(world.get_light(local(20, 4, 4)).block(), 7);
+
+        world.set_block(local(14, 4, 4), BlockState::STONE);
+        let relit = update_block_light(&mut world, local(14, 4, 4));
+        assert!(relit.contains(&local(20, 4, 4)));
+        assert_eq!(world.get_light(local(13, 4, 4)).block(), 14);
+        assert_eq!(world.get_light(local(20, 4, 4)).block(), 0);
+
+        world.set_block(local(14, 4, 4), BlockState::AIR);
+        update_block_light(&mut world, local(14, 4, 4));
+        assert_eq!(world.get_light(local(20, 4, 4)).block(), 7);
+
+        world.set_block(local(12, 4, 4), BlockState::AIR);
+        update_block_light(&mut world, local(12, 4, 4));
+        assert_eq!(world.get_light(local(12, 4, 4)), Light::DARK);
+        assert_eq!(world.get_light(local(20, 4, 4)), Light::DARK);
+    }
+
+    #[test]
+    fn chunk_above_covers_the_sky() {
+        let mut world = World::new();
+        add_chunk(&mut world, ChunkPos(0, 0, 0), Chunk::EMPTY);
+        assert_eq!(world.get_light(local(3, 0, 3)), Light::SKY);
+        // a roof with one hole
+        let mut roof = Chunk::from_fn(|_, y, _| if y == 0 { BlockState::STONE } else { BlockState::AIR });
+        roof.set(3, 0, 3, BlockState::AIR);
+        add_chunk(&mut world, ChunkPos(0, 1, 0), roof);
+        assert_eq!(world.get_light(local(3, 0, 3)), Light::SKY);
+        assert_eq!(world.get_light(local(5, 0, 3)).sky(), 13);
+        assert_eq!(world.get_light(local(3, 15, 6)).sky(), 12);
+    }
+
+    /// Light by its definition: every block as bright as it shines itself or as its neighbours light it,
+    /// relaxed until nothing changes
+    fn flood_by_definition(world: &World) -> HashMap<BlockPos, Light> {
+        let positions: Vec<BlockPos> = world
+            .chunk_positions()
+            .flat_map(|pos| chunk_cells().map(move |(x, y, z)| pos.origin() + local(x as i32, y as i32, z as i32)))
+            .collect();
+        let mut light: HashMap<BlockPos, Light> = positions.iter().map(|&pos| (pos, Light::DARK)).collect();
+        loop {
+            let mut changed = false;
+            for &pos in &positions {
+                let block = world.get_block(pos).block_type;
+                let open_sky = pos.in_chunk().1 == CHUNK_SIZE_16 - 1
+                    && world.get_chunk(pos.chunk_pos().neighbour(BlockSide::Py)).is_none();
+                let mut sky = if block.lets_light_through() && open_sky { Light::MAX } else { 0 };
+                let mut from_blocks = block.light_emission();
+                if block.lets_light_through() {
+                    for side_idx in 0..6 {
+                        let side = BlockSide::from_position(side_idx);
+                        let Some(neighbour) = light.get(&(pos + side.offset())) else { continue };
+                        sky = sky.max(Channel::Sky.passed(neighbour.sky(), side.opposite()));
+                        from_blocks = from_blocks.max(Channel::Block.passed(neighbour.block(), side.opposite()));
+                    }
+                }
+                let new = Light::new(sky, from_blocks);
+                if light[&pos] != new {
+                    light.insert(pos, new);
+                    changed = true;
+                }
+            }
+            if !changed {
+                return light;
+            }
+        }
+    }
+
+    fn assert_light_matches_definition(world: &World) {
+        for (pos, light) in flood_by_definition(world) {
+            assert_eq!(world.get_light(pos), light, "at {:?}", pos);
+        }
+    }
+
+    fn random_block(rng: &mut StdRng, stone_chance: f64) -> BlockState {
+        match rng.gen_range(0.0..1.0) {
+            roll if roll < 0.01 => glowstone(),
+            roll if roll < 0.05 => BlockState::new(BlockRegistry::global().by_name("glass").unwrap()),
+            roll if roll < 0.05 + stone_chance => BlockState::STONE,
+            _ => BlockState::AIR,
+        }
+    }
+
+    #[test]
+    fn incremental_light_matches_definition() {
+        let mut rng = StdRng::seed_from_u64(18);
+        let mut positions = vec![ChunkPos(0, 0, 0), ChunkPos(0, 1, 0), ChunkPos(0, 0, 1), ChunkPos(0, 1, 1)];
+        // chunks come in any order, some lit before their neighbours came
+        for i in (1..positions.len()).rev() {
+            positions.swap(i, rng.gen_range(0..=i));
+        }
+        let mut world = World::new();
+        for pos in positions {
+            let stone_chance = if pos.1 == 0 { 0.6 } else { 0.15 };
+            let mut chunk = Chunk::from_fn(|_, _, _| random_block(&mut rng, stone_chance));
+            if rng.gen_bool(0.5) {
+                add_chunk(&mut world, pos, chunk);
+            } else {
+                chunk.light = ChunkLight::compute(&chunk, &NO_BORDER_LIGHT);
+                world.insert_chunk(pos, chunk);
+                settle_chunk_light(&mut world, pos, &NO_BORDER_LIGHT);
+            }
+        }
+        assert_light_matches_definition(&world);
+
+        for _ in 0..200 {
+            let pos = local(rng.gen_range(0..16), rng.gen_range(0..32), rng.gen_range(0..32));
+            let state = if world.get_block(pos).is_empty() { random_block(&mut rng, 1.0) } else { BlockState::AIR };
+            world.set_block(pos, state);
+            update_block_light(&mut world, pos);
+        }
+        assert_light_matches_definition(&world);
+    }
+}
@@ -0,0 +1,415 @@
+use std::mem::size_of;
+
+use crate::world::{BlockState, CHUNK_SIZE_16};
+
+pub const CHUNK_VOLUME: usize = CHUNK_SIZE_16 * CHUNK_SIZE_16 * CHUNK_SIZE_16;
+
+/// Position of a block in the chunk storage, blocks go in y, x, z order
+pub const fn block_index(x: usize, y: usize, z: usize) -> usize {
+    (y * CHUNK_SIZE_16 + x) * CHUNK_SIZE_16 + z
+}
+
+/// Blocks of a chunk. A uniform chunk (all air, all stone) keeps a single state,
+/// others keep the different states in a palette and a bit packed palette index per block
+#[derive(Debug, Clone)]
+pub enum PalettedBlocks {
+    Single(BlockState),
+    Packed(PackedBlocks),
+}
+
+impl Default for PalettedBlocks {
+    fn default() -> Self {
+        Self::EMPTY
+    }
+}
+
+#[allow(dead_code)]
+impl PalettedBlocks {
+    pub const EMPTY: PalettedBlocks = PalettedBlocks::Single(BlockState::AIR);
+
+    /// (usize, usize, usize) - pos in chunk 0..16
+    pub fn from_fn(mut func: impl FnMut(usize, usize, usize) -> BlockState) -> Self {
+        let mut palette: Vec<BlockState> = Vec::new();
+        let mut counts: Vec<u16> = Vec::new();
+        let mut indices = Vec::with_capacity(CHUNK_VOLUME);
+        for y in 0..CHUNK_SIZE_16 {
+            for x in 0..CHUNK_SIZE_16 {
+                for z in 0..CHUNK_SIZE_16 {
+                    let state = func(x, y, z);
+                    let index = match palette.iter().position(|known| *known == state) {
+                        Some(index) => index,
+                        None => {
+                            palette.push(state);
+                            counts.push(0);
+                            palette.len() - 1
+                        }
+                    };
+                    counts[index] += 1;
+                    indices.push(index);
+                }
+            }
+        }
+        if palette.len() == 1 {
+            return Self::Single(palette.pop().unwrap());
+        }
+        let bits = bits_for(palette.len());
+        let mut packed = PackedBlocks::with_bits(palette, counts, bits);
+        for (i, index) in indices.into_iter().enumerate() {
+            packed.set_index(i, index);
+        }
+        Self::Packed(packed)
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> &BlockState {
+        let i = block_index(x, y, z);
+        match self {
+            Self::Single(state) => state,
+            Self::Packed(packed) => &packed.palette[packed.index(i)],
+        }
+    }
+
+    /// Switches to the packed form on the first different block
+    /// and back to a single state once all blocks are the same again
+    pub fn set(&mut self, x: usize, y: usize, z: usize, state: BlockState) {
+        let i = block_index(x, y, z);
+        match self {
+            Self::Single(current) => {
+                if *current != state {
+                    let mut packed = PackedBlocks::uniform(current.clone());
+                    packed.set(i, state);
+                    *self = Self::Packed(packed);
+                }
+            }
+            Self::Packed(packed) => {
+                packed.set(i, state);
+                if let Some(state) = packed.uniform_state() {
+                    *self = Self::Single(state.clone());
+                }
+            }
+        }
+    }
+
+    pub fn is_single(&self) -> bool {
+        matches!(self, Self::Single(_))
+    }
+
+    /// All blocks in y, x, z order
+    pub fn iter(&self) -> impl Iterator<Item = &BlockState> + '_ {
+        (0..CHUNK_VOLUME).map(move |i| match self {
+            Self::Single(state) => state,
+            Self::Packed(packed) => &packed.palette[packed.index(i)],
+        })
+    }
+
+    /// Bytes taken on the heap, the enum itself not included
+    pub fn heap_size(&self) -> usize {
+        match self {
+            Self::Single(_) => 0,
+            Self::Packed(packed) => {
+                packed.palette.capacity() * size_of::<BlockState>()
+                    + packed.counts.capacity() * size_of::<u16>()
+                    + packed.words.capacity() * size_of::<u64>()
+            }
+        }
+    }
+}
+
+/// Compares blocks, not how they are stored
+impl PartialEq for PalettedBlocks {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Single(a), Self::Single(b)) => a == b,
+            _ => self.iter().eq(other.iter()),
+        }
+    }
+}
+
+impl Eq for PalettedBlocks {}
+
+/// Palette indices of `bits` bits each, packed into u64 words without crossing
+/// word boundaries. Palette entries no block uses anymore are reused by new states
+#[derive(Debug, Clone)]
+pub struct PackedBlocks {
+    palette: Vec<BlockState>,
+    /// How many blocks use each palette entry
+    counts: Vec<u16>,
+    bits: usize,
+    words: Vec<u64>,
+}
+
+impl PackedBlocks {
+    fn with_bits(palette: Vec<BlockState>, counts: Vec<u16>, bits: usize) -> Self {
+        Self { palette, counts, bits, words: vec![0; word_count(bits)] }
+    }
+
+    fn uniform(state: BlockState) -> Self {
+        Self::with_bits(vec![state], vec![CHUNK_VOLUME as u16], 1)
+    }
+
+    fn index(&self, i: usize) -> usize {
+        let per_word = 64 / self.bits;
+        let shift = (i % per_word) * self.bits;
+        ((self.words[i / per_word] >> shift) & self.mask()) as usize
+    }
+
+    fn set_index(&mut self, i: usize, index: usize) {
+        let per_word = 64 / self.bits;
+        let shift = (i % per_word) * self.bits;
+        let mask = self.mask() << shift;
+        let word = &mut self.words[i / per_word];
+        *word = (*word & !mask) | ((index as u64) << shift);
+    }
+
+    fn mask(&self) -> u64 {
+        (1 << self.bits) - 1
+    }
+
+    fn set(&mut self, i: usize, state: BlockState) {
+        let old = self.index(i);
+        if self.palette[old] == state {
+            return;
+        }
+        self.counts[old] -= 1;
+        let new = self.palette_index(state);
+        self.counts[new] += 1;
+        self.set_index(i, new);
+    }
+
+    /// Index of `state` in the palette, added if missing
+    fn palette_index(&mut self, state: BlockState) -> usize {
+        if let Some(index) = self.palette.iter().position(|known| *known == state) {
+            return index;
+        }
+        if let Some(index) = self.counts.iter().position(|count| *count == 0) {
+            self.palette[index] = state;
+            return index;
+        }
+        self.palette.push(state);
+        self.counts.push(0);
+        if self.palette.len() > 1 << self.bits {
+            self.repack(bits_for(self.palette.len()));
+        }
+        self.palette.len() - 1
+    }
+
+    fn repack(&mut self, bits: usize) {
+        let old = std::mem::replace(self, Self::with_bits(Vec::new(), Vec::new(), bits));
+        for i in 0..CHUNK_VOLUME {
+            self.set_index(i, old.index(i));
+        }
+        self.palette = old.palette;
+        self.counts = old.counts;
+    }
+
+    fn uniform_state(&self) -> Option<&BlockState> {
+        let index = self.counts.iter().position(|count| *count as usize == CHUNK_VOLUME)?;
+        Some(&self.palette[index])
+    }
+}
+
+/// Bits to tell `len` palette entries apart, at least 1
+fn bits_for(len: usize) -> usize {
+    (usize::BITS - len.saturating_sub(1).leading_zeros()).max(1) as usize
+}
+
+fn word_count(bits: usize) -> usize {
+    CHUNK_VOLUME.div_ceil(64 / bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use ::rand::rngs::StdRng;
+    use ::rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::world::terrain::TerrainGenerator;
+    use crate::world::{BlockType, ChunkLayer, ChunkPos};
+
+    const ALL_TYPES: [BlockType; 6] = [
+        BlockType::AIR,
+        BlockType::DIRT,
+        BlockType::GRASS,
+        BlockType::STONE,
+        BlockType::SAND,
+        BlockType::TILE,
+    ];
+
+    pub(super) fn terrain_blocks() -> PalettedBlocks {
+        TerrainGenerator::new(1).generate_chunk(ChunkPos(0, 0, 0)).blocks
+    }
+
+    #[test]
+    fn bits_grow_with_palette() {
+        assert_eq!(bits_for(1), 1);
+        assert_eq!(bits_for(2), 1);
+        assert_eq!(bits_for(3), 2);
+        assert_eq!(bits_for(4), 2);
+        assert_eq!(bits_for(5), 3);
+        assert_eq!(bits_for(256), 8);
+        assert_eq!(word_count(1), 64);
+        assert_eq!(word_count(3), 196);
+    }
+
+    #[test]
+    fn uniform_chunks_stay_single() {
+        let mut blocks = PalettedBlocks::from_fn(|_, _, _| BlockState::STONE);
+        assert!(blocks.is_single());
+        assert_eq!(blocks.heap_size(), 0);
+        blocks.set(1, 2, 3, BlockState::STONE);
+        assert!(blocks.is_single());
+        assert_eq!(*blocks.get(15, 15, 15), BlockState::STONE);
+    }
+
+    #[test]
+    fn set_switches_between_single_and_packed() {
+        let mut blocks = PalettedBlocks::EMPTY;
+        blocks.set(1, 2, 3, BlockState::STONE);
+        assert!(!blocks.is_single());
+        assert_eq!(*blocks.get(1, 2, 3), BlockState::STONE);
+        assert_eq!(*blocks.get(3, 2, 1), BlockState::AIR);
+        blocks.set(1, 2, 3, BlockState::AIR);
+        assert!(blocks.is_single());
+        assert_eq!(blocks, PalettedBlocks::EMPTY);
+    }
+
+    #[test]
+    fn matches_arrays_under_random_edits() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut blocks = terrain_blocks();
+        let mut arrays: [ChunkLayer; CHUNK_SIZE_16] =
+            std::array::from_fn(|y| ChunkLayer::from_fn(|x, z| blocks.get(x, y, z).clone()));
+        for _ in 0..20_000 {
+            let (x, y, z) = (rng.gen_range(0..16), rng.gen_range(0..16), rng.gen_range(0..16));
+            let state = BlockState::new(ALL_TYPES[rng.gen_range(0..ALL_TYPES.len())]);
+            blocks.set(x, y, z, state.clone());
+            *arrays[y].get_mut(x, z) = state;
+        }
+        for y in 0..CHUNK_SIZE_16 {
+            for x in 0..CHUNK_SIZE_16 {
+                for z in 0..CHUNK_SIZE_16 {
+                    assert_eq!(blocks.get(x, y, z), arrays[y].get(x, z));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn freed_palette_entries_are_reused() {
+        let mut blocks = PalettedBlocks::EMPTY;
+        for block_type in &ALL_TYPES[1..] {
+            blocks.set(0, 0, 0, BlockState::new(*block_type));
+        }
+        assert_eq!(*blocks.get(0, 0, 0), BlockState::TILE);
+        let PalettedBlocks::Packed(packed) = &blocks else { panic!("expected packed blocks") };
+        assert_eq!(packed.palette.len(), 2);
+        assert_eq!(packed.bits, 1);
+    }
+
+    #[test]
+    fn equality_ignores_storage() {
+        let single = PalettedBlocks::Single(BlockState::DIRT);
+        let mut packed = single.clone();
+        packed.set(0, 0, 0, BlockState::STONE);
+        packed.set(0, 0, 0, BlockState::SAND);
+        assert_ne!(single, packed);
+        packed.set(0, 0, 0, BlockState::DIRT);
+        assert_eq!(single, packed);
+        assert_eq!(terrain_blocks(), terrain_blocks());
+    }
+
+    #[test]
+    fn terrain_takes_less_memory_than_arrays() {
+        let blocks = terrain_blocks();
+        let arrays = size_of::<[ChunkLayer; CHUNK_SIZE_16]>();
+        assert!(size_of::<PalettedBlocks>() + blocks.heap_size() < arrays / 2);
+    }
+}
+
+/// `cargo bench` against the `[ChunkLayer; 16]` arrays the blocks were kept in before
+#[cfg(test)]
+mod benches {
+    use test::{black_box, Bencher};
+
+    use super::tests::terrain_blocks;
+    use super::*;
+    use crate::world::ChunkLayer;
+
+    type Arrays = [ChunkLayer; CHUNK_SIZE_16];
+
+    fn terrain_arrays() -> Arrays {
+        let blocks = terrain_blocks();
+        std::array::from_fn(|y| ChunkLayer::from_fn(|x, z| blocks.get(x, y, z).clone()))
+    }
+
+    #[bench]
+    fn get_all_paletted(b: &mut Bencher) {
+        let blocks = terrain_blocks();
+        b.iter(|| {
+            let mut solid = 0;
+            for y in 0..CHUNK_SIZE_16 {
+                for x in 0..CHUNK_SIZE_16 {
+                    for z in 0..CHUNK_SIZE_16 {
+                        solid += !black_box(&blocks).get(x, y, z).is_empty() as usize;
+                    }
+                }
+            }
+            solid
+        });
+    }
+
+    #[bench]
+    fn get_all_arrays(b: &mut Bencher) {
+        let arrays = terrain_arrays();
+        b.iter(|| {
+            let mut solid = 0;
+            for y in 0..CHUNK_SIZE_16 {
+                for x in 0..CHUNK_SIZE_16 {
+                    for z in 0..CHUNK_SIZE_16 {
+                        solid += !black_box(&arrays)[y].get(x, z).is_empty() as usize;
+                    }
+                }
+            }
+            solid
+        });
+    }
+
+    #[bench]
+    fn set_layer_paletted(b: &mut Bencher) {
+        let mut blocks = terrain_blocks();
+        b.iter(|| {
+            for x in 0..CHUNK_SIZE_16 {
+                for z in 0..CHUNK_SIZE_16 {
+                    blocks.set(x, 8, z, BlockState::TILE);
+                    blocks.set(x, 8, z, BlockState::STONE);
+                }
+            }
+        });
+    }
+
+    #[bench]
+    fn set_layer_arrays(b: &mut Bencher) {
+        let mut arrays = terrain_arrays();
+        b.iter(|| {
+            for x in 0..CHUNK_SIZE_16 {
+                for z in 0..CHUNK_SIZE_16 {
+                    *black_box(&mut arrays)[8].get_mut(x, z) = BlockState::TILE;
+                    *black_box(&mut arrays)[8].get_mut(x, z) = BlockState::STONE;
+                }
+            }
+        });
+    }
+
+    #[bench]
+    fn from_fn_paletted(b: &mut Bencher) {
+        let arrays = terrain_arrays();
+        b.iter(|| PalettedBlocks::from_fn(|x, y, z| arrays[y].get(x, z).clone()));
+    }
+
+    #[bench]
+    fn from_fn_arrays(b: &mut Bencher) {
+        let arrays = terrain_arrays();
+        b.iter(|| -> Arrays {
+            std::array::from_fn(|y| ChunkLayer::from_fn(|x, z| arrays[y].get(x, z).clone()))
+        });
+    }
+}
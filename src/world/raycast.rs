@@ -0,0 +1,177 @@
+use macroquad::math::Vec3;
+
+use crate::world::render::BlockSide;
+use crate::world::{BlockPos, BlockSource};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RaycastHit {
+    /// First block on the ray that `BlockType::is_targetable`
+    pub block: BlockPos,
+    /// Side of `block` the ray came through
+    pub side: BlockSide,
+    /// Block in front of `side`, empty or a fluid, a placed block goes there
+    pub adjacent: BlockPos,
+    /// From the ray origin to the hit point
+    pub distance: f32,
+}
+
+/// Voxel DDA (Amanatides & Woo): walks every block the ray passes through, in order.
+/// The block containing `origin` is not checked
+pub fn raycast(
+    source: &impl BlockSource,
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+) -> Option<RaycastHit> {
+    let direction = direction.normalize_or_zero();
+    if direction == Vec3::ZERO {
+        return None;
+    }
+    let start = BlockPos::from(origin);
+    let mut block = [start.x, start.y, start.z];
+    let origin = origin.to_array();
+    let direction = direction.to_array();
+
+    let mut step = [0; 3];
+    // ray length to pass one block along the axis
+    let mut t_delta = [f32::INFINITY; 3];
+    // ray length to the next block boundary along the axis
+    let mut t_max = [f32::INFINITY; 3];
+    for axis in 0..3 {
+        if direction[axis] > 0.0 {
+            step[axis] = 1;
+            t_delta[axis] = 1.0 / direction[axis];
+            t_max[axis] = (block[axis] as f32 + 1.0 - origin[axis]) * t_delta[axis];
+        } else if direction[axis] < 0.0 {
+            step[axis] = -1;
+            t_delta[axis] = -1.0 / direction[axis];
+            t_max[axis] = (origin[axis] - block[axis] as f32) * t_delta[axis];
+        }
+    }
+
+    loop {
+        let axis = (0..3)
+            .min_by(|&a, &b| t_max[a].total_cmp(&t_max[b]))
+            .unwrap();
+        let distance = t_max[axis];
+        if distance > max_distance {
+            return None;
+        }
+        block[axis] += step[axis];
+        t_max[axis] += t_delta[axis];
+
+        let pos = BlockPos::new(block[0], block[1], block[2]);
+        if source.get_block(pos).block_type.is_targetable() {
+            let side = entered_side(axis, step[axis]);
+            return Some(RaycastHit {
+                block: pos,
+                side,
+                adjacent: pos + side.offset(),
+                distance,
+            });
+        }
+    }
+}
+
+/// Moving along +x the ray enters a block through its -x side
+fn entered_side(axis: usize, step: i32) -> BlockSide {
+    match (axis, step > 0) {
+        (0, true) => BlockSide::Nx,
+        (0, false) => BlockSide::Px,
+        (1, true) => BlockSide::Ny,
+        (1, false) => BlockSide::Py,
+        (_, true) => BlockSide::Nz,
+        (_, false) => BlockSide::Pz,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::vec3;
+
+    use super::*;
+    use crate::world::registry::BlockRegistry;
+    use crate::world::{BlockState, Chunk, World};
+
+    fn chunk_with(blocks: &[(usize, usize, usize)]) -> Chunk {
+        let mut chunk = Chunk::EMPTY;
+        for &(x, y, z) in blocks {
+            chunk.set(x, y, z, BlockState::STONE);
+        }
+        chunk
+    }
+
+    #[test]
+    fn hits_block_straight_ahead() {
+        let chunk = chunk_with(&[(5, 5, 5)]);
+        let hit = raycast(&chunk, vec3(5.5, 5.5, 0.5), vec3(0.0, 0.0, 1.0), 10.0).unwrap();
+        assert_eq!(hit.block, BlockPos::new(5, 5, 5));
+        assert_eq!(hit.side, BlockSide::Nz);
+        assert_eq!(hit.adjacent, BlockPos::new(5, 5, 4));
+        assert_eq!(hit.distance, 4.5);
+    }
+
+    #[test]
+    fn each_axis_reports_its_side() {
+        let chunk = chunk_with(&[(8, 8, 8)]);
+        let center = vec3(8.5, 8.5, 8.5);
+        for side_idx in 0..6 {
+            let side = BlockSide::from_position(side_idx);
+            let offset = side.offset();
+            let normal = vec3(offset.x as f32, offset.y as f32, offset.z as f32);
+            let hit = raycast(&chunk, center + normal * 3.0, -normal, 10.0).unwrap();
+            assert_eq!(hit.block, BlockPos::new(8, 8, 8));
+            assert_eq!(hit.side, side);
+            assert_eq!(hit.adjacent, BlockPos::new(8, 8, 8) + offset);
+            assert_eq!(hit.distance, 2.5);
+        }
+    }
+
+    #[test]
+    fn diagonal_ray_finds_nearest_block() {
+        let chunk = chunk_with(&[(3, 1, 3), (6, 1, 6)]);
+        let hit = raycast(&chunk, vec3(0.5, 1.5, 0.2), vec3(1.0, 0.0, 1.0), 20.0).unwrap();
+        // the ray reaches x = 3 at z = 2.7, so the block is entered through its -z side
+        assert_eq!(hit.block, BlockPos::new(3, 1, 3));
+        assert_eq!(hit.side, BlockSide::Nz);
+        assert_eq!(hit.adjacent, BlockPos::new(3, 1, 2));
+    }
+
+    #[test]
+    fn stops_at_max_distance() {
+        let chunk = chunk_with(&[(5, 5, 5)]);
+        assert_eq!(raycast(&chunk, vec3(5.5, 5.5, 0.5), vec3(0.0, 0.0, 1.0), 4.0), None);
+        assert_eq!(raycast(&chunk, vec3(5.5, 5.5, 0.5), vec3(0.0, 1.0, 0.0), 100.0), None);
+        assert_eq!(raycast(&chunk, vec3(5.5, 5.5, 0.5), Vec3::ZERO, 100.0), None);
+    }
+
+    #[test]
+    fn starting_block_is_skipped() {
+        let chunk = chunk_with(&[(1, 1, 1), (1, 1, 3)]);
+        let hit = raycast(&chunk, vec3(1.5, 1.5, 1.5), vec3(0.0, 0.0, 1.0), 10.0).unwrap();
+        assert_eq!(hit.block, BlockPos::new(1, 1, 3));
+    }
+
+    #[test]
+    fn looks_through_water() {
+        let water = BlockState::new(BlockRegistry::global().by_name("water").unwrap());
+        let mut chunk = chunk_with(&[(2, 0, 2)]);
+        for y in 1..4 {
+            chunk.set(2, y, 2, water.clone());
+        }
+        let hit = raycast(&chunk, vec3(2.5, 6.5, 2.5), vec3(0.0, -1.0, 0.0), 10.0).unwrap();
+        assert_eq!(hit.block, BlockPos::new(2, 0, 2));
+        assert_eq!(hit.adjacent, BlockPos::new(2, 1, 2));
+    }
+
+    #[test]
+    fn works_across_negative_chunks() {
+        let mut world = World::new();
+        world.set_block(BlockPos::new(-20, 4, -1), BlockState::DIRT);
+        let hit = raycast(&world, vec3(3.5, 4.5, -0.5), vec3(-1.0, 0.0, 0.0), 30.0).unwrap();
+        assert_eq!(hit.block, BlockPos::new(-20, 4, -1));
+        assert_eq!(hit.side, BlockSide::Px);
+        assert_eq!(hit.adjacent, BlockPos::new(-19, 4, -1));
+        assert_eq!(hit.distance, 22.5);
+    }
+}
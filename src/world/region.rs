@@ -0,0 +1,427 @@
+//! Region files: `REGION_SIZE` x `REGION_SIZE` chunks of one chunk layer in a file.
+//!
+//! ```text
+//! magic "CGRG" | version u16 | unused u16
+//! REGION_CHUNKS x (offset u32, length u32)    offset 0 - chunk not saved
+//! zlib compressed chunks: biome u8, CHUNK_SIZE_16^3 x block type id u16 (y, x, z order)
+//! ```
+//! Numbers are little endian, offsets are from the start of the file.
+//! Version 1 files have u8 block type ids, they are read and saved again as the current version
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::world::palette::block_index;
+use crate::world::{Biome, BlockState, BlockType, Chunk, ChunkPos, CHUNK_SIZE_16};
+
+/// Chunks along x and z in one region
+pub const REGION_SIZE: i32 = 32;
+const REGION_CHUNKS: usize = (REGION_SIZE * REGION_SIZE) as usize;
+
+const MAGIC: &[u8; 4] = b"CGRG";
+/// 2 - block type ids from the `BlockRegistry` grew to u16
+pub const REGION_VERSION: u16 = 2;
+/// Block type ids were u8
+const REGION_VERSION_U8_IDS: u16 = 1;
+const HEADER_LEN: usize = 8 + REGION_CHUNKS * 8;
+const CHUNK_BLOCKS: usize = CHUNK_SIZE_16 * CHUNK_SIZE_16 * CHUNK_SIZE_16;
+const CHUNK_DATA_LEN: usize = 1 + CHUNK_BLOCKS * 2;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct RegionPos(pub i32, pub i32, pub i32);
+
+impl RegionPos {
+    pub const fn of_chunk(pos: ChunkPos) -> Self {
+        Self(pos.0.div_euclid(REGION_SIZE), pos.1, pos.2.div_euclid(REGION_SIZE))
+    }
+
+    pub fn file_name(&self) -> String {
+        format!("r.{}.{}.{}.region", self.0, self.1, self.2)
+    }
+
+    fn from_file_name(name: &str) -> Option<Self> {
+        let mut parts = name.strip_prefix("r.")?.strip_suffix(".region")?.split('.');
+        let pos = Self(parts.next()?.parse().ok()?, parts.next()?.parse().ok()?, parts.next()?.parse().ok()?);
+        parts.next().is_none().then_some(pos)
+    }
+
+    fn chunk_pos(&self, index: usize) -> ChunkPos {
+        let (x, z) = (index as i32 / REGION_SIZE, index as i32 % REGION_SIZE);
+        ChunkPos(self.0 * REGION_SIZE + x, self.1, self.2 * REGION_SIZE + z)
+    }
+}
+
+fn chunk_index(pos: ChunkPos) -> usize {
+    (pos.0.rem_euclid(REGION_SIZE) * REGION_SIZE + pos.2.rem_euclid(REGION_SIZE)) as usize
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Compressed chunks of one region file
+struct Region {
+    chunks: Vec<Option<Vec<u8>>>,
+}
+
+impl Region {
+    fn empty() -> Self {
+        Self { chunks: vec![None; REGION_CHUNKS] }
+    }
+
+    /// The file version and the (offset, length) of every chunk
+    fn read_header(file: &mut impl Read) -> io::Result<(u16, Vec<(u32, u32)>)> {
+        let mut header = vec![0; HEADER_LEN];
+        file.read_exact(&mut header)?;
+        if &header[0..4] != MAGIC {
+            return Err(invalid_data("not a region file"));
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version != REGION_VERSION && version != REGION_VERSION_U8_IDS {
+            return Err(invalid_data("unsupported region file version"));
+        }
+        let entries = header[8..]
+            .chunks_exact(8)
+            .map(|entry| {
+                let offset = u32::from_le_bytes(entry[0..4].try_into().unwrap());
+                let length = u32::from_le_bytes(entry[4..8].try_into().unwrap());
+                (offset, length)
+            })
+            .collect();
+        Ok((version, entries))
+    }
+
+    /// Empty region if there is no file. Chunks of older versions come in the current one
+    fn read(path: &Path) -> io::Result<Self> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::empty()),
+            Err(err) => return Err(err),
+        };
+        let (version, header) = Self::read_header(&mut bytes.as_slice())?;
+        let chunks = header
+            .into_iter()
+            .map(|(offset, length)| {
+                if offset == 0 {
+                    return Ok(None);
+                }
+                let range = offset as usize..offset as usize + length as usize;
+                let data = bytes.get(range).ok_or_else(|| invalid_data("chunk out of file"))?;
+                if version == REGION_VERSION {
+                    Ok(Some(data.to_vec()))
+                } else {
+                    Ok(Some(compress(&decompress(data, version)?)))
+                }
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self { chunks })
+    }
+
+    /// Writes a temporary file first, so a crash never leaves half a region behind
+    fn write(&self, path: &Path) -> io::Result<()> {
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&REGION_VERSION.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        let mut data = vec![];
+        for chunk in &self.chunks {
+            let (offset, length) = match chunk {
+                Some(chunk) => {
+                    let offset = HEADER_LEN + data.len();
+                    data.extend_from_slice(chunk);
+                    (offset as u32, chunk.len() as u32)
+                }
+                None => (0, 0),
+            };
+            header.extend_from_slice(&offset.to_le_bytes());
+            header.extend_from_slice(&length.to_le_bytes());
+        }
+
+        let temp_path = path.with_extension("region.tmp");
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&header)?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+        fs::rename(temp_path, path)
+    }
+}
+
+fn biome_id(biome: &Biome) -> u8 {
+    match biome {
+        Biome::Plains => 0,
+        Biome::Desert => 1,
+        Biome::Forest => 2,
+        Biome::Jungle => 3,
+    }
+}
+
+fn biome_from_id(id: u8) -> Option<Biome> {
+    match id {
+        0 => Some(Biome::Plains),
+        1 => Some(Biome::Desert),
+        2 => Some(Biome::Forest),
+        3 => Some(Biome::Jungle),
+        _ => None,
+    }
+}
+
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+    encoder.write_all(data).expect("writing to a Vec can't fail");
+    encoder.finish().expect("writing to a Vec can't fail")
+}
+
+/// Chunk data of a file of `version`, with the ids widened to u16 if they were u8
+fn decompress(bytes: &[u8], version: u16) -> io::Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(CHUNK_DATA_LEN);
+    ZlibDecoder::new(bytes).read_to_end(&mut data)?;
+    if version == REGION_VERSION_U8_IDS {
+        if data.len() != 1 + CHUNK_BLOCKS {
+            return Err(invalid_data("wrong chunk length"));
+        }
+        let ids = data[1..].iter().flat_map(|&id| u16::from(id).to_le_bytes());
+        data = std::iter::once(data[0]).chain(ids).collect();
+    }
+    if data.len() != CHUNK_DATA_LEN {
+        return Err(invalid_data("wrong chunk length"));
+    }
+    Ok(data)
+}
+
+pub fn encode_chunk(chunk: &Chunk) -> Vec<u8> {
+    let mut data = Vec::with_capacity(CHUNK_DATA_LEN);
+    data.push(biome_id(&chunk.biome));
+    data.extend(chunk.blocks.iter().flat_map(|block| block.block_type.id().to_le_bytes()));
+    compress(&data)
+}
+
+/// The chunk comes without a model, like a generated one
+pub fn decode_chunk(bytes: &[u8], pos: ChunkPos) -> io::Result<Chunk> {
+    decode_chunk_version(bytes, pos, REGION_VERSION)
+}
+
+fn decode_chunk_version(bytes: &[u8], pos: ChunkPos, version: u16) -> io::Result<Chunk> {
+    let data = decompress(bytes, version)?;
+    let biome = biome_from_id(data[0]).ok_or_else(|| invalid_data("unknown biome"))?;
+    let types = data[1..]
+        .chunks_exact(2)
+        .map(|id| BlockType::from_id(u16::from_le_bytes([id[0], id[1]])))
+        .collect::<Option<Vec<_>>>();
+    let types = types.ok_or_else(|| invalid_data("unknown block type"))?;
+    let mut chunk = Chunk::from_fn(|x, y, z| BlockState::new(types[block_index(x, y, z)]));
+    chunk.biome = biome;
+    chunk.pos = pos.world_pos();
+    Ok(chunk)
+}
+
+pub fn region_path(dir: &Path, pos: ChunkPos) -> PathBuf {
+    dir.join(RegionPos::of_chunk(pos).file_name())
+}
+
+/// Adds the chunks to their region files, other chunks already in the files stay
+pub fn save_chunks<'a>(dir: &Path, chunks: impl IntoIterator<Item = (ChunkPos, &'a Chunk)>) -> io::Result<()> {
+    let mut regions: HashMap<RegionPos, Vec<(ChunkPos, &Chunk)>> = HashMap::new();
+    for (pos, chunk) in chunks {
+        regions.entry(RegionPos::of_chunk(pos)).or_default().push((pos, chunk));
+    }
+    if regions.is_empty() {
+        return Ok(());
+    }
+    fs::create_dir_all(dir)?;
+    for (region_pos, chunks) in regions {
+        let path = dir.join(region_pos.file_name());
+        let mut region = Region::read(&path)?;
+        for (pos, chunk) in chunks {
+            region.chunks[chunk_index(pos)] = Some(encode_chunk(chunk));
+        }
+        region.write(&path)?;
+    }
+    Ok(())
+}
+
+/// Reads one chunk without reading the whole region, `None` if it was never saved
+pub fn load_chunk(dir: &Path, pos: ChunkPos) -> io::Result<Option<Chunk>> {
+    let mut file = match File::open(region_path(dir, pos)) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let (version, header) = Region::read_header(&mut file)?;
+    let (offset, length) = header[chunk_index(pos)];
+    if offset == 0 {
+        return Ok(None);
+    }
+    // a broken header must not make us allocate whatever length it says
+    if offset as u64 + length as u64 > file.metadata()?.len() {
+        return Err(invalid_data("chunk out of file"));
+    }
+    let mut bytes = vec![0; length as usize];
+    file.seek(SeekFrom::Start(offset as u64))?;
+    file.read_exact(&mut bytes)?;
+    decode_chunk_version(&bytes, pos, version).map(Some)
+}
+
+/// Every chunk of every region file in `dir`
+pub fn load_all_chunks(dir: &Path) -> io::Result<Vec<(ChunkPos, Chunk)>> {
+    let mut chunks = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(region_pos) = path.file_name().and_then(|name| RegionPos::from_file_name(name.to_str()?)) else {
+            continue;
+        };
+        let region = Region::read(&path)?;
+        for (index, bytes) in region.chunks.iter().enumerate() {
+            if let Some(bytes) = bytes {
+                let pos = region_pos.chunk_pos(index);
+                chunks.push((pos, decode_chunk(bytes, pos)?));
+            }
+        }
+    }
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::terrain::TerrainGenerator;
+    use crate::world::temp_dir::TempDir;
+    use crate::world::{BlockPos, World};
+
+    #[test]
+    fn region_pos_and_index() {
+        assert_eq!(RegionPos::of_chunk(ChunkPos(31, 2, -1)), RegionPos(0, 2, -1));
+        assert_eq!(RegionPos::of_chunk(ChunkPos(-33, -1, 64)), RegionPos(-2, -1, 2));
+        for pos in [ChunkPos(0, 0, 0), ChunkPos(-1, 3, 31), ChunkPos(-33, -1, 64)] {
+            assert_eq!(RegionPos::of_chunk(pos).chunk_pos(chunk_index(pos)), pos);
+        }
+        let region = RegionPos(-2, 5, 13);
+        assert_eq!(RegionPos::from_file_name(&region.file_name()), Some(region));
+        assert_eq!(RegionPos::from_file_name("r.1.2.region"), None);
+    }
+
+    #[test]
+    fn chunk_encoding_round_trip() {
+        let generator = TerrainGenerator::new(5);
+        let chunk = generator.generate_chunk(ChunkPos(-4, 0, 9));
+        let decoded = decode_chunk(&encode_chunk(&chunk), ChunkPos(-4, 0, 9)).unwrap();
+        assert_eq!(decoded.blocks, chunk.blocks);
+        assert_eq!(decoded.biome, chunk.biome);
+        assert_eq!(decoded.pos, chunk.pos);
+        assert!(decode_chunk(&[1, 2, 3], ChunkPos(0, 0, 0)).is_err());
+    }
+
+    #[test]
+    fn world_round_trip() {
+        let dir = TempDir::new("world-round-trip");
+        let mut world = World::new();
+        // chunks in several regions, some negative
+        let blocks = [
+            (BlockPos::new(1, 2, 3), BlockState::STONE),
+            (BlockPos::new(-1, -20, -3), BlockState::SAND),
+            (BlockPos::new(600, 40, -700), BlockState::DIRT),
+            (BlockPos::new(511, 0, 511), BlockState::GRASS),
+            (BlockPos::new(512, 0, 511), BlockState::TILE),
+        ];
+        for (pos, state) in &blocks {
+            world.set_block(*pos, state.clone());
+        }
+        world.save(dir.path()).unwrap();
+
+        let loaded = World::load(dir.path()).unwrap();
+        let mut saved_positions: Vec<_> = world.chunk_positions().collect();
+        let mut loaded_positions: Vec<_> = loaded.chunk_positions().collect();
+        saved_positions.sort_by_key(|pos| (pos.0, pos.1, pos.2));
+        loaded_positions.sort_by_key(|pos| (pos.0, pos.1, pos.2));
+        assert_eq!(loaded_positions, saved_positions);
+        for (pos, state) in &blocks {
+            assert_eq!(loaded.get_block(*pos), state);
+        }
+        for (pos, chunk) in world.chunks() {
+            let loaded_chunk = loaded.get_chunk(*pos).unwrap();
+            assert_eq!(loaded_chunk.blocks, chunk.blocks);
+            assert_eq!(loaded_chunk.pos, chunk.pos);
+        }
+    }
+
+    #[test]
+    fn saving_keeps_other_chunks_of_the_region() {
+        let dir = TempDir::new("keeps-other-chunks");
+        let first = Chunk::from_fn(|_, y, _| if y == 0 { BlockState::STONE } else { BlockState::AIR });
+        let second = Chunk::from_fn(|x, _, _| if x == 3 { BlockState::DIRT } else { BlockState::AIR });
+        save_chunks(dir.path(), [(ChunkPos(0, 0, 0), &first)]).unwrap();
+        save_chunks(dir.path(), [(ChunkPos(1, 0, 0), &second)]).unwrap();
+
+        assert_eq!(load_chunk(dir.path(), ChunkPos(0, 0, 0)).unwrap().unwrap().blocks, first.blocks);
+        assert_eq!(load_chunk(dir.path(), ChunkPos(1, 0, 0)).unwrap().unwrap().blocks, second.blocks);
+        assert!(load_chunk(dir.path(), ChunkPos(2, 0, 0)).unwrap().is_none());
+        assert!(load_chunk(dir.path(), ChunkPos(0, 1, 0)).unwrap().is_none());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    /// A version 1 region file with `chunk` at the origin, ids written as u8
+    fn write_version_1_region(dir: &Path, chunk: &Chunk) {
+        let mut data = vec![biome_id(&chunk.biome)];
+        data.extend(chunk.blocks.iter().map(|block| block.block_type.id() as u8));
+        let mut region = Region::empty();
+        region.chunks[chunk_index(ChunkPos(0, 0, 0))] = Some(compress(&data));
+        fs::create_dir_all(dir).unwrap();
+        let path = region_path(dir, ChunkPos(0, 0, 0));
+        region.write(&path).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[4..6].copy_from_slice(&REGION_VERSION_U8_IDS.to_le_bytes());
+        fs::write(&path, bytes).unwrap();
+    }
+
+    #[test]
+    fn version_1_files_are_read_and_upgraded() {
+        let dir = TempDir::new("version-1");
+        let old = Chunk::from_fn(|x, y, _| if y == 0 { BlockState::STONE } else if x == 1 { BlockState::SAND } else { BlockState::AIR });
+        write_version_1_region(dir.path(), &old);
+        assert_eq!(load_chunk(dir.path(), ChunkPos(0, 0, 0)).unwrap().unwrap().blocks, old.blocks);
+
+        // saving another chunk of the region rewrites the old one in the current version
+        let new = Chunk::from_fn(|_, _, z| if z == 2 { BlockState::TILE } else { BlockState::AIR });
+        save_chunks(dir.path(), [(ChunkPos(0, 0, 1), &new)]).unwrap();
+        let bytes = fs::read(region_path(dir.path(), ChunkPos(0, 0, 0))).unwrap();
+        assert_eq!(u16::from_le_bytes([bytes[4], bytes[5]]), REGION_VERSION);
+        assert_eq!(load_chunk(dir.path(), ChunkPos(0, 0, 0)).unwrap().unwrap().blocks, old.blocks);
+        assert_eq!(load_chunk(dir.path(), ChunkPos(0, 0, 1)).unwrap().unwrap().blocks, new.blocks);
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let dir = TempDir::new("versions");
+        save_chunks(dir.path(), [(ChunkPos(0, 0, 0), &Chunk::EMPTY)]).unwrap();
+        let path = region_path(dir.path(), ChunkPos(0, 0, 0));
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[4..6].copy_from_slice(&(REGION_VERSION + 1).to_le_bytes());
+        fs::write(&path, bytes).unwrap();
+        let err = load_chunk(dir.path(), ChunkPos(0, 0, 0)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn chunks_out_of_file_are_rejected() {
+        let dir = TempDir::new("out-of-file");
+        save_chunks(dir.path(), [(ChunkPos(0, 0, 0), &Chunk::EMPTY)]).unwrap();
+        let path = region_path(dir.path(), ChunkPos(0, 0, 0));
+        let saved = fs::read(&path).unwrap();
+        let (offset, _) = Region::read_header(&mut saved.as_slice()).unwrap().1[chunk_index(ChunkPos(0, 0, 0))];
+        // a huge length, then a chunk starting at the end of the file
+        for (offset, length) in [(offset, u32::MAX), (saved.len() as u32, 1)] {
+            let mut bytes = saved.clone();
+            let entry = 8 + chunk_index(ChunkPos(0, 0, 0)) * 8;
+            bytes[entry..entry + 4].copy_from_slice(&offset.to_le_bytes());
+            bytes[entry + 4..entry + 8].copy_from_slice(&length.to_le_bytes());
+            fs::write(&path, bytes).unwrap();
+            assert_eq!(load_chunk(dir.path(), ChunkPos(0, 0, 0)).unwrap_err().kind(), io::ErrorKind::InvalidData);
+            assert_eq!(load_all_chunks(dir.path()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use crate::world::render::atlas::TextureAtlas;
+use crate::world::render::face::TextureRotation;
+use crate::world::{BlockType, TextureSet};
+
+/// Blocks the game ships with, used when no registry was installed
+const BUILTIN_BLOCKS: &str = include_str!("../../assets/blocks.ron");
+
+static REGISTRY: OnceLock<BlockRegistry> = OnceLock::new();
+
+/// One entry of `blocks.ron`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct BlockDefinition {
+    pub id: u16,
+    pub name: String,
+    pub textures: FaceTextures,
+    #[serde(default)]
+    pub rotation: FaceRotation,
+    pub solid: bool,
+    pub transparency: Transparency,
+    pub light: u8,
+    pub hardness: f32,
+    #[serde(default)]
+    pub drops: Drops,
+}
+
+/// What breaking the block leaves to pick up
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+pub enum Drops {
+    /// The block itself
+    #[default]
+    Itself,
+    Nothing,
+    /// Another block by name, like dirt from grass
+    Block(String),
+}
+
+/// How the block lets the faces behind it through
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Deserialize)]
+pub enum Transparency {
+    /// Hides every face touching it
+    #[default]
+    Opaque,
+    /// Fully see through pixels with alpha tested textures, like leaves, hides nothing
+    Cutout,
+    /// Blended over what is behind, like glass or water, hides only faces of the same block
+    Translucent,
+}
+
+/// Atlas tile names of the block faces
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub enum FaceTextures {
+    Invisible,
+    All(String),
+    Column { top: String, side: String, bottom: String },
+    Faces {
+        #[serde(default)]
+        top: Option<String>,
+        #[serde(default)]
+        bottom: Option<String>,
+        #[serde(default)]
+        px: Option<String>,
+        #[serde(default)]
+        nx: Option<String>,
+        #[serde(default)]
+        pz: Option<String>,
+        #[serde(default)]
+        nz: Option<String>,
+    },
+}
+
+impl FaceTextures {
+    /// Names in `RenderCubeByte` bits order
+    fn names(&self) -> [Option<&str>; 6] {
+        match self {
+            FaceTextures::Invisible => [None; 6],
+            FaceTextures::All(name) => [Some(name.as_str()); 6],
+            FaceTextures::Column { top, side, bottom } => {
+                let side = Some(side.as_str());
+                [side, side, side, side, Some(bottom.as_str()), Some(top.as_str())]
+            }
+            FaceTextures::Faces { top, bottom, px, nx, pz, nz } => {
+                [nz, pz, nx, px, bottom, top].map(|name| name.as_deref())
+            }
+        }
+    }
+}
+
+/// Turns of the face textures, faces left out stay upright
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct FaceRotation {
+    pub top: TextureRotation,
+    pub bottom: TextureRotation,
+    pub px: TextureRotation,
+    pub nx: TextureRotation,
+    pub pz: TextureRotation,
+    pub nz: TextureRotation,
+}
+
+impl FaceRotation {
+    /// In `RenderCubeByte` bits order
+    fn sides(&self) -> [TextureRotation; 6] {
+        [self.nz, self.pz, self.nx, self.px, self.bottom, self.top]
+    }
+}
+
+/// A block definition with textures looked up in the atlas
+#[derive(Debug, Clone, PartialEq)]
+#[allow(dead_code)]
+pub struct BlockProperties {
+    pub name: String,
+    /// In `RenderCubeByte` bits order, `None` faces are not drawn
+    pub textures: TextureSet,
+    /// Stops the player
+    pub solid: bool,
+    pub transparency: Transparency,
+    pub light: u8,
+    pub hardness: f32,
+    /// Item left when the block is broken
+    pub drops: Option<BlockType>,
+}
+
+/// All known blocks by their numeric id
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockRegistry {
+    blocks: Vec<Option<BlockProperties>>,
+    by_name: HashMap<String, BlockType>,
+}
+
+#[allow(dead_code)]
+impl BlockRegistry {
+    pub const MAX_LIGHT: u8 = 15;
+
+    /// Texture names are looked up in `atlas`
+    pub fn parse(text: &str, atlas: &TextureAtlas) -> io::Result<Self> {
+        let definitions: Vec<BlockDefinition> = ron::from_str(text).map_err(|err| invalid_data(err.to_string()))?;
+        Self::from_definitions(definitions, atlas)
+    }
+
+    pub fn load(path: &Path, atlas: &TextureAtlas) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?, atlas)
+    }
+
+    /// The blocks the game ships with, fails if `atlas` misses their textures
+    pub fn builtin(atlas: &TextureAtlas) -> io::Result<Self> {
+        Self::parse(BUILTIN_BLOCKS, atlas)
+    }
+
+    /// Checks ids, names and textures, the blocks the game places itself
+    /// (`BlockType::BUILTIN`) must be there with their ids
+    pub fn from_definitions(definitions: Vec<BlockDefinition>, atlas: &TextureAtlas) -> io::Result<Self> {
+        let mut registry = Self { blocks: Vec::new(), by_name: HashMap::new() };
+        // blocks dropped by name can come later in the file
+        let mut drops = Vec::new();
+        for definition in definitions {
+            let id = definition.id as usize;
+            if registry.blocks.get(id).is_some_and(Option::is_some) {
+                return Err(invalid_data(format!("block id {} is used twice", id)));
+            }
+            if registry.by_name.contains_key(&definition.name) {
+                return Err(invalid_data(format!("block name {:?} is used twice", definition.name)));
+            }
+            if definition.light > Self::MAX_LIGHT {
+                return Err(invalid_data(format!("{}: light is over {}", definition.name, Self::MAX_LIGHT)));
+            }
+            let mut textures = [None; 6];
+            let faces = definition.textures.names().into_iter().zip(definition.rotation.sides());
+            for (texture, (name, rotation)) in textures.iter_mut().zip(faces) {
+                if let Some(name) = name {
+                    let found = atlas.texture(name).ok_or_else(|| {
+                        invalid_data(format!("{}: unknown texture {:?}", definition.name, name))
+                    })?;
+                    *texture = Some(found.rotated(rotation));
+                }
+            }
+            if registry.blocks.len() <= id {
+                registry.blocks.resize(id + 1, None);
+            }
+            registry.by_name.insert(definition.name.clone(), BlockType(definition.id));
+            drops.push((BlockType(definition.id), definition.name.clone(), definition.drops));
+            registry.blocks[id] = Some(BlockProperties {
+                name: definition.name,
+                textures,
+                solid: definition.solid,
+                transparency: definition.transparency,
+                light: definition.light,
+                hardness: definition.hardness,
+                drops: None,
+            });
+        }
+        for (block_type, name, dropped) in drops {
+            let dropped = match dropped {
+                Drops::Itself => Some(block_type),
+                Drops::Nothing => None,
+                Drops::Block(dropped) => Some(registry.by_name(&dropped).ok_or_else(|| {
+                    invalid_data(format!("{}: drops unknown block {:?}", name, dropped))
+                })?),
+            };
+            if let Some(properties) = registry.blocks[block_type.0 as usize].as_mut() {
+                properties.drops = dropped;
+            }
+        }
+        for (block_type, name) in BlockType::BUILTIN {
+            if registry.by_name(name) != Some(block_type) {
+                return Err(invalid_data(format!("block {:?} must have id {}", name, block_type.id())));
+            }
+        }
+        Ok(registry)
+    }
+
+    /// Makes the registry the one `BlockType` looks properties up in.
+    /// Only the first call counts, it has to be done before any block is used
+    pub fn install(self) -> Result<(), Self> {
+        REGISTRY.set(self)
+    }
+
+    /// The installed registry, the builtin one with the builtin atlas if nothing was installed
+    pub fn global() -> &'static Self {
+        REGISTRY.get_or_init(|| Self::builtin(&TextureAtlas::builtin()).expect("assets/blocks.ron is valid"))
+    }
+
+    pub fn get(&self, block_type: BlockType) -> Option<&BlockProperties> {
+        self.blocks.get(block_type.0 as usize)?.as_ref()
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<BlockType> {
+        self.by_name.get(name).copied()
+    }
+
+    pub fn contains(&self, id: u16) -> bool {
+        self.get(BlockType(id)).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BlockType, &BlockProperties)> + '_ {
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(id, properties)| Some((BlockType(id as u16), properties.as_ref()?)))
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definition(id: u16, name: &str, textures: FaceTextures) -> BlockDefinition {
+        BlockDefinition {
+            id,
+            name: name.to_string(),
+            textures,
+            rotation: FaceRotation::default(),
+            solid: true,
+            transparency: Transparency::Opaque,
+            light: 0,
+            hardness: 1.0,
+            drops: Drops::Itself,
+        }
+    }
+
+    fn from_definitions(definitions: Vec<BlockDefinition>) -> io::Result<BlockRegistry> {
+        BlockRegistry::from_definitions(definitions, &TextureAtlas::builtin())
+    }
+
+    fn builtin_definitions() -> Vec<BlockDefinition> {
+        ron::from_str(BUILTIN_BLOCKS).unwrap()
+    }
+
+    #[test]
+    fn builtin_blocks_load() {
+        let atlas = TextureAtlas::builtin();
+        let registry = BlockRegistry::builtin(&atlas).unwrap();
+        let texture = |name| atlas.texture(name);
+        for (block_type, name) in BlockType::BUILTIN {
+            assert_eq!(registry.get(block_type).unwrap().name, name);
+        }
+        let grass = registry.get(BlockType::GRASS).unwrap();
+        assert_eq!(grass.textures[5], texture("grass_top"));
+        assert_eq!(grass.textures[4], texture("dirt"));
+        assert_eq!(grass.textures[0], texture("grass_side"));
+        let air = registry.get(BlockType::AIR).unwrap();
+        assert_eq!(air.textures, [None; 6]);
+        assert!(!air.solid);
+        let leaves = registry.get(registry.by_name("leaves").unwrap()).unwrap();
+        assert_eq!(leaves.transparency, Transparency::Cutout);
+        let tile = registry.get(BlockType::TILE).unwrap();
+        assert_eq!(tile.textures, [None, None, None, None, None, texture("sand")]);
+    }
+
+    #[test]
+    fn face_textures_are_rotated() {
+        let lying_log = r#"(id: 20, name: "lying_log", textures: Column(top: "log_side", side: "log_side", bottom: "log_top"),
+            rotation: (top: R90, px: R90, nx: R270), solid: true, transparency: Opaque, light: 0, hardness: 2.0)"#;
+        let mut definitions = builtin_definitions();
+        definitions.push(ron::from_str(lying_log).unwrap());
+        let registry = from_definitions(definitions).unwrap();
+        let textures = registry.get(registry.by_name("lying_log").unwrap()).unwrap().textures;
+        let log_side = TextureAtlas::builtin().texture("log_side").unwrap();
+        assert_eq!(textures[5], Some(log_side.rotated(TextureRotation::R90)));
+        assert_eq!(textures[3], Some(log_side.rotated(TextureRotation::R90)));
+        assert_eq!(textures[2], Some(log_side.rotated(TextureRotation::R270)));
+        assert_eq!(textures[0], Some(log_side));
+    }
+
+    #[test]
+    fn broken_blocks_drop_items() {
+        let registry = BlockRegistry::builtin(&TextureAtlas::builtin()).unwrap();
+        let drops = |name| registry.get(registry.by_name(name).unwrap()).unwrap().drops;
+        assert_eq!(drops("stone"), Some(BlockType::STONE));
+        assert_eq!(drops("grass"), Some(BlockType::DIRT));
+        assert_eq!(drops("glass"), None);
+        assert_eq!(drops("air"), None);
+
+        let mut definitions = builtin_definitions();
+        let mut ore = definition(20, "ore", FaceTextures::All("stone".to_string()));
+        ore.drops = Drops::Block("gem".to_string());
+        definitions.push(ore);
+        assert!(from_definitions(definitions.clone()).is_err());
+        // the dropped block may come after the one dropping it
+        definitions.push(definition(21, "gem", FaceTextures::All("glass".to_string())));
+        let registry = from_definitions(definitions).unwrap();
+        assert_eq!(registry.get(registry.by_name("ore").unwrap()).unwrap().drops, registry.by_name("gem"));
+    }
+
+    #[test]
+    fn new_blocks_need_no_code() {
+        let mut definitions = builtin_definitions();
+        let builtin_count = definitions.len();
+        let mut lamp = definition(40, "lamp", FaceTextures::All("sand".to_string()));
+        lamp.light = 14;
+        definitions.push(lamp);
+        let registry = from_definitions(definitions).unwrap();
+        let lamp = registry.by_name("lamp").unwrap();
+        assert_eq!(lamp.id(), 40);
+        assert_eq!(registry.get(lamp).unwrap().light, 14);
+        assert!(registry.contains(40));
+        assert!(!registry.contains(39));
+        assert_eq!(registry.iter().count(), builtin_count + 1);
+    }
+
+    #[test]
+    fn bad_definitions_are_rejected() {
+        let with = |extra: BlockDefinition| {
+            let mut definitions = builtin_definitions();
+            definitions.push(extra);
+            from_definitions(definitions)
+        };
+        assert!(with(definition(3, "granite", FaceTextures::All("stone".to_string()))).is_err());
+        assert!(with(definition(20, "stone", FaceTextures::All("stone".to_string()))).is_err());
+        assert!(with(definition(20, "granite", FaceTextures::All("granite".to_string()))).is_err());
+        let mut bright = definition(20, "sun", FaceTextures::Invisible);
+        bright.light = 16;
+        assert!(with(bright).is_err());
+
+        let without_stone = builtin_definitions().into_iter().filter(|block| block.name != "stone").collect();
+        assert!(from_definitions(without_stone).is_err());
+    }
+
+    #[test]
+    fn syntax_errors_are_reported() {
+        let err = BlockRegistry::parse("[(id: 0, name: \"air\"", &TextureAtlas::builtin()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
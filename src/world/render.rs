@@ -1,13 +1,22 @@
 use std::fmt::Debug;
-use std::ops::Add;
 
 use derive_more::{Deref, DerefMut};
+use macroquad::models::Vertex;
 use macroquad::prelude::*;
 
 use super::*;
+use crate::world::light::Light;
+use crate::world::render::face::{FaceGeometry, TextureRotation, FACES};
+use crate::world::render::occlusion::FaceOcclusion;
+use crate::world::render::render_cube_byte::RenderCubeByte;
 
+pub mod atlas;
+pub mod face;
+pub mod frustum;
+pub mod material;
 pub mod mesh;
 pub mod model;
+pub mod occlusion;
 pub mod render_cube_byte;
 
 #[derive(Debug, Default, Clone, PartialEq)]
@@ -20,12 +29,9 @@ impl ChunkModel {
     //faster than default!
     pub fn new_empty() -> Self {
         let ml = ModelLayer([[BlockModel::EMPTY; CHUNK_SIZE_16]; CHUNK_SIZE_16]);
-        Self { 0: Some([ml; CHUNK_SIZE_16]) }
+        Self(Some([ml; CHUNK_SIZE_16]))
     }
     pub fn set(&mut self, x: usize, y: usize, z: usize, model: BlockModel) {
-        let side_px = model.render_byte.get_bit(3);
-        let side_pz = model.render_byte.get_bit(1);
-        if (side_px || side_pz) && y != 0 {}
         match &mut self.0 {
             Some(arr) => *arr[y].get_mut(x, z) = model,
             None => {
@@ -43,17 +49,11 @@ impl ChunkModel {
             None => true,
         }
     }
-    pub fn get_render_byte(&self, x: usize, y: usize, z: usize) -> Option<RenderCubeByte> {
-        if let Some(model) = self.0 {
-            Some(model[y].get(x, z).render_byte)
-        } else {
-            None
-        }
+    pub fn get(&self, x: usize, y: usize, z: usize) -> BlockModel {
+        self.0.map_or(BlockModel::EMPTY, |model| *model[y].get(x, z))
     }
-    pub fn set_render_byte(&mut self, x: usize, y: usize, z: usize, new_byte: RenderCubeByte) {
-        if let Some(mut model) = self.0 {
-            model[y].get_mut(x, z).render_byte = new_byte;
-        }
+    pub fn get_render_byte(&self, x: usize, y: usize, z: usize) -> Option<RenderCubeByte> {
+        self.0.map(|model| model[y].get(x, z).render_byte)
     }
 }
 
@@ -77,7 +77,7 @@ impl ModelLayer {
     }
 }
 
-struct ChunkPlusConnected<'ch, 'to, 'bo, 'px, 'nx, 'pz, 'nz> {
+pub struct ChunkPlusConnected<'ch, 'to, 'bo, 'px, 'nx, 'pz, 'nz> {
     chunk: &'ch Chunk,
     top: &'to ChunkLayer,
     bottom: &'bo ChunkLayer,
@@ -89,20 +89,25 @@ struct ChunkPlusConnected<'ch, 'to, 'bo, 'px, 'nx, 'pz, 'nz> {
 
 #[rustfmt::skip]
 impl<'ch, 'to, 'bo, 'px, 'nx, 'pz, 'nz> ChunkPlusConnected<'ch, 'to, 'bo, 'px, 'nx, 'pz, 'nz> {
+    pub fn new(chunk: &'ch Chunk, connected: &ConnectedChunks<'to, 'bo, 'px, 'nx, 'pz, 'nz>) -> Self {
+        let ConnectedChunks { top, bottom, px, nx, pz, nz } = *connected;
+        Self { chunk, top, bottom, px, nx, pz, nz }
+    }
+
     /// (usize, usize, usize) - (x, y, z) pos in chunk 0..16
     #[rustfmt::skip]
-    fn connected_blocks(&self, x: usize, y: usize, z: usize) -> ConnectedBlocks {
+    pub fn connected_blocks(&self, x: usize, y: usize, z: usize) -> ConnectedBlocks<'_, '_, '_, '_, '_, '_> {
 
-        let top = if y == 15 { &self.top.get(x, z) } else { &self.chunk.get(x, y + 1, z) };
-        let bottom = if y == 0 { &self.bottom.get(x, z) } else { &self.chunk.get(x, y - 1, z) };
+        let top = if y == 15 { self.top.get(x, z) } else { self.chunk.get(x, y + 1, z) };
+        let bottom = if y == 0 { self.bottom.get(x, z) } else { self.chunk.get(x, y - 1, z) };
 
-        let px = if x == 15 { &self.px.get(x, z) } else { &self.chunk.get(x + 1, y, z) };
-        let nx = if x == 0 { &self.nx.get(x, z) } else { &self.chunk.get(x - 1, y, z) };
+        let px = if x == 15 { self.px.get(y, z) } else { self.chunk.get(x + 1, y, z) };
+        let nx = if x == 0 { self.nx.get(y, z) } else { self.chunk.get(x - 1, y, z) };
 
-        let pz = if z == 15 { &self.pz.get(x, z) } else { &self.chunk.get(x, y, z + 1) };
-        let nz = if z == 0 { &self.nz.get(x, z) } else { &self.chunk.get(x, y, z - 1) };
+        let pz = if z == 15 { self.pz.get(x, y) } else { self.chunk.get(x, y, z + 1) };
+        let nz = if z == 0 { self.nz.get(x, y) } else { self.chunk.get(x, y, z - 1) };
 
-        ConnectedBlocks::new(&top, &bottom, &px, &nx, &pz, &nz)
+        ConnectedBlocks::new(top, bottom, px, nx, pz, nz)
     }
 }
 
@@ -114,107 +119,126 @@ pub struct WorldPos {
 }
 
 
-#[derive(Deref, Clone, Copy, PartialEq)]
-pub struct UvTexture(Vec2);
+/// Tile number in the `TextureAtlas`, turned by `rotation` on the face
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct UvTexture {
+    tile: u16,
+    rotation: TextureRotation,
+}
 
+#[allow(dead_code)]
 impl UvTexture {
-    pub const fn from_n(n: usize) -> UvTexture {
-        UvTexture::new(vec2(0., 0.01 * n as f32))
-    }
-    pub const fn new(inner: Vec2) -> UvTexture {
-        Self(inner)
-    }
-
-    pub const fn up_left(&self) -> Vec2 {
-        self.0
-    }
-
-    pub const fn up_right(&self) -> Vec2 {
-        vec2(self.0.x + 1., self.0.y + 0.)
-    }
-
-    pub const fn low_left(&self) -> Vec2 {
-        vec2(self.0.x + 0., self.0.y + 0.01)
+    pub const fn new(index: u16) -> UvTexture {
+        Self { tile: index, rotation: TextureRotation::R0 }
+    }
+
+    /// The same tile turned, like a log lying on its side
+    pub const fn rotated(self, rotation: TextureRotation) -> UvTexture {
+        Self { rotation, ..self }
+    }
+
+    pub fn rotation(&self) -> TextureRotation {
+        self.rotation
+    }
+
+    /// Atlas tile of the texture, the chunk material reads it from `Vertex::normal.w`
+    pub fn tile_index(&self) -> f32 {
+        self.tile as f32
+    }
+
+    /// `size` - face size in blocks, 1.0 along the side normal.
+    /// Uv are counted in blocks too, so the texture repeats over merged faces
+    pub fn get_vertices(
+        &self,
+        pos: WorldPos,
+        side: BlockSide,
+        size: Vec3,
+        light: Light,
+        occlusion: FaceOcclusion,
+        shading: &FaceShading,
+    ) -> [Vertex; 4] {
+        let origin = vec3(pos.x, pos.y, pos.z);
+        let tile = self.tile_index();
+        let geometry = side.geometry();
+        let normal = Vec3::from(geometry.normal);
+        let (u_axis, v_axis) = geometry.uv_axes;
+        let extent = vec2(size[u_axis], size[v_axis]);
+        let shade = shading.factor(side);
+        arr_fn(|idx| {
+            let corner = Vec3::from(geometry.corners[idx]) * size;
+            let uv = geometry.corner_uv(idx, self.rotation, extent);
+            let color = light_color(light, occlusion.corner(idx), shade);
+            vertex(origin + corner, uv, normal, tile, color)
+        })
     }
+}
 
-    pub const fn low_right(&self) -> Vec2 {
-        vec2(self.0.x + 1., self.0.y + 0.01)
+impl Debug for UvTexture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("UvTexture").field(&self.tile).field(&self.rotation).finish()
     }
+}
 
-    pub const DIRT: UvTexture = UvTexture::from_n(0);
-    pub const GRASS_SIDE: UvTexture = UvTexture::from_n(1);
-    pub const GRASS_TOP: UvTexture = UvTexture::from_n(2);
-    pub const STONE: UvTexture = UvTexture::from_n(3);
-    pub const SAND: UvTexture = UvTexture::from_n(4);
-    pub fn get_vertices(&self, pos: WorldPos, side: BlockSide) -> Vec<Vertex> {
-        let coef = side.get_coef();
-        let corners = match side {
-            BlockSide::Py => [self.low_left(), self.low_right(), self.up_right(), self.low_right()],
-            BlockSide::Ny => [self.low_left(), self.low_right(), self.up_right(), self.low_right()],
-            BlockSide::Px => [self.low_left(), self.low_right(), self.up_right(), self.up_left()],
-            BlockSide::Nx => [self.low_right(), self.low_left(), self.up_left(), self.up_right()],
-            BlockSide::Pz => [self.low_right(), self.low_left(), self.up_left(), self.up_right()],
-            BlockSide::Nz => [self.low_right(), self.low_left(), self.up_left(), self.up_right()],
-        };
-        let (x, y, z) = (pos.x, pos.y, pos.z);
-        let mut ans = vec![];
-        ans.push(vertex(vec3(coef[0][0] + x, coef[0][1] + y, coef[0][2] + z), corners[0]));
-        ans.push(vertex(vec3(coef[1][0] + x, coef[1][1] + y, coef[1][2] + z), corners[1]));
-        ans.push(vertex(vec3(coef[2][0] + x, coef[2][1] + y, coef[2][2] + z), corners[2]));
-        ans.push(vertex(vec3(coef[3][0] + x, coef[3][1] + y, coef[3][2] + z), corners[3]));
-        ans
-    }
+/// Brightness of light level 0, faces in the dark are still seen a bit
+const MIN_BRIGHTNESS: f32 = 0.08;
+/// Each light level is this much as bright as the next one
+const LIGHT_FALLOFF: f32 = 0.8;
+/// Brightness of a face corner by its ambient occlusion, see `FaceOcclusion`
+const OCCLUSION_BRIGHTNESS: [f32; 4] = [0.5, 0.7, 0.85, 1.0];
+
+/// Brightness of the faces by the side they look to, as if lit by a fixed light from above.
+/// Makes the top and the sides of a block tell apart
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaceShading {
+    pub top: f32,
+    pub bottom: f32,
+    /// `Px` and `Nx` sides
+    pub x: f32,
+    /// `Pz` and `Nz` sides
+    pub z: f32,
 }
 
-impl Debug for UvTexture {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_tuple("UvTexture")
-            .field(&((self.0.y * 100.) as usize))
-            .finish()
+#[allow(dead_code)]
+impl FaceShading {
+    pub const DEFAULT: Self = Self { top: 1.0, bottom: 0.5, x: 0.8, z: 0.65 };
+    /// Every side as bright as the top
+    pub const FLAT: Self = Self { top: 1.0, bottom: 1.0, x: 1.0, z: 1.0 };
+
+    pub fn factor(&self, side: BlockSide) -> f32 {
+        match side {
+            BlockSide::Py => self.top,
+            BlockSide::Ny => self.bottom,
+            BlockSide::Px | BlockSide::Nx => self.x,
+            BlockSide::Pz | BlockSide::Nz => self.z,
+        }
     }
 }
 
-impl Default for UvTexture {
+impl Default for FaceShading {
     fn default() -> Self {
-        Self::from_n(3)
+        Self::DEFAULT
     }
 }
 
-use macroquad::models::Vertex;
-use crate::world::render::mesh::PLANE_IND;
-use crate::world::render::render_cube_byte::RenderCubeByte;
-
-const WHITE: [u8; 4] = [u8::MAX, u8::MAX, u8::MAX, u8::MAX];
+/// Vertex colour of a face corner lit with `light`, shaded by `occlusion` and by the `shade` of its side,
+/// the chunk material multiplies the texture by it
+fn light_color(light: Light, occlusion: u8, shade: f32) -> [u8; 4] {
+    let falloff = LIGHT_FALLOFF.powi((Light::MAX - light.level()) as i32);
+    let brightness = (MIN_BRIGHTNESS + (1.0 - MIN_BRIGHTNESS) * falloff) * OCCLUSION_BRIGHTNESS[occlusion as usize] * shade;
+    let value = (brightness.clamp(0.0, 1.0) * 255.0).round() as u8;
+    [value, value, value, u8::MAX]
+}
 
-const fn vertex(pos: Vec3, uv: Vec2) -> Vertex {
+/// `normal.w` is the atlas tile, see `material::load_chunk_material`
+const fn vertex(pos: Vec3, uv: Vec2, normal: Vec3, tile: f32, color: [u8; 4]) -> Vertex {
     Vertex {
-        normal: Vec4::ONE,
+        normal: Vec4::new(normal.x, normal.y, normal.z, tile),
         position: pos,
         uv,
-        color: WHITE,
+        color,
     }
 }
 
-// #[derive(Debug, Clone, Copy)]
-// pub struct BlockPos {
-//     pub x: isize,
-//     pub y: isize,
-//     pub z: isize,
-// }
-
-// impl Add for BlockPos {
-//     type Output = BlockPos;
-//
-//     fn add(self, rhs: Self) -> Self::Output {
-//         let BlockPos { x, y, z } = self;
-//         BlockPos {
-//             x: x + rhs.x,
-//             y: y + rhs.y,
-//             z: z + rhs.z,
-//         }
-//     }
-// }
-
 #[allow(dead_code)]
 pub struct ConnectedBlocks<'to, 'bo, 'px, 'nx, 'pz, 'nz> {
     pub top: &'to BlockState,
@@ -240,9 +264,21 @@ impl<'to, 'bo, 'px, 'nx, 'pz, 'nz> ConnectedBlocks<'to, 'bo, 'px, 'nx, 'pz, 'nz>
     ) -> Self {
         Self { top, bottom, px, nx, pz, nz }
     }
+
+    /// Sides where the neighbour hides the face of `block`, in `RenderCubeByte` bits order
+    pub fn neighbours(&self, block: BlockType) -> RenderCubeByte {
+        let mut ans = RenderCubeByte::ALL;
+        let blocks = [self.nz, self.pz, self.nx, self.px, self.bottom, self.top];
+        for (pos, neighbour) in blocks.iter().enumerate() {
+            ans.set_bit(pos, neighbour.block_type.hides_face_of(block));
+        }
+        ans
+    }
 }
 
-#[allow(dead_code)]
+/// Border layers of the neighbour chunks touching the chunk.
+/// `top` and `bottom` are indexed (x, z), `px` and `nx` - (y, z), `pz` and `nz` - (x, y)
+#[derive(Clone, Copy)]
 pub struct ConnectedChunks<'to, 'bo, 'px, 'nx, 'pz, 'nz> {
     pub top: &'to ChunkLayer,
     pub bottom: &'bo ChunkLayer,
@@ -260,6 +296,12 @@ impl<'to, 'bo, 'px, 'nx, 'pz, 'nz> ConnectedChunks<'to, 'bo, 'px, 'nx, 'pz, 'nz>
         &ChunkLayer::EMPTY, &ChunkLayer::EMPTY, &ChunkLayer::EMPTY,
     );
 
+    pub const SOLID: ConnectedChunks<'static, 'static, 'static, 'static, 'static, 'static>
+    = ConnectedChunks::new(
+        &ChunkLayer::SOLID, &ChunkLayer::SOLID, &ChunkLayer::SOLID,
+        &ChunkLayer::SOLID, &ChunkLayer::SOLID, &ChunkLayer::SOLID,
+    );
+
     pub const fn new(
         top: &'to ChunkLayer, bottom: &'bo ChunkLayer,
         px: &'px ChunkLayer, nx: &'nx ChunkLayer,
@@ -269,70 +311,10 @@ impl<'to, 'bo, 'px, 'nx, 'pz, 'nz> ConnectedChunks<'to, 'bo, 'px, 'nx, 'pz, 'nz>
     }
 }
 
-pub enum MyTexture {
-    Transparent,
-    AllSides(UvTexture),
-    Grass {
-        top: UvTexture,
-        side: UvTexture,
-        bottom: UvTexture,
-    },
-}
-
-#[allow(dead_code)]
-impl MyTexture {
-    const fn top(&self) -> Option<UvTexture> {
-        match self {
-            MyTexture::Transparent => None,
-            MyTexture::AllSides(texture) => Some(*texture),
-            MyTexture::Grass { top, .. } => Some(*top),
-        }
-    }
-
-    const fn bottom(&self) -> Option<UvTexture> {
-        match self {
-            MyTexture::Transparent => None,
-            MyTexture::AllSides(texture) => Some(*texture),
-            MyTexture::Grass { bottom, .. } => Some(*bottom),
-        }
-    }
-
-    const fn px(&self) -> Option<UvTexture> {
-        match self {
-            MyTexture::Transparent => None,
-            MyTexture::AllSides(texture) => Some(*texture),
-            MyTexture::Grass { side, .. } => Some(*side),
-        }
-    }
-
-    const fn pz(&self) -> Option<UvTexture> {
-        self.px()
-    }
-
-    const fn nx(&self) -> Option<UvTexture> {
-        self.px()
-    }
-
-    const fn nz(&self) -> Option<UvTexture> {
-        self.px()
-    }
-}
-
-// TODO: Make texture depend on connected block
-// For example dirt will need to be merged with gravel etc.
-#[allow(dead_code)]
-const fn my_texture(bs: &BlockState, _conn: &ConnectedBlocks) -> MyTexture {
-    match bs.block_type {
-        BlockType::Air => MyTexture::Transparent,
-        BlockType::Dirt => MyTexture::AllSides(UvTexture::DIRT),
-        BlockType::Grass => MyTexture::Grass {
-            top: UvTexture::GRASS_TOP,
-            side: UvTexture::GRASS_SIDE,
-            bottom: UvTexture::DIRT,
-        },
-        BlockType::Stone => MyTexture::AllSides(UvTexture::STONE),
-        BlockType::Sand => MyTexture::AllSides(UvTexture::SAND),
-        BlockType::Tile => MyTexture::AllSides(UvTexture::SAND),
+impl<'a> ConnectedChunks<'a, 'a, 'a, 'a, 'a, 'a> {
+    /// `layers` in `RenderCubeByte` bits order, see `model::neighbour_border_layers`
+    pub const fn from_layers(layers: &'a [ChunkLayer; 6]) -> Self {
+        Self::new(&layers[5], &layers[4], &layers[3], &layers[2], &layers[1], &layers[0])
     }
 }
 
@@ -340,11 +322,18 @@ const fn my_texture(bs: &BlockState, _conn: &ConnectedBlocks) -> MyTexture {
 pub struct BlockModel {
     pub render_byte: RenderCubeByte,
     block_type: BlockType,
+    /// Light of the neighbour in front of each visible face, in `RenderCubeByte` bits order.
+    /// Hidden faces are dark
+    light: [Light; 6],
+    /// Ambient occlusion of the visible faces, hidden faces are open
+    occlusion: [FaceOcclusion; 6],
 }
 impl BlockModel {
     const EMPTY: Self = BlockModel {
         render_byte: RenderCubeByte::NOTHING,
-        block_type: BlockType::Air,
+        block_type: BlockType::AIR,
+        light: [Light::DARK; 6],
+        occlusion: [FaceOcclusion::OPEN; 6],
     };
 }
 
@@ -354,27 +343,6 @@ impl Default for BlockModel {
     }
 }
 
-impl BlockModel {
-    pub fn get_meshes(&self, atlas: &Texture2D, pos: WorldPos) -> Vec<Mesh> {
-        let mut ans = vec![];
-        let textures = self.block_type.get_textures();
-        for side_idx in 0..6 {
-            if self.render_byte.bool_in_pos(side_idx) {
-                if let Some(uv_texture) = textures[side_idx] {
-                    let side = BlockSide::from_position(side_idx);
-                    let vertices = uv_texture.get_vertices(pos, side);
-                    ans.push(Mesh {
-                        vertices,
-                        indices: PLANE_IND.to_vec(),
-                        texture: Some(atlas.clone()),
-                    })
-                }
-            }
-        }
-        ans
-    }
-}
-
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum BlockSide {
     Py,
@@ -395,15 +363,50 @@ impl BlockSide {
             _ => Self::Nz
         }
     }
-    fn get_coef(&self) -> [[f32; 3]; 4] {
+    /// Step to the neighbour block on this side
+    pub const fn offset(&self) -> BlockPos {
+        match self {
+            BlockSide::Py => BlockPos::new(0, 1, 0),
+            BlockSide::Ny => BlockPos::new(0, -1, 0),
+            BlockSide::Px => BlockPos::new(1, 0, 0),
+            BlockSide::Nx => BlockPos::new(-1, 0, 0),
+            BlockSide::Pz => BlockPos::new(0, 0, 1),
+            BlockSide::Nz => BlockPos::new(0, 0, -1),
+        }
+    }
+    /// Bit of the side in `RenderCubeByte`, `from_position` the other way
+    pub const fn position(&self) -> usize {
+        match self {
+            BlockSide::Py => 5,
+            BlockSide::Ny => 4,
+            BlockSide::Px => 3,
+            BlockSide::Nx => 2,
+            BlockSide::Pz => 1,
+            BlockSide::Nz => 0,
+        }
+    }
+    pub const fn opposite(&self) -> Self {
         match self {
-            BlockSide::Py => [[0.0, 1.0, 0.0], [1.0, 1.0, 0.0], [1.0, 1.0, 1.0], [0.0, 1.0, 1.0]],
-            BlockSide::Ny => [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 0.0, 1.0], [0.0, 0.0, 1.0]],
-            BlockSide::Px => [[1.0, 0.0, 0.0], [1.0, 0.0, 1.0], [1.0, 1.0, 1.0], [1.0, 1.0, 0.0]],
-            BlockSide::Nx => [[0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 1.0], [0.0, 1.0, 0.0]],
-            BlockSide::Pz => [[0.0, 0.0, 1.0], [1.0, 0.0, 1.0], [1.0, 1.0, 1.0], [0.0, 1.0, 1.0]],
-            BlockSide::Nz => [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]],
+            BlockSide::Py => BlockSide::Ny,
+            BlockSide::Ny => BlockSide::Py,
+            BlockSide::Px => BlockSide::Nx,
+            BlockSide::Nx => BlockSide::Px,
+            BlockSide::Pz => BlockSide::Nz,
+            BlockSide::Nz => BlockSide::Pz,
         }
     }
+    /// (normal, u, v) axes of the side plane, 0 - x, 1 - y, 2 - z
+    const fn axes(&self) -> (usize, usize, usize) {
+        match self {
+            BlockSide::Py | BlockSide::Ny => (1, 0, 2),
+            BlockSide::Px | BlockSide::Nx => (0, 2, 1),
+            BlockSide::Pz | BlockSide::Nz => (2, 0, 1),
+        }
+    }
+
+    /// Corners, uv and normal of the face on this side
+    pub fn geometry(&self) -> &'static FaceGeometry {
+        &FACES[self.position()]
+    }
 }
 
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use macroquad::math::{vec4, Rect, Vec4};
+use macroquad::prelude::{Color, Image, ImageFormat};
+
+use crate::world::render::UvTexture;
+
+/// Pixels around each tile repeating its border, so filtering near the tile edge
+/// never picks up the neighbour tile
+pub const PADDING: usize = 2;
+
+/// Tiles the game ships with, used when there is no texture directory
+const BUILTIN_TILES: [(&str, &[u8]); 14] = [
+    ("crafting_table_side", include_bytes!("../../../assets/textures/crafting_table_side.png")),
+    ("crafting_table_top", include_bytes!("../../../assets/textures/crafting_table_top.png")),
+    ("dirt", include_bytes!("../../../assets/textures/dirt.png")),
+    ("glass", include_bytes!("../../../assets/textures/glass.png")),
+    ("glowstone", include_bytes!("../../../assets/textures/glowstone.png")),
+    ("grass_side", include_bytes!("../../../assets/textures/grass_side.png")),
+    ("grass_top", include_bytes!("../../../assets/textures/grass_top.png")),
+    ("leaves", include_bytes!("../../../assets/textures/leaves.png")),
+    ("log_side", include_bytes!("../../../assets/textures/log_side.png")),
+    ("log_top", include_bytes!("../../../assets/textures/log_top.png")),
+    ("planks", include_bytes!("../../../assets/textures/planks.png")),
+    ("sand", include_bytes!("../../../assets/textures/sand.png")),
+    ("stone", include_bytes!("../../../assets/textures/stone.png")),
+    ("water", include_bytes!("../../../assets/textures/water.png")),
+];
+
+/// Square tiles packed into a square power of two image as a grid of padded cells.
+/// Tiles are numbered row by row in name order, `UvTexture` is that number
+#[derive(Clone)]
+pub struct TextureAtlas {
+    pub image: Image,
+    tile_size: usize,
+    columns: usize,
+    textures: HashMap<String, UvTexture>,
+}
+
+#[allow(dead_code)]
+impl TextureAtlas {
+    /// All tiles must be square and of the same size
+    pub fn build(mut tiles: Vec<(String, Image)>) -> io::Result<Self> {
+        tiles.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let tile_size = match tiles.first() {
+            Some((_, image)) => image.width as usize,
+            None => return Err(invalid_data("no textures".to_string())),
+        };
+        for (name, image) in &tiles {
+            if image.width as usize != tile_size || image.height as usize != tile_size {
+                return Err(invalid_data(format!("{}: textures must be {}x{}", name, tile_size, tile_size)));
+            }
+        }
+
+        let cell = tile_size + 2 * PADDING;
+        let min_columns = (tiles.len() as f32).sqrt().ceil() as usize;
+        let size = (min_columns * cell).next_power_of_two();
+        let columns = size / cell;
+        let mut image = Image::gen_image_color(size as u16, size as u16, Color::new(0., 0., 0., 0.));
+        let mut textures = HashMap::new();
+        for (index, (name, tile)) in tiles.into_iter().enumerate() {
+            let (cell_x, cell_y) = (index % columns * cell, index / columns * cell);
+            for y in 0..cell {
+                for x in 0..cell {
+                    let tile_x = x.saturating_sub(PADDING).min(tile_size - 1);
+                    let tile_y = y.saturating_sub(PADDING).min(tile_size - 1);
+                    let from = (tile_y * tile_size + tile_x) * 4;
+                    let to = ((cell_y + y) * size + cell_x + x) * 4;
+                    image.bytes[to..to + 4].copy_from_slice(&tile.bytes[from..from + 4]);
+                }
+            }
+            textures.insert(name, UvTexture::new(index as u16));
+        }
+        Ok(Self { image, tile_size, columns, textures })
+    }
+
+    /// Every `*.png` in `dir`, named by the file name without the extension
+    pub fn load_dir(dir: &Path) -> io::Result<Self> {
+        let mut tiles = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension != "png") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            tiles.push((name.to_string(), decode_png(name, &fs::read(&path)?)?));
+        }
+        Self::build(tiles)
+    }
+
+    pub fn builtin() -> Self {
+        let tiles = BUILTIN_TILES
+            .iter()
+            .map(|(name, bytes)| decode_png(name, bytes).map(|image| (name.to_string(), image)))
+            .collect::<io::Result<_>>();
+        tiles.and_then(Self::build).expect("assets/textures are valid")
+    }
+
+    pub fn texture(&self, name: &str) -> Option<UvTexture> {
+        self.textures.get(name).copied()
+    }
+
+    fn size(&self) -> usize {
+        self.image.width as usize
+    }
+
+    /// Where the tile is in the image, in uv
+    pub fn uv_rect(&self, texture: UvTexture) -> Rect {
+        let index = texture.tile_index() as usize;
+        let cell = self.tile_size + 2 * PADDING;
+        let x = index % self.columns * cell + PADDING;
+        let y = index / self.columns * cell + PADDING;
+        let size = self.size() as f32;
+        Rect::new(x as f32 / size, y as f32 / size, self.tile_size as f32 / size, self.tile_size as f32 / size)
+    }
+
+    /// Texture name to uv rect of every tile
+    pub fn uv_rects(&self) -> HashMap<&str, Rect> {
+        self.textures.iter().map(|(name, texture)| (name.as_str(), self.uv_rect(*texture))).collect()
+    }
+
+    /// (columns, cell size, padding, tile size) with sizes in uv, the chunk material `AtlasGrid`
+    pub fn grid(&self) -> Vec4 {
+        let size = self.size() as f32;
+        let cell = (self.tile_size + 2 * PADDING) as f32;
+        vec4(self.columns as f32, cell / size, PADDING as f32 / size, self.tile_size as f32 / size)
+    }
+}
+
+fn decode_png(name: &str, bytes: &[u8]) -> io::Result<Image> {
+    Image::from_file_with_format(bytes, Some(ImageFormat::Png)).map_err(|err| invalid_data(format!("{}: {}", name, err)))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile(size: u16, value: u8) -> Image {
+        Image { bytes: vec![value; size as usize * size as usize * 4], width: size, height: size }
+    }
+
+    fn pixel(image: &Image, x: usize, y: usize) -> &[u8] {
+        let i = (y * image.width as usize + x) * 4;
+        &image.bytes[i..i + 4]
+    }
+
+    #[test]
+    fn builtin_tiles_are_packed() {
+        let atlas = TextureAtlas::builtin();
+        assert_eq!((atlas.image.width, atlas.image.height), (128, 128));
+        assert_eq!(atlas.columns, 6);
+        let names = [
+            "crafting_table_side", "crafting_table_top", "dirt", "glass", "glowstone", "grass_side", "grass_top",
+            "leaves", "log_side", "log_top", "planks", "sand", "stone", "water",
+        ];
+        for (index, name) in names.iter().enumerate() {
+            assert_eq!(atlas.texture(name), Some(UvTexture::new(index as u16)));
+        }
+        assert_eq!(atlas.texture("granite"), None);
+        assert_eq!(atlas.uv_rects().len(), names.len());
+    }
+
+    #[test]
+    fn padding_repeats_the_border() {
+        let mut first = tile(4, 10);
+        first.bytes[0..4].copy_from_slice(&[1, 2, 3, 4]);
+        let atlas = TextureAtlas::build(vec![("b".to_string(), tile(4, 20)), ("a".to_string(), first)]).unwrap();
+        assert_eq!(atlas.image.width, 16);
+        assert_eq!(atlas.texture("a"), Some(UvTexture::new(0)));
+        for (x, y) in [(0, 0), (2, 0), (0, 2), (2, 2)] {
+            assert_eq!(pixel(&atlas.image, x, y), &[1, 2, 3, 4]);
+        }
+        assert_eq!(pixel(&atlas.image, 7, 7), &[10; 4]);
+        // "b" is the next cell to the right, its padding too
+        assert_eq!(pixel(&atlas.image, 8, 0), &[20; 4]);
+        assert_eq!(pixel(&atlas.image, 15, 7), &[20; 4]);
+    }
+
+    #[test]
+    fn uv_rects_point_inside_padding() {
+        let tiles = (0..5).map(|i| (i.to_string(), tile(16, i))).collect();
+        let atlas = TextureAtlas::build(tiles).unwrap();
+        let size = 64.0;
+        let rect = atlas.uv_rect(atlas.texture("4").unwrap());
+        assert_eq!(rect, Rect::new(22.0 / size, 22.0 / size, 16.0 / size, 16.0 / size));
+        let grid = atlas.grid();
+        assert_eq!(grid, vec4(3.0, 20.0 / size, 2.0 / size, 16.0 / size));
+        // the shader way: cell origin plus padding
+        assert_eq!(rect.x, 1.0 * grid.y + grid.z);
+    }
+
+    #[test]
+    fn bad_tiles_are_rejected() {
+        assert!(TextureAtlas::build(vec![]).is_err());
+        let mismatched = vec![("a".to_string(), tile(16, 0)), ("b".to_string(), tile(8, 0))];
+        assert!(TextureAtlas::build(mismatched).is_err());
+        let not_square = Image { bytes: vec![0; 16 * 8 * 4], width: 16, height: 8 };
+        assert!(TextureAtlas::build(vec![("a".to_string(), not_square)]).is_err());
+        assert!(decode_png("a", b"not a png").is_err());
+    }
+}
@@ -0,0 +1,163 @@
+use macroquad::prelude::{vec2, Vec2};
+use serde::Deserialize;
+
+/// One face of the unit block cube
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaceGeometry {
+    /// Counter clockwise seen from outside, starting at the bottom left of the texture:
+    /// bottom left, bottom right, top right, top left
+    pub corners: [[f32; 3]; 4],
+    /// Texture coordinates of the `corners`, `v` goes down the texture
+    pub uvs: [[f32; 2]; 4],
+    /// Points out of the block
+    pub normal: [f32; 3],
+    /// Axes the texture `u` and `v` run along, 0 - x, 1 - y, 2 - z
+    pub uv_axes: (usize, usize),
+}
+
+/// Upright texture, the same for every face
+const UVS: [[f32; 2]; 4] = [[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]];
+
+/// In `RenderCubeByte` bits order: nz, pz, nx, px, ny, py.
+/// Side textures stand upright, the top one has its top to -z, the bottom one to +z
+pub const FACES: [FaceGeometry; 6] = [
+    FaceGeometry {
+        corners: [[1.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [1.0, 1.0, 0.0]],
+        uvs: UVS,
+        normal: [0.0, 0.0, -1.0],
+        uv_axes: (0, 1),
+    },
+    FaceGeometry {
+        corners: [[0.0, 0.0, 1.0], [1.0, 0.0, 1.0], [1.0, 1.0, 1.0], [0.0, 1.0, 1.0]],
+        uvs: UVS,
+        normal: [0.0, 0.0, 1.0],
+        uv_axes: (0, 1),
+    },
+    FaceGeometry {
+        corners: [[0.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 1.0], [0.0, 1.0, 0.0]],
+        uvs: UVS,
+        normal: [-1.0, 0.0, 0.0],
+        uv_axes: (2, 1),
+    },
+    FaceGeometry {
+        corners: [[1.0, 0.0, 1.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [1.0, 1.0, 1.0]],
+        uvs: UVS,
+        normal: [1.0, 0.0, 0.0],
+        uv_axes: (2, 1),
+    },
+    FaceGeometry {
+        corners: [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 0.0, 1.0], [0.0, 0.0, 1.0]],
+        uvs: UVS,
+        normal: [0.0, -1.0, 0.0],
+        uv_axes: (0, 2),
+    },
+    FaceGeometry {
+        corners: [[0.0, 1.0, 1.0], [1.0, 1.0, 1.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]],
+        uvs: UVS,
+        normal: [0.0, 1.0, 0.0],
+        uv_axes: (0, 2),
+    },
+];
+
+/// Turns of a face texture, clockwise as seen on the face, set per face in `blocks.ron`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum TextureRotation {
+    #[default]
+    R0,
+    R90,
+    R180,
+    R270,
+}
+
+impl TextureRotation {
+    fn quarter_turns(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl FaceGeometry {
+    /// Uv of the corner `idx` of a face `extent` blocks large along `uv_axes`,
+    /// the texture repeats once per block
+    pub fn corner_uv(&self, idx: usize, rotation: TextureRotation, extent: Vec2) -> Vec2 {
+        let turns = rotation.quarter_turns();
+        // a turned texture repeats along the other axis
+        let extent = if turns % 2 == 1 { vec2(extent.y, extent.x) } else { extent };
+        Vec2::from(self.uvs[(idx + turns) % 4]) * extent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::prelude::Vec3;
+
+    use super::*;
+
+    fn corners(face: &FaceGeometry) -> [Vec3; 4] {
+        face.corners.map(Vec3::from)
+    }
+
+    #[test]
+    fn corners_wind_counter_clockwise_from_outside() {
+        for face in &FACES {
+            let corners = corners(face);
+            let normal = Vec3::from(face.normal);
+            for idx in 0..4 {
+                let [a, b, c] = [0, 1, 2].map(|step| corners[(idx + step) % 4]);
+                assert_eq!((b - a).cross(c - b), normal, "{:?}", face);
+            }
+        }
+    }
+
+    #[test]
+    fn faces_lie_on_their_side_of_the_cube() {
+        for face in &FACES {
+            let normal = Vec3::from(face.normal);
+            let center = corners(face).iter().sum::<Vec3>() / 4.0;
+            assert_eq!(center - Vec3::splat(0.5), normal / 2.0);
+            assert_eq!(normal.length(), 1.0);
+        }
+    }
+
+    #[test]
+    fn uvs_follow_corners() {
+        for face in &FACES {
+            let corners = corners(face);
+            let (u_axis, v_axis) = face.uv_axes;
+            for a in 0..4 {
+                for b in 0..4 {
+                    let along = |axis: usize| (corners[b] - corners[a])[axis].abs();
+                    let uv = (Vec2::from(face.uvs[b]) - Vec2::from(face.uvs[a])).abs();
+                    assert_eq!(uv, vec2(along(u_axis), along(v_axis)), "{:?}", face);
+                }
+            }
+            // with the counter clockwise corners u goes right and v goes down seen from outside
+            assert_eq!(face.uvs, UVS);
+        }
+    }
+
+    #[test]
+    fn side_textures_stand_upright() {
+        for face in &FACES[..4] {
+            let corners = corners(face);
+            assert_eq!(face.uv_axes.1, 1);
+            assert!(corners[0].y == 0.0 && corners[1].y == 0.0);
+            assert!(corners[2].y == 1.0 && corners[3].y == 1.0);
+        }
+    }
+
+    #[test]
+    fn rotation_turns_uvs() {
+        let face = &FACES[1];
+        let extent = vec2(3.0, 2.0);
+        let uvs = |rotation| [0, 1, 2, 3].map(|idx| face.corner_uv(idx, rotation, extent));
+        assert_eq!(uvs(TextureRotation::R0), [vec2(0.0, 2.0), vec2(3.0, 2.0), vec2(3.0, 0.0), vec2(0.0, 0.0)]);
+        // the texture top is to the right, v runs along the bottom edge
+        assert_eq!(uvs(TextureRotation::R90), [vec2(2.0, 3.0), vec2(2.0, 0.0), vec2(0.0, 0.0), vec2(0.0, 3.0)]);
+        assert_eq!(uvs(TextureRotation::R180), [vec2(3.0, 0.0), vec2(0.0, 0.0), vec2(0.0, 2.0), vec2(3.0, 2.0)]);
+        for rotation in [TextureRotation::R0, TextureRotation::R90, TextureRotation::R180, TextureRotation::R270] {
+            // opposite corners stay opposite
+            let uvs = uvs(rotation);
+            assert_eq!(uvs[0] + uvs[2], uvs[1] + uvs[3]);
+        }
+    }
+}
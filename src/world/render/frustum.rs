@@ -0,0 +1,134 @@
+use macroquad::math::{vec3, Mat4, Vec3, Vec4, Vec4Swizzles};
+
+use crate::world::ChunkPos;
+use crate::world::CHUNK_SIZE_16;
+
+/// Points with `normal.dot(p) + distance >= 0` are on the inner side
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Plane {
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+impl Plane {
+    /// `coefs` is (a, b, c, d) of `ax + by + cz + d = 0`, normalized here
+    fn from_coefs(coefs: Vec4) -> Self {
+        let length = coefs.xyz().length();
+        Self {
+            normal: coefs.xyz() / length,
+            distance: coefs.w / length,
+        }
+    }
+
+    pub fn signed_distance(&self, point: Vec3) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+}
+
+/// Everything the camera can see, six planes facing inwards:
+/// left, right, bottom, top, near, far
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Frustum {
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Planes of an OpenGL style (clip z in -w..w) projection * view matrix,
+    /// like `Camera3D::matrix` (Gribb & Hartmann)
+    pub fn from_view_projection(matrix: Mat4) -> Self {
+        let rows = [matrix.row(0), matrix.row(1), matrix.row(2), matrix.row(3)];
+        let planes = [
+            rows[3] + rows[0],
+            rows[3] - rows[0],
+            rows[3] + rows[1],
+            rows[3] - rows[1],
+            rows[3] + rows[2],
+            rows[3] - rows[2],
+        ];
+        Self { planes: planes.map(Plane::from_coefs) }
+    }
+
+    /// False only if the box is surely outside: it is behind one of the planes
+    pub fn intersects_aabb(&self, min: Vec3, max: Vec3) -> bool {
+        self.planes.iter().all(|plane| {
+            // the corner furthest along the plane normal
+            let corner = Vec3::select(plane.normal.cmpge(Vec3::ZERO), max, min);
+            plane.signed_distance(corner) >= 0.0
+        })
+    }
+
+    pub fn intersects_chunk(&self, pos: ChunkPos) -> bool {
+        let origin = pos.origin();
+        let min = vec3(origin.x as f32, origin.y as f32, origin.z as f32);
+        self.intersects_aabb(min, min + Vec3::splat(CHUNK_SIZE_16 as f32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 90 degrees up and down, square screen, looking along -z from the origin
+    fn frustum() -> Frustum {
+        let projection = Mat4::perspective_rh_gl(90f32.to_radians(), 1.0, 0.1, 100.0);
+        let view = Mat4::look_at_rh(Vec3::ZERO, vec3(0.0, 0.0, -1.0), Vec3::Y);
+        Frustum::from_view_projection(projection * view)
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn planes_are_extracted() {
+        let [left, right, bottom, top, near, far] = frustum().planes;
+        let diagonal = 0.5f32.sqrt();
+        assert_close(left.normal, vec3(diagonal, 0.0, -diagonal));
+        assert_close(right.normal, vec3(-diagonal, 0.0, -diagonal));
+        assert_close(bottom.normal, vec3(0.0, diagonal, -diagonal));
+        assert_close(top.normal, vec3(0.0, -diagonal, -diagonal));
+        assert_close(near.normal, vec3(0.0, 0.0, -1.0));
+        assert_close(far.normal, vec3(0.0, 0.0, 1.0));
+        assert!((near.distance + 0.1).abs() < 1e-4);
+        assert!((far.distance - 100.0).abs() < 1e-2);
+        for plane in [left, right, bottom, top] {
+            assert!(plane.distance.abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn points_inside_and_outside() {
+        let frustum = frustum();
+        let inside = |point: Vec3| frustum.planes.iter().all(|plane| plane.signed_distance(point) >= 0.0);
+        assert!(inside(vec3(0.0, 0.0, -10.0)));
+        assert!(inside(vec3(9.0, -9.0, -10.0)));
+        assert!(!inside(vec3(11.0, 0.0, -10.0)));
+        assert!(!inside(vec3(0.0, 0.0, 10.0)));
+        assert!(!inside(vec3(0.0, 0.0, -0.05)));
+        assert!(!inside(vec3(0.0, 0.0, -101.0)));
+    }
+
+    #[test]
+    fn aabb_tests() {
+        let frustum = frustum();
+        let unit = Vec3::ONE;
+        // in front, behind, beside, beyond far
+        assert!(frustum.intersects_aabb(vec3(-0.5, -0.5, -5.5), vec3(-0.5, -0.5, -5.5) + unit));
+        assert!(!frustum.intersects_aabb(vec3(0.0, 0.0, 5.0), vec3(0.0, 0.0, 5.0) + unit));
+        assert!(!frustum.intersects_aabb(vec3(20.0, 0.0, -5.0), vec3(20.0, 0.0, -5.0) + unit));
+        assert!(!frustum.intersects_aabb(vec3(0.0, 0.0, -200.0), vec3(0.0, 0.0, -200.0) + unit));
+        // crossing a plane counts as visible
+        assert!(frustum.intersects_aabb(vec3(9.0, 0.0, -11.0), vec3(12.0, 1.0, -10.0)));
+        // around the camera
+        assert!(frustum.intersects_aabb(vec3(-1.0, -1.0, -1.0), unit));
+    }
+
+    #[test]
+    fn chunks_behind_are_culled() {
+        let frustum = frustum();
+        assert!(frustum.intersects_chunk(ChunkPos(0, 0, -2)));
+        assert!(frustum.intersects_chunk(ChunkPos(-1, -1, -1)));
+        assert!(!frustum.intersects_chunk(ChunkPos(0, 0, 1)));
+        assert!(!frustum.intersects_chunk(ChunkPos(5, 0, -1)));
+    }
+}
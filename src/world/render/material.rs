@@ -0,0 +1,104 @@
+use macroquad::miniquad::{BlendFactor, BlendState, BlendValue, CullFace, Equation, FrontFaceOrder};
+use macroquad::prelude::*;
+
+use crate::world::render::atlas::TextureAtlas;
+
+/// Opaque and cutout faces: pixels with alpha under this are not drawn at all
+const CUTOUT_ALPHA: f32 = 0.5;
+/// Translucent faces: only fully transparent pixels are skipped, so they leave no depth
+const TRANSLUCENT_ALPHA: f32 = 0.01;
+
+/// Chunk vertices carry uv in blocks and the atlas tile in `normal.w`,
+/// so one texture can be repeated over a greedy merged face.
+/// Faces are wound counter clockwise seen from outside, their backs are culled
+pub fn load_chunk_material(atlas: &TextureAtlas) -> Result<Material, macroquad::Error> {
+    let pipeline_params = PipelineParams {
+        depth_test: Comparison::LessOrEqual,
+        depth_write: true,
+        cull_face: CullFace::Back,
+        front_face_order: FrontFaceOrder::CounterClockwise,
+        ..Default::default()
+    };
+    chunk_material(atlas, pipeline_params, CUTOUT_ALPHA)
+}
+
+/// Blends faces over what was drawn before without writing depth,
+/// they have to be drawn last and back to front.
+/// Backs are not culled, a water surface is seen from below too
+pub fn load_translucent_chunk_material(atlas: &TextureAtlas) -> Result<Material, macroquad::Error> {
+    let pipeline_params = PipelineParams {
+        depth_test: Comparison::LessOrEqual,
+        depth_write: false,
+        color_blend: Some(BlendState::new(
+            Equation::Add,
+            BlendFactor::Value(BlendValue::SourceAlpha),
+            BlendFactor::OneMinusValue(BlendValue::SourceAlpha),
+        )),
+        ..Default::default()
+    };
+    chunk_material(atlas, pipeline_params, TRANSLUCENT_ALPHA)
+}
+
+fn chunk_material(atlas: &TextureAtlas, pipeline_params: PipelineParams, alpha_cutoff: f32) -> Result<Material, macroquad::Error> {
+    let material = load_material(
+        ShaderSource::Glsl {
+            vertex: CHUNK_VERTEX_SHADER,
+            fragment: CHUNK_FRAGMENT_SHADER,
+        },
+        MaterialParams {
+            pipeline_params,
+            uniforms: vec![
+                UniformDesc::new("AtlasGrid", UniformType::Float4),
+                UniformDesc::new("AlphaCutoff", UniformType::Float1),
+            ],
+            ..Default::default()
+        },
+    )?;
+    material.set_uniform("AtlasGrid", atlas.grid());
+    material.set_uniform("AlphaCutoff", alpha_cutoff);
+    Ok(material)
+}
+
+const CHUNK_VERTEX_SHADER: &str = r#"#version 100
+attribute vec3 position;
+attribute vec2 texcoord;
+attribute vec4 color0;
+attribute vec4 normal;
+
+varying lowp vec4 color;
+varying highp vec2 uv;
+varying highp vec2 tile_origin;
+
+uniform mat4 Model;
+uniform mat4 Projection;
+uniform vec4 AtlasGrid;
+
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1);
+    color = color0 / 255.0;
+    uv = texcoord;
+    float column = mod(normal.w, AtlasGrid.x);
+    float row = floor(normal.w / AtlasGrid.x);
+    tile_origin = vec2(column, row) * AtlasGrid.y + AtlasGrid.z;
+}
+"#;
+
+const CHUNK_FRAGMENT_SHADER: &str = r#"#version 100
+precision mediump float;
+
+varying lowp vec4 color;
+varying highp vec2 uv;
+varying highp vec2 tile_origin;
+
+uniform sampler2D Texture;
+uniform vec4 AtlasGrid;
+uniform float AlphaCutoff;
+
+void main() {
+    vec4 texel = texture2D(Texture, tile_origin + fract(uv) * AtlasGrid.w);
+    if (texel.a < AlphaCutoff) {
+        discard;
+    }
+    gl_FragColor = color * texel;
+}
+"#;
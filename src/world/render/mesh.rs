@@ -1,39 +1,608 @@
-use crate::world::render::{ChunkModel, WorldPos};
-use crate::world::CHUNK_SIZE_16;
-use macroquad::prelude::{Mesh, Texture2D};
-pub fn build_model_meshes(
-    model: &ChunkModel,
-    atlas: Texture2D,
-    chunk_pos: WorldPos
-) -> Vec<Mesh> {
-    let mut ans= vec![];
-    if let Some(layers) = model.0{
-        println!("Some");
-        for (y, layer) in layers.iter().enumerate() {
-            for x in 0..CHUNK_SIZE_16 {
-                for z in 0..CHUNK_SIZE_16 {
-                    let block = layer.get(x,z);
-                    if !block.render_byte.is_nothing() {
-                        let mut pos = WorldPos {
-                            x: x as f32 + chunk_pos.x,
-                            y: y as f32 + chunk_pos.y,
-                            z: z as f32 + chunk_pos.z,
-                        };
-                    let block_meshes = block.get_meshes(&atlas, pos.clone());
-                    ans.extend(block_meshes);
+use macroquad::models::Vertex;
+use macroquad::prelude::{Mesh, Texture2D, Vec3};
+
+use crate::world::light::Light;
+use crate::world::render::occlusion::FaceOcclusion;
+use crate::world::render::{BlockSide, ChunkModel, FaceShading, UvTexture, WorldPos};
+use crate::world::registry::Transparency;
+use crate::world::{BlockType, ChunkPos, CHUNK_SIZE_16};
+
+/// Vertices in one batched mesh stay below this, `u16` indices can't address more
+pub const MESH_VERTEX_LIMIT: usize = u16::MAX as usize + 1;
+/// Indices in one batched mesh stay below this
+pub const MESH_INDEX_LIMIT: usize = MESH_VERTEX_LIMIT / 4 * PLANE_IND.len();
+const MAX_QUADS_PER_MESH: usize = MESH_VERTEX_LIMIT / 4 - 1;
+
+/// CPU side of a batched mesh, no GPU needed to build or inspect it
+#[derive(Debug, Default, Clone)]
+pub struct MeshBuffers {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u16>,
+}
+
+impl MeshBuffers {
+    pub fn quad_count(&self) -> usize {
+        self.vertices.len() / 4
+    }
+
+    /// `flipped` quads are split along the 1-3 diagonal, see `FaceOcclusion::flips_diagonal`
+    fn push_quad(&mut self, vertices: [Vertex; 4], flipped: bool) {
+        let first = self.vertices.len() as u16;
+        let indices = if flipped { FLIPPED_PLANE_IND } else { PLANE_IND };
+        self.indices.extend(indices.map(|i| first + i));
+        self.vertices.extend(vertices);
+    }
+}
+
+/// Reorders the quads so the ones farthest from the `camera` are drawn first, blending needs it.
+/// Each 6 indices are one quad over 4 vertices in a row, see `MeshBuffers::push_quad`
+fn sort_quads_back_to_front(vertices: &[Vertex], indices: &mut [u16], camera: Vec3) {
+    let distance = |quad: &[u16]| {
+        let first = (quad[0] / 4 * 4) as usize;
+        let center = vertices[first..first + 4].iter().map(|vertex| vertex.position).sum::<Vec3>() / 4.0;
+        center.distance_squared(camera)
+    };
+    let mut quads: Vec<(f32, [u16; 6])> = indices
+        .chunks_exact(6)
+        .map(|quad| (distance(quad), quad.try_into().unwrap()))
+        .collect();
+    quads.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    for (slot, (_, quad)) in indices.chunks_exact_mut(6).zip(quads) {
+        slot.copy_from_slice(&quad);
+    }
+}
+
+/// All faces of a chunk, merged and split into as few meshes as `u16` indices allow.
+/// Opaque faces are split by the way they face, so the sides facing away from the camera
+/// are skipped whole. Translucent faces are kept apart, they are drawn after everything else
+#[derive(Debug, Default, Clone)]
+pub struct ChunkMesh {
+    /// Opaque and cutout faces, by side in `RenderCubeByte` bits order
+    pub opaque: [Vec<MeshBuffers>; 6],
+    pub translucent: Vec<MeshBuffers>,
+}
+
+impl ChunkMesh {
+    fn buffers(&self) -> impl Iterator<Item = &MeshBuffers> {
+        self.opaque.iter().flatten().chain(&self.translucent)
+    }
+
+    #[allow(dead_code)]
+    pub fn vertex_count(&self) -> usize {
+        self.buffers().map(|buffers| buffers.vertices.len()).sum()
+    }
+
+    #[allow(dead_code)]
+    pub fn index_count(&self) -> usize {
+        self.buffers().map(|buffers| buffers.indices.len()).sum()
+    }
+
+    #[allow(dead_code)]
+    pub fn quad_count(&self) -> usize {
+        self.buffers().map(MeshBuffers::quad_count).sum()
+    }
+
+    fn push_quad(&mut self, side: BlockSide, translucent: bool, vertices: [Vertex; 4], flipped: bool) {
+        let meshes = if translucent { &mut self.translucent } else { &mut self.opaque[side.position()] };
+        match meshes.last_mut() {
+            Some(buffers) if buffers.quad_count() < MAX_QUADS_PER_MESH => buffers.push_quad(vertices, flipped),
+            _ => {
+                let mut buffers = MeshBuffers::default();
+                buffers.push_quad(vertices, flipped);
+                meshes.push(buffers);
+            }
+        }
+    }
+
+    /// Meshes for the chunk materials, see `material::load_chunk_material`
+    pub fn to_meshes(&self, atlas: &Texture2D) -> ChunkDrawMeshes {
+        let upload = |meshes: &Vec<MeshBuffers>| {
+            meshes
+                .iter()
+                .map(|buffers| Mesh {
+                    vertices: buffers.vertices.clone(),
+                    indices: buffers.indices.clone(),
+                    texture: Some(atlas.clone()),
+                })
+                .collect()
+        };
+        ChunkDrawMeshes { opaque: self.opaque.each_ref().map(upload), translucent: upload(&self.translucent) }
+    }
+}
+
+/// `ChunkMesh` ready to draw, the two passes use different materials
+pub struct ChunkDrawMeshes {
+    pub opaque: [Vec<Mesh>; 6],
+    pub translucent: Vec<Mesh>,
+}
+
+impl ChunkDrawMeshes {
+    /// Redone when the camera moves to another block, see `sort_quads_back_to_front`
+    pub fn sort_translucent(&mut self, camera: Vec3) {
+        for mesh in &mut self.translucent {
+            sort_quads_back_to_front(&mesh.vertices, &mut mesh.indices, camera);
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Mesh> {
+        self.opaque.iter().flatten().chain(&self.translucent)
+    }
+
+    /// Opaque meshes of the chunk at `pos` with faces turned to the `camera`
+    pub fn opaque_facing(&self, pos: ChunkPos, camera: Vec3) -> impl Iterator<Item = &Mesh> {
+        self.opaque
+            .iter()
+            .enumerate()
+            .filter(move |(side_idx, _)| side_faces_camera(BlockSide::from_position(*side_idx), pos, camera))
+            .flat_map(|(_, meshes)| meshes)
+    }
+}
+
+/// Faces on `side` of the chunk at `pos` lie on the planes across the chunk,
+/// once the camera is behind the nearest one all of them are seen from the back
+pub fn side_faces_camera(side: BlockSide, pos: ChunkPos, camera: Vec3) -> bool {
+    let (axis, _, _) = side.axes();
+    let origin = pos.origin();
+    let low = [origin.x, origin.y, origin.z][axis] as f32;
+    if side.geometry().normal[axis] > 0.0 {
+        camera[axis] > low + 1.0
+    } else {
+        camera[axis] < low + (CHUNK_SIZE_16 - 1) as f32
+    }
+}
+
+/// Greedy meshing: visible faces of one side and one slice of the chunk
+/// with the same texture, light and ambient occlusion are merged into as large rectangles as possible.
+/// The light, the occlusion and the `shading` of the side are baked into the vertex colours
+pub fn build_chunk_mesh(model: &ChunkModel, chunk_pos: WorldPos, shading: &FaceShading) -> ChunkMesh {
+    let mut ans = ChunkMesh::default();
+    let Some(layers) = &model.0 else {
+        return ans;
+    };
+    for side_idx in 0..6 {
+        let side = BlockSide::from_position(side_idx);
+        let (normal_axis, u_axis, v_axis) = side.axes();
+        for depth in 0..CHUNK_SIZE_16 {
+            // texture, whether the face is translucent, its light and occlusion
+            let mut mask: FaceMask<(UvTexture, bool, Light, FaceOcclusion)> = [[None; CHUNK_SIZE_16]; CHUNK_SIZE_16];
+            for u in 0..CHUNK_SIZE_16 {
+                for v in 0..CHUNK_SIZE_16 {
+                    let mut pos = [0; 3];
+                    pos[normal_axis] = depth;
+                    pos[u_axis] = u;
+                    pos[v_axis] = v;
+                    let block = layers[pos[1]].get(pos[0], pos[2]);
+                    if block.render_byte.bool_in_pos(side_idx) {
+                        let translucent = block.block_type.transparency() == Transparency::Translucent;
+                        let light = block.light[side_idx];
+                        let occlusion = block.occlusion[side_idx];
+                        mask[u][v] = block.block_type.get_textures()[side_idx]
+                            .map(|texture| (texture, translucent, light, occlusion));
                     }
                 }
             }
+
+            for quad in greedy_quads(&mut mask) {
+                let mut origin = [0.0; 3];
+                origin[normal_axis] = depth as f32;
+                origin[u_axis] = quad.u as f32;
+                origin[v_axis] = quad.v as f32;
+                let mut size = [1.0; 3];
+                size[u_axis] = quad.width as f32;
+                size[v_axis] = quad.height as f32;
+
+                let pos = WorldPos {
+                    x: origin[0] + chunk_pos.x,
+                    y: origin[1] + chunk_pos.y,
+                    z: origin[2] + chunk_pos.z,
+                };
+                let (texture, translucent, light, occlusion) = quad.face;
+                let vertices = texture.get_vertices(pos, side, Vec3::from(size), light, occlusion, shading);
+                ans.push_quad(side, translucent, vertices, occlusion.flips_diagonal());
+            }
         }
     }
     ans
 }
 
+/// Every face `block` has, as a cube of `size` blocks with its lower corner at `origin`.
+/// For blocks out of the chunks, like dropped items, the texture covers a face whatever its size
+pub fn build_block_mesh(block: BlockType, origin: Vec3, size: f32, light: Light, shading: &FaceShading) -> MeshBuffers {
+    let mut buffers = MeshBuffers::default();
+    for (side_idx, texture) in block.get_textures().iter().enumerate() {
+        let Some(texture) = texture else {
+            continue;
+        };
+        let side = BlockSide::from_position(side_idx);
+        let mut vertices = texture.get_vertices(WorldPos::default(), side, Vec3::ONE, light, FaceOcclusion::OPEN, shading);
+        for vertex in &mut vertices {
+            vertex.position = origin + vertex.position * size;
+        }
+        buffers.push_quad(vertices, false);
+    }
+    buffers
+}
+
+/// Faces of one chunk slice, indexed [u][v]
+type FaceMask<T> = [[Option<T>; CHUNK_SIZE_16]; CHUNK_SIZE_16];
 
-fn has_same_texture(m1: Mesh, m2: Mesh) -> bool {
-    m1.texture.eq(&m2.texture)
+#[derive(Debug, PartialEq)]
+struct GreedyQuad<T> {
+    u: usize,
+    v: usize,
+    width: usize,
+    height: usize,
+    face: T,
 }
+
+/// Takes faces out of the mask, each returned quad covers `width` x `height` equal faces
+fn greedy_quads<T: Copy + PartialEq>(mask: &mut FaceMask<T>) -> Vec<GreedyQuad<T>> {
+    let mut ans = vec![];
+    for v in 0..CHUNK_SIZE_16 {
+        for u in 0..CHUNK_SIZE_16 {
+            let Some(face) = mask[u][v] else {
+                continue;
+            };
+            let width = (u..CHUNK_SIZE_16)
+                .take_while(|&u2| mask[u2][v] == Some(face))
+                .count();
+            let height = (v..CHUNK_SIZE_16)
+                .take_while(|&v2| (u..u + width).all(|u2| mask[u2][v2] == Some(face)))
+                .count();
+            for row in &mut mask[u..u + width] {
+                row[v..v + height].fill(None);
+            }
+            ans.push(GreedyQuad { u, v, width, height, face });
+        }
+    }
+    ans
+}
+
+/// Two triangles of a quad, counter clockwise like the face corners, see `face::FACES`
 pub const PLANE_IND: [u16; 6] = [
     0, 1, 2,
-    0, 3, 2,
+    0, 2, 3,
 ];
+
+/// The same split along the other diagonal
+pub const FLIPPED_PLANE_IND: [u16; 6] = [
+    1, 2, 3,
+    1, 3, 0,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::light::{ChunkLight, NO_BORDER_LIGHT};
+    use crate::world::registry::BlockRegistry;
+    use crate::world::render::light_color;
+    use crate::world::render::model::build_chunk_model;
+    use crate::world::{BlockState, Chunk};
+    use macroquad::prelude::{vec3, Vec2};
+
+    fn chunk_mesh(chunk: &mut Chunk) -> ChunkMesh {
+        build_chunk_model(chunk);
+        build_chunk_mesh(&chunk.model, WorldPos::default(), &FaceShading::DEFAULT)
+    }
+
+    #[test]
+    fn empty_chunk_has_no_mesh() {
+        let mut chunk = Chunk::EMPTY;
+        let mesh = chunk_mesh(&mut chunk);
+        assert!(mesh.buffers().next().is_none());
+        assert_eq!(mesh.vertex_count(), 0);
+    }
+
+    #[test]
+    fn floor_layer_is_one_quad() {
+        let mut chunk = Chunk::from_fn(|_, y, _| if y == 1 { BlockState::TILE } else { BlockState::AIR });
+        let mesh = chunk_mesh(&mut chunk);
+        assert_eq!(mesh.quad_count(), 1);
+        assert_eq!(mesh.vertex_count(), 4);
+        assert_eq!(mesh.index_count(), 6);
+    }
+
+    #[test]
+    fn full_chunk_is_one_quad_per_side() {
+        let mut chunk = Chunk::from_fn(|_, _, _| BlockState::STONE);
+        let mesh = chunk_mesh(&mut chunk);
+        assert!(mesh.opaque.iter().all(|meshes| meshes.len() == 1));
+        assert_eq!(mesh.quad_count(), 6);
+
+        let top_y = mesh.opaque[5][0].vertices.iter().map(|v| v.position.y).fold(f32::MIN, f32::max);
+        assert_eq!(top_y, CHUNK_SIZE_16 as f32);
+    }
+
+    #[test]
+    fn different_textures_are_not_merged() {
+        let mut chunk = Chunk::from_fn(|x, y, _| match (y, x < 8) {
+            (0, true) => BlockState::STONE,
+            (0, false) => BlockState::SAND,
+            _ => BlockState::AIR,
+        });
+        let mesh = chunk_mesh(&mut chunk);
+        // top and bottom of both halves, 3 outer sides of each half, the inner sides touch
+        assert_eq!(mesh.quad_count(), 2 * 2 + 2 * 3);
+    }
+
+    #[test]
+    fn translucent_faces_go_to_their_own_meshes() {
+        let glass = BlockState::new(BlockRegistry::global().by_name("glass").unwrap());
+        let leaves = BlockState::new(BlockRegistry::global().by_name("leaves").unwrap());
+        let mut chunk = Chunk::EMPTY;
+        chunk.set(1, 1, 1, glass.clone());
+        chunk.set(2, 1, 1, glass);
+        chunk.set(8, 1, 1, leaves);
+        let mesh = chunk_mesh(&mut chunk);
+        // the glass pair is one box of 6 merged faces
+        assert_eq!(mesh.translucent.iter().map(MeshBuffers::quad_count).sum::<usize>(), 6);
+        assert_eq!(mesh.opaque.iter().flatten().map(MeshBuffers::quad_count).sum::<usize>(), 6);
+    }
+
+    #[test]
+    fn translucent_quads_sort_back_to_front() {
+        let glass = BlockState::new(BlockRegistry::global().by_name("glass").unwrap());
+        // a row of panes along x, apart so nothing merges
+        let mut chunk = Chunk::EMPTY;
+        for x in [1, 5, 9, 13] {
+            chunk.set(x, 1, 1, glass.clone());
+        }
+        let mesh = chunk_mesh(&mut chunk);
+        let mut buffers = mesh.translucent[0].clone();
+        assert_eq!(buffers.quad_count(), 4 * 6);
+        // the x of each quad's centre in drawing order
+        let quad_xs = |buffers: &MeshBuffers| -> Vec<f32> {
+            buffers.indices
+                .chunks(6)
+                .map(|quad| quad.iter().map(|&i| buffers.vertices[i as usize].position.x).sum::<f32>() / 6.0)
+                .collect()
+        };
+
+        sort_quads_back_to_front(&buffers.vertices, &mut buffers.indices, vec3(-10.0, 1.5, 1.5));
+        let xs = quad_xs(&buffers);
+        assert!(xs.windows(2).all(|pair| pair[0] >= pair[1]), "{:?}", xs);
+        // the triangles still belong to the same quads, wound the same way
+        let mut quads: Vec<Vec<u16>> = buffers.indices.chunks(6).map(<[u16]>::to_vec).collect();
+        let mut built: Vec<Vec<u16>> = mesh.translucent[0].indices.chunks(6).map(<[u16]>::to_vec).collect();
+        quads.sort();
+        built.sort();
+        assert_eq!(quads, built);
+
+        sort_quads_back_to_front(&buffers.vertices, &mut buffers.indices, vec3(30.0, 1.5, 1.5));
+        let xs = quad_xs(&buffers);
+        assert!(xs.windows(2).all(|pair| pair[0] <= pair[1]), "{:?}", xs);
+    }
+
+    #[test]
+    fn light_is_baked_into_vertex_colours() {
+        // a floor at y = 0 and a roof at y = 8 over x < 8, the sky is open
+        let mut chunk = Chunk::from_fn(|x, y, _| match (y, x < 8) {
+            (0, _) | (8, true) => BlockState::STONE,
+            _ => BlockState::AIR,
+        });
+        chunk.light = ChunkLight::compute(&chunk, &NO_BORDER_LIGHT);
+        let mesh = chunk_mesh(&mut chunk);
+        let floor_tops: Vec<&[Vertex]> = mesh.opaque[BlockSide::Py.position()][0]
+            .vertices
+            .chunks(4)
+            .filter(|quad| quad.iter().all(|vertex| vertex.position.y == 1.0))
+            .collect();
+        // one strip per light level under the roof, one quad in the open
+        assert_eq!(floor_tops.len(), 8 + 1);
+        // of the quad starting at `x`
+        let brightness = |x: f32| {
+            let starts_at = |quad: &&&[Vertex]| quad.iter().map(|vertex| vertex.position.x).fold(f32::MAX, f32::min) == x;
+            let quad = floor_tops.iter().find(starts_at).unwrap();
+            assert!(quad.iter().all(|vertex| vertex.color == quad[0].color));
+            quad[0].color[0]
+        };
+        assert_eq!(brightness(8.0), u8::MAX);
+        for x in 0..8 {
+            assert!(brightness(x as f32) < brightness(x as f32 + 1.0));
+        }
+    }
+
+    #[test]
+    fn sides_have_normals_and_shading() {
+        let mut chunk = Chunk::EMPTY;
+        chunk.set(3, 3, 3, BlockState::STONE);
+        chunk.light = ChunkLight::Uniform(Light::SKY);
+        build_chunk_model(&mut chunk);
+        let shading = FaceShading { top: 1.0, bottom: 0.25, x: 0.75, z: 0.5 };
+        let mesh = build_chunk_mesh(&chunk.model, WorldPos::default(), &shading);
+        assert_eq!(mesh.quad_count(), 6);
+        let block_center = Vec3::splat(3.5);
+        for quad in mesh.opaque.iter().flatten().flat_map(|buffers| buffers.vertices.chunks(4)) {
+            let normal = quad[0].normal.truncate();
+            let face_center = quad.iter().map(|vertex| vertex.position).sum::<Vec3>() / 4.0;
+            // points out of the block
+            assert_eq!(normal, (face_center - block_center) * 2.0);
+            let side = (0..6).map(BlockSide::from_position).find(|side| Vec3::from(side.geometry().normal) == normal).unwrap();
+            for vertex in quad {
+                assert_eq!(vertex.normal.truncate(), normal);
+                assert_eq!(vertex.color, light_color(Light::SKY, 3, shading.factor(side)));
+            }
+        }
+        let top = light_color(Light::SKY, 3, shading.top)[0];
+        let x = light_color(Light::SKY, 3, shading.x)[0];
+        let z = light_color(Light::SKY, 3, shading.z)[0];
+        let bottom = light_color(Light::SKY, 3, shading.bottom)[0];
+        assert!(top > x && x > z && z > bottom);
+    }
+
+    #[test]
+    fn occlusion_darkens_corners_next_to_blocks() {
+        let mut chunk = Chunk::from_fn(|_, y, _| if y == 0 { BlockState::STONE } else { BlockState::AIR });
+        chunk.set(5, 1, 5, BlockState::STONE);
+        chunk.light = ChunkLight::compute(&chunk, &NO_BORDER_LIGHT);
+        let mesh = chunk_mesh(&mut chunk);
+        let buffers = &mesh.opaque[BlockSide::Py.position()][0];
+        // the floor top quad covering just the block at `x, z` and its indices
+        let floor_top = |x: f32, z: f32| {
+            let has = |quad: &[Vertex], x: f32, z: f32| quad.iter().any(|vertex| vertex.position == Vec3::new(x, 1.0, z));
+            let (idx, quad) = buffers.vertices
+                .chunks(4)
+                .enumerate()
+                .find(|(_, quad)| has(quad, x, z) && has(quad, x + 1.0, z + 1.0))
+                .unwrap();
+            let first = idx as u16 * 4;
+            let indices: Vec<u16> = buffers.indices[idx * 6..idx * 6 + 6].iter().map(|i| i - first).collect();
+            (quad, indices)
+        };
+        let open = light_color(Light::SKY, 3, FaceShading::DEFAULT.top);
+        let shaded = light_color(Light::SKY, 2, FaceShading::DEFAULT.top);
+
+        // next to the block: the corners touching it are shaded
+        let (quad, indices) = floor_top(6.0, 5.0);
+        for vertex in quad {
+            assert_eq!(vertex.color, if vertex.position.x == 6.0 { shaded } else { open });
+        }
+        assert_eq!(indices, PLANE_IND);
+
+        // diagonal to the block: one shaded corner, the quad is split away from it
+        let (quad, indices) = floor_top(6.0, 6.0);
+        for vertex in quad {
+            let corner = vertex.position.x == 6.0 && vertex.position.z == 6.0;
+            assert_eq!(vertex.color, if corner { shaded } else { open });
+        }
+        assert_eq!(indices, PLANE_IND);
+        let (quad, indices) = floor_top(6.0, 4.0);
+        for vertex in quad {
+            let corner = vertex.position.x == 6.0 && vertex.position.z == 5.0;
+            assert_eq!(vertex.color, if corner { shaded } else { open });
+        }
+        assert_eq!(indices, FLIPPED_PLANE_IND);
+    }
+
+    /// Stone, glass and leaves scattered around with the light computed, every quad split both ways shows up
+    fn scattered_chunk() -> Chunk {
+        let glass = BlockState::new(BlockRegistry::global().by_name("glass").unwrap());
+        let leaves = BlockState::new(BlockRegistry::global().by_name("leaves").unwrap());
+        let mut chunk = Chunk::from_fn(|x, y, z| match (x * 7 + y * 13 + z * 5) % 11 {
+            0..=2 => BlockState::STONE,
+            3 => glass.clone(),
+            4 => leaves.clone(),
+            _ => BlockState::AIR,
+        });
+        chunk.light = ChunkLight::compute(&chunk, &NO_BORDER_LIGHT);
+        chunk
+    }
+
+    /// Triangles of the buffers with their normals
+    fn triangles(buffers: &MeshBuffers) -> impl Iterator<Item = ([Vec3; 3], Vec3)> + '_ {
+        buffers.indices.chunks(3).map(|triangle| {
+            let vertices = [0, 1, 2].map(|idx| &buffers.vertices[triangle[idx] as usize]);
+            (vertices.map(|vertex| vertex.position), vertices[0].normal.truncate())
+        })
+    }
+
+    #[test]
+    fn triangles_wind_outward() {
+        let mut chunk = scattered_chunk();
+        let mesh = chunk_mesh(&mut chunk);
+        let buffers: Vec<&MeshBuffers> = mesh.buffers().collect();
+        let mut flipped = 0;
+        for buffers in buffers {
+            flipped += buffers.indices.chunks(6).filter(|quad| quad[0] % 4 == 1).count();
+            for ([a, b, c], normal) in triangles(buffers) {
+                let winding = (b - a).cross(c - b);
+                assert!(winding.dot(normal) > 0.0, "{:?} {:?} {:?} faces {:?}", a, b, c, normal);
+            }
+        }
+        assert!(flipped > 0);
+    }
+
+    #[test]
+    fn sides_facing_away_are_skipped() {
+        let pos = ChunkPos(1, -1, 0);
+        let sides_facing = |camera: Vec3| (0..6).filter(|&idx| side_faces_camera(BlockSide::from_position(idx), pos, camera)).count();
+        // inside the chunk every side can be seen
+        assert_eq!(sides_facing(vec3(24.0, -8.0, 8.0)), 6);
+        // right above it only the tops and the sides
+        assert!(!side_faces_camera(BlockSide::Ny, pos, vec3(24.0, 4.0, 8.0)));
+        assert!(side_faces_camera(BlockSide::Py, pos, vec3(24.0, 4.0, 8.0)));
+        assert_eq!(sides_facing(vec3(24.0, 4.0, 8.0)), 5);
+        // off a corner only the three sides turned to it
+        assert_eq!(sides_facing(vec3(-5.0, 20.0, 40.0)), 3);
+
+        let mut chunk = scattered_chunk();
+        build_chunk_model(&mut chunk);
+        let mesh = build_chunk_mesh(&chunk.model, pos.world_pos(), &FaceShading::DEFAULT);
+        let cameras = [vec3(16.5, -15.5, 0.5), vec3(31.5, 0.5, 15.5), vec3(10.0, -3.0, 30.0), vec3(20.0, -8.0, 8.0)];
+        for camera in cameras {
+            for (side_idx, meshes) in mesh.opaque.iter().enumerate() {
+                if side_faces_camera(BlockSide::from_position(side_idx), pos, camera) {
+                    continue;
+                }
+                // skipped sides have nothing turned to the camera
+                for ([a, _, _], normal) in meshes.iter().flat_map(triangles) {
+                    assert!((camera - a).dot(normal) <= 0.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn checkerboard_stays_under_index_limit() {
+        let mut chunk = Chunk::from_fn(|x, y, z| {
+            if (x + y + z) % 2 == 0 { BlockState::STONE } else { BlockState::AIR }
+        });
+        let mesh = chunk_mesh(&mut chunk);
+        assert_eq!(mesh.quad_count(), CHUNK_SIZE_16.pow(3) / 2 * 6);
+        assert_eq!(mesh.vertex_count(), mesh.quad_count() * 4);
+        assert_eq!(mesh.index_count(), mesh.quad_count() * 6);
+        for buffers in mesh.opaque.iter().flatten() {
+            assert!(buffers.vertices.len() < MESH_VERTEX_LIMIT);
+            assert!(buffers.indices.len() < MESH_INDEX_LIMIT);
+            let max_index = *buffers.indices.iter().max().unwrap() as usize;
+            assert_eq!(max_index, buffers.vertices.len() - 1);
+        }
+    }
+
+    #[test]
+    fn quads_over_limit_start_new_mesh() {
+        let mut mesh = ChunkMesh::default();
+        for _ in 0..MAX_QUADS_PER_MESH + 1 {
+            let vertices = UvTexture::new(4).get_vertices(WorldPos::default(), BlockSide::Py, Vec3::ONE, Light::SKY, FaceOcclusion::OPEN, &FaceShading::DEFAULT);
+            mesh.push_quad(BlockSide::Py, false, vertices, false);
+        }
+        let top = &mesh.opaque[BlockSide::Py.position()];
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[1].quad_count(), 1);
+        assert_eq!(top[1].indices, PLANE_IND.to_vec());
+        assert!(mesh.translucent.is_empty());
+    }
+
+    #[test]
+    fn block_mesh_is_a_small_full_texture_cube() {
+        let origin = Vec3::new(10.0, 20.0, 30.0);
+        let mesh = build_block_mesh(BlockType::STONE, origin, 0.25, Light::SKY, &FaceShading::FLAT);
+        assert_eq!(mesh.quad_count(), 6);
+        assert_eq!(mesh.indices.len(), 6 * PLANE_IND.len());
+        for vertex in &mesh.vertices {
+            let local = (vertex.position - origin) / 0.25;
+            assert!(local.cmpge(Vec3::ZERO).all() && local.cmple(Vec3::ONE).all(), "{:?}", vertex.position);
+            assert!(vertex.uv.cmpge(Vec2::ZERO).all() && vertex.uv.cmple(Vec2::ONE).all());
+        }
+        assert!(mesh.vertices.iter().any(|vertex| vertex.uv == Vec2::ONE));
+        // only the faces the block has a texture for
+        assert_eq!(build_block_mesh(BlockType::TILE, origin, 0.25, Light::SKY, &FaceShading::FLAT).quad_count(), 1);
+    }
+
+    #[test]
+    fn greedy_quads_cover_rectangles() {
+        let mut mask: FaceMask<u8> = [[None; CHUNK_SIZE_16]; CHUNK_SIZE_16];
+        for u in 2..5 {
+            for v in 0..3 {
+                mask[u][v] = Some(1);
+            }
+        }
+        mask[4][3] = Some(1);
+        let quads = greedy_quads(&mut mask);
+        assert_eq!(quads, vec![
+            GreedyQuad { u: 2, v: 0, width: 3, height: 3, face: 1 },
+            GreedyQuad { u: 4, v: 3, width: 1, height: 1, face: 1 },
+        ]);
+        assert!(mask.iter().flatten().all(Option::is_none));
+    }
+}
@@ -1,51 +1,521 @@
+use std::collections::{HashMap, HashSet};
+
 use super::*;
+use crate::world::palette::CHUNK_VOLUME;
+use crate::world::light::{border_coords, neighbour_border_light, BorderLight, Light, NO_BORDER_LIGHT};
+use crate::world::render::occlusion::{face_occlusion, ChunkShell, FaceOcclusion};
 use crate::world::render::render_cube_byte::RenderCubeByte;
 
+/// What is behind the chunk border when the neighbour chunk is not loaded
+#[allow(dead_code)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum MissingNeighbour {
+    /// Border faces are hidden until the neighbour is there
+    Solid,
+    /// Border faces are always drawn
+    #[default] Air,
+}
+
+impl MissingNeighbour {
+    pub fn layer(&self) -> ChunkLayer {
+        match self {
+            MissingNeighbour::Solid => ChunkLayer::SOLID,
+            MissingNeighbour::Air => ChunkLayer::EMPTY,
+        }
+    }
+}
+
+/// `neighbours` and the result are in `RenderCubeByte` bits order: nz, pz, nx, px, ny, py
+pub fn neighbour_border_layers(
+    neighbours: [Option<&Chunk>; 6],
+    missing: MissingNeighbour,
+) -> [ChunkLayer; 6] {
+    arr_fn(|pos| match neighbours[pos] {
+        Some(chunk) => chunk.border_layer(BlockSide::from_position(pos).opposite()),
+        None => missing.layer(),
+    })
+}
+
+/// Culls border faces against the loaded neighbours of the chunk at `pos`, if the chunk is there
+pub fn build_world_chunk_model(world: &mut World, pos: ChunkPos, missing: MissingNeighbour) {
+    let neighbours = arr_fn(|side_idx| world.get_chunk(pos.neighbour(BlockSide::from_position(side_idx))));
+    let layers = neighbour_border_layers(neighbours, missing);
+    let light = neighbour_border_light(neighbours);
+    let shell = ChunkShell::around(world, pos);
+    if let Some(chunk) = world.get_chunk_mut(pos) {
+        build_connected_chunk_model(chunk, &ConnectedChunks::from_layers(&layers), &light, &shell);
+    }
+}
+
+/// Rebuilds models of the block at `pos` and the 26 blocks around it after the block changed,
+/// the diagonal ones for ambient occlusion. Chunks without a model yet are built whole.
+/// Touched chunks are marked dirty
+pub fn update_block_models(world: &mut World, pos: BlockPos, missing: MissingNeighbour) {
+    for dx in -1..=1 {
+        for dy in -1..=1 {
+            for dz in -1..=1 {
+                update_block_model(world, pos + BlockPos::new(dx, dy, dz), missing);
+            }
+        }
+    }
+}
+
+/// Rebuilds models of the blocks next to the `relit` blocks, their faces show that light.
+/// Chunks with most of their blocks relit are built whole, that is faster
+pub fn update_light_models(world: &mut World, relit: &[BlockPos], missing: MissingNeighbour) {
+    let mut by_chunk: HashMap<ChunkPos, HashSet<BlockPos>> = HashMap::new();
+    for &pos in relit {
+        for side_idx in 0..6 {
+            let block_pos = pos + BlockSide::from_position(side_idx).offset();
+            by_chunk.entry(block_pos.chunk_pos()).or_default().insert(block_pos);
+        }
+    }
+    for (chunk_pos, positions) in by_chunk {
+        if positions.len() > CHUNK_VOLUME / 4 {
+            build_world_chunk_model(world, chunk_pos, missing);
+            continue;
+        }
+        for block_pos in positions {
+            update_block_model(world, block_pos, missing);
+        }
+    }
+}
+
+/// Rebuilds models of the blocks on the `side` border of the chunk at `pos`,
+/// after the neighbour chunk behind that side was loaded or unloaded
+pub fn update_border_models(world: &mut World, pos: ChunkPos, side: BlockSide, missing: MissingNeighbour) {
+    match world.get_chunk(pos) {
+        None => return,
+        Some(chunk) if chunk.model.0.is_none() => {
+            build_world_chunk_model(world, pos, missing);
+            return;
+        }
+        Some(_) => {}
+    }
+    let (normal, u, v) = side.axes();
+    let offset = side.offset();
+    let border = if offset.x + offset.y + offset.z > 0 { CHUNK_SIZE_16 as i32 - 1 } else { 0 };
+    let origin = pos.origin();
+    for a in 0..CHUNK_SIZE_16 as i32 {
+        for b in 0..CHUNK_SIZE_16 as i32 {
+            let mut local = [0; 3];
+            local[normal] = border;
+            local[u] = a;
+            local[v] = b;
+            update_block_model(world, origin + BlockPos::new(local[0], local[1], local[2]), missing);
+        }
+    }
+}
+
+/// Rebuilds models of the blocks in the chunks touching the chunk at `pos` only by an edge or a corner,
+/// the ones on that edge or corner, after the chunk was loaded or unloaded. Its blocks shade their corners
+pub fn update_diagonal_border_models(world: &mut World, pos: ChunkPos, missing: MissingNeighbour) {
+    let last = CHUNK_SIZE_16 as i32 - 1;
+    // blocks of the neighbour touching the chunk, by offset of the neighbour
+    let range = |offset: i32| match offset {
+        -1 => last..=last,
+        0 => 0..=last,
+        _ => 0..=0,
+    };
+    for dx in -1..=1i32 {
+        for dy in -1..=1i32 {
+            for dz in -1..=1i32 {
+                if dx.abs() + dy.abs() + dz.abs() < 2 {
+                    continue;
+                }
+                let neighbour = ChunkPos(pos.0 + dx, pos.1 + dy, pos.2 + dz);
+                if world.get_chunk(neighbour).is_none() {
+                    continue;
+                }
+                for x in range(dx) {
+                    for y in range(dy) {
+                        for z in range(dz) {
+                            update_block_model(world, neighbour.origin() + BlockPos::new(x, y, z), missing);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Marks the chunk dirty only if the model really changed
+fn update_block_model(world: &mut World, pos: BlockPos, missing: MissingNeighbour) {
+    let chunk_pos = pos.chunk_pos();
+    match world.get_chunk(chunk_pos) {
+        None => return,
+        Some(chunk) if chunk.model.0.is_none() => {
+            build_world_chunk_model(world, chunk_pos, missing);
+            return;
+        }
+        Some(_) => {}
+    }
+    let model = build_world_block_model(world, pos, missing);
+    let (x, y, z) = pos.in_chunk();
+    let chunk = world.get_chunk_mut(chunk_pos).unwrap();
+    if chunk.model.get(x, y, z) != model {
+        chunk.model.set(x, y, z, model);
+        chunk.is_dirty = true;
+    }
+}
+
+fn build_world_block_model(world: &World, pos: BlockPos, missing: MissingNeighbour) -> BlockModel {
+    let block_type = world.get_block(pos).block_type;
+    let mut render_byte = RenderCubeByte::from_block_type(block_type);
+    if render_byte.is_nothing() {
+        return BlockModel::EMPTY;
+    }
+    let mut neighbours = RenderCubeByte::NOTHING;
+    for side_idx in 0..6 {
+        let neighbour = pos + BlockSide::from_position(side_idx).offset();
+        let hidden = match world.get_chunk(neighbour.chunk_pos()) {
+            Some(_) => world.get_block(neighbour).block_type.hides_face_of(block_type),
+            None => missing == MissingNeighbour::Solid,
+        };
+        neighbours.set_bit(side_idx, hidden);
+    }
+    render_byte.apply_other_negative(neighbours);
+    if render_byte.is_nothing() {
+        return BlockModel::EMPTY;
+    }
+    let light = arr_fn(|side_idx| {
+        if render_byte.bool_in_pos(side_idx) {
+            world.get_light(pos + BlockSide::from_position(side_idx).offset())
+        } else {
+            Light::DARK
+        }
+    });
+    let occlusion = arr_fn(|side_idx| {
+        if render_byte.bool_in_pos(side_idx) {
+            face_occlusion(BlockSide::from_position(side_idx), |offset| world.get_block(pos + offset).block_type.is_opaque())
+        } else {
+            FaceOcclusion::OPEN
+        }
+    });
+    BlockModel { render_byte, block_type, light, occlusion }
+}
+
+#[allow(dead_code)]
 pub fn build_chunk_model(chunk: &mut Chunk) {
+    build_connected_chunk_model(chunk, &ConnectedChunks::EMPTY, &NO_BORDER_LIGHT, &ChunkShell::empty());
+}
+
+/// Light of the visible faces of the block at `x, y, z`, from the chunk light
+/// and on the border from the neighbour chunks, not loaded ones are open to the sky
+fn face_light(chunk: &Chunk, border_light: &BorderLight, x: usize, y: usize, z: usize, render_byte: RenderCubeByte) -> [Light; 6] {
+    arr_fn(|side_idx| {
+        if !render_byte.bool_in_pos(side_idx) {
+            return Light::DARK;
+        }
+        let side = BlockSide::from_position(side_idx);
+        let offset = side.offset();
+        let (nx, ny, nz) = (x as i32 + offset.x, y as i32 + offset.y, z as i32 + offset.z);
+        let range = 0..CHUNK_SIZE_16 as i32;
+        if range.contains(&nx) && range.contains(&ny) && range.contains(&nz) {
+            return chunk.light.get(nx as usize, ny as usize, nz as usize);
+        }
+        let (a, b) = border_coords(side, x, y, z);
+        border_light[side_idx].as_ref().map_or(Light::SKY, |layer| layer.get(a, b))
+    })
+}
+
+/// Ambient occlusion of the visible faces of the block at `x, y, z`,
+/// blocks out of the chunk are looked up in the `shell`
+fn chunk_face_occlusion(chunk: &Chunk, shell: &ChunkShell, x: usize, y: usize, z: usize, render_byte: RenderCubeByte) -> [FaceOcclusion; 6] {
+    let is_opaque = |offset: BlockPos| {
+        let (nx, ny, nz) = (x as i32 + offset.x, y as i32 + offset.y, z as i32 + offset.z);
+        let range = 0..CHUNK_SIZE_16 as i32;
+        if range.contains(&nx) && range.contains(&ny) && range.contains(&nz) {
+            chunk.get(nx as usize, ny as usize, nz as usize).block_type.is_opaque()
+        } else {
+            shell.is_opaque(nx, ny, nz)
+        }
+    };
+    arr_fn(|side_idx| {
+        if render_byte.bool_in_pos(side_idx) {
+            face_occlusion(BlockSide::from_position(side_idx), is_opaque)
+        } else {
+            FaceOcclusion::OPEN
+        }
+    })
+}
+
+/// Faces on the chunk border are culled against the border layers of the neighbour chunks
+/// and lit with their border light, the `shell` around the chunk shades their corners
+pub fn build_connected_chunk_model(chunk: &mut Chunk, connected: &ConnectedChunks, border_light: &BorderLight, shell: &ChunkShell) {
+    let chunk_plus = ChunkPlusConnected::new(chunk, connected);
     let mut this_chunk_model = ChunkModel::new_empty();
     for y in 0..CHUNK_SIZE_16 {
         for x in 0..CHUNK_SIZE_16 {
             for z in 0..CHUNK_SIZE_16 {
                 let block_type: BlockType = chunk.get(x, y, z).block_type;
                 let mut render_byte = RenderCubeByte::from_block_type(block_type);
-                if render_byte.is_nothing() || chunk.is_isolated(x as isize, y as isize, z as isize) {
+                if render_byte.is_nothing() {
                     continue;
                 }
-                let neighbours = chunk.get_neighbours(x as isize, y as isize, z as isize);
+                let neighbours = chunk_plus.connected_blocks(x, y, z).neighbours(block_type);
                 render_byte.apply_other_negative(neighbours);
                 if render_byte.is_nothing() {
                     continue;
                 }
-                let block_model = BlockModel { render_byte, block_type };
+                let light = face_light(chunk, border_light, x, y, z, render_byte);
+                let occlusion = chunk_face_occlusion(chunk, shell, x, y, z, render_byte);
+                let block_model = BlockModel { render_byte, block_type, light, occlusion };
                 this_chunk_model.set(x, y, z, block_model);
             }
         }
     }
     chunk.model = this_chunk_model;
+    chunk.is_dirty = true;
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-pub fn update_chunk_model(chunk: &mut Chunk, player_pos: Vec3) {
-    let chunk_pos: WorldPos = chunk.get_pos();
-    for y in 0..CHUNK_SIZE_16 {
-        for x in 0..CHUNK_SIZE_16 {
+    fn visible_faces(model: &ChunkModel) -> u32 {
+        model.0.iter()
+            .flatten()
+            .flat_map(|layer| layer.iter().flatten())
+            .map(|block| block.render_byte.get_value().count_ones())
+            .sum()
+    }
+
+    fn stone_chunk() -> Chunk {
+        Chunk::from_fn(|_, _, _| BlockState::STONE)
+    }
+
+    fn block(name: &str) -> BlockState {
+        BlockState::new(BlockRegistry::global().by_name(name).unwrap())
+    }
+
+    /// Visible faces of `a` and `b` side by side
+    fn pair_faces(a: BlockState, b: BlockState) -> u32 {
+        let mut chunk = Chunk::EMPTY;
+        chunk.set(5, 5, 5, a);
+        chunk.set(6, 5, 5, b);
+        build_chunk_model(&mut chunk);
+        visible_faces(&chunk.model)
+    }
+
+    #[test]
+    fn transparency_classes_cull_differently() {
+        let (glass, leaves, water) = (block("glass"), block("leaves"), block("water"));
+        assert_eq!(pair_faces(BlockState::STONE, BlockState::DIRT), 10);
+        // translucent blocks hide faces of the same block only
+        assert_eq!(pair_faces(glass.clone(), glass.clone()), 10);
+        assert_eq!(pair_faces(BlockState::STONE, glass.clone()), 11);
+        assert_eq!(pair_faces(water, glass), 12);
+        // cutout blocks hide nothing
+        assert_eq!(pair_faces(leaves.clone(), leaves.clone()), 12);
+        assert_eq!(pair_faces(BlockState::STONE, leaves), 11);
+    }
+
+    #[test]
+    fn missing_neighbours_as_air_show_border() {
+        let mut chunk = stone_chunk();
+        let layers = neighbour_border_layers([None; 6], MissingNeighbour::Air);
+        build_connected_chunk_model(&mut chunk, &ConnectedChunks::from_layers(&layers), &NO_BORDER_LIGHT, &ChunkShell::empty());
+        assert_eq!(visible_faces(&chunk.model), 6 * 16 * 16);
+    }
+
+    #[test]
+    fn missing_neighbours_as_solid_hide_border() {
+        let mut chunk = stone_chunk();
+        let layers = neighbour_border_layers([None; 6], MissingNeighbour::Solid);
+        build_connected_chunk_model(&mut chunk, &ConnectedChunks::from_layers(&layers), &NO_BORDER_LIGHT, &ChunkShell::empty());
+        assert_eq!(visible_faces(&chunk.model), 0);
+    }
+
+    #[test]
+    fn solid_neighbour_culls_touching_side_only() {
+        let px_chunk = stone_chunk();
+        let mut chunk = stone_chunk();
+        let mut neighbours = [None; 6];
+        neighbours[3] = Some(&px_chunk);
+        let layers = neighbour_border_layers(neighbours, MissingNeighbour::Air);
+        build_connected_chunk_model(&mut chunk, &ConnectedChunks::from_layers(&layers), &NO_BORDER_LIGHT, &ChunkShell::empty());
+
+        assert_eq!(visible_faces(&chunk.model), 5 * 16 * 16);
+        for y in 0..CHUNK_SIZE_16 {
             for z in 0..CHUNK_SIZE_16 {
-                let neighbours = &chunk.get_neighbours(x as isize, y as isize, z as isize);
-                if let Some(mut render_byte) = chunk.model.get_render_byte(x, y, z) {
-                    render_byte.apply_other_negative(*neighbours);
-                    if render_byte.is_nothing() {
-                        continue;
+                let byte = chunk.model.get_render_byte(15, y, z).unwrap();
+                assert!(!byte.bool_in_pos(3));
+                let byte = chunk.model.get_render_byte(0, y, z).unwrap();
+                assert!(byte.bool_in_pos(2));
+            }
+        }
+    }
+
+    #[test]
+    fn border_layers_are_oriented() {
+        // the neighbour on px is solid only in its lower half
+        let px_chunk = Chunk::from_fn(|x, y, _| if x == 0 && y < 8 { BlockState::STONE } else { BlockState::AIR });
+        let mut chunk = stone_chunk();
+        let mut neighbours = [None; 6];
+        neighbours[3] = Some(&px_chunk);
+        let layers = neighbour_border_layers(neighbours, MissingNeighbour::Air);
+        build_connected_chunk_model(&mut chunk, &ConnectedChunks::from_layers(&layers), &NO_BORDER_LIGHT, &ChunkShell::empty());
+
+        for z in 0..CHUNK_SIZE_16 {
+            assert!(!chunk.model.get_render_byte(15, 0, z).unwrap().bool_in_pos(3));
+            assert!(chunk.model.get_render_byte(15, 15, z).unwrap().bool_in_pos(3));
+        }
+    }
+
+    #[test]
+    fn world_neighbours_are_culled() {
+        let mut world = World::new();
+        for x in 0..2 * CHUNK_SIZE_16 as i32 {
+            world.set_block(BlockPos::new(x, 0, 0), BlockState::STONE);
+        }
+        build_world_chunk_model(&mut world, ChunkPos(0, 0, 0), MissingNeighbour::Air);
+        build_world_chunk_model(&mut world, ChunkPos(1, 0, 0), MissingNeighbour::Air);
+
+        let left = world.get_chunk(ChunkPos(0, 0, 0)).unwrap();
+        let right = world.get_chunk(ChunkPos(1, 0, 0)).unwrap();
+        assert!(!left.model.get_render_byte(15, 0, 0).unwrap().bool_in_pos(3));
+        assert!(!right.model.get_render_byte(0, 0, 0).unwrap().bool_in_pos(2));
+        assert!(left.model.get_render_byte(0, 0, 0).unwrap().bool_in_pos(2));
+        assert!(right.model.get_render_byte(15, 0, 0).unwrap().bool_in_pos(3));
+    }
+
+    fn assert_models_match_full_rebuild(world: &World, missing: MissingNeighbour) {
+        let mut rebuilt = World::new();
+        for (pos, chunk) in world.chunks() {
+            rebuilt.insert_chunk(*pos, chunk.clone());
+        }
+        for pos in world.chunk_positions() {
+            build_world_chunk_model(&mut rebuilt, pos, missing);
+            let expected = &rebuilt.get_chunk(pos).unwrap().model;
+            assert_eq!(&world.get_chunk(pos).unwrap().model, expected, "chunk {:?}", pos);
+        }
+    }
+
+    fn build_all(world: &mut World, missing: MissingNeighbour) {
+        for pos in world.chunk_positions().collect::<Vec<_>>() {
+            build_world_chunk_model(world, pos, missing);
+        }
+        world.take_dirty_chunks();
+    }
+
+    #[test]
+    fn block_updates_match_full_rebuild() {
+        use ::rand::rngs::StdRng;
+        use ::rand::{Rng, SeedableRng};
+
+        for missing in [MissingNeighbour::Air, MissingNeighbour::Solid] {
+            let mut rng = StdRng::seed_from_u64(4);
+            let mut world = World::new();
+            for x in -16..16 {
+                for y in 0..8 {
+                    for z in -16..16 {
+                        if rng.gen_bool(0.5) {
+                            world.set_block(BlockPos::new(x, y, z), BlockState::STONE);
+                        }
                     }
-                    let block_pos = WorldPos {
-                        x: x as f32 + chunk_pos.x,
-                        y: y as f32 + chunk_pos.y,
-                        z: z as f32 + chunk_pos.z,
-                    };
-                    let visible = Chunk::get_block_visible_sides(block_pos, player_pos);
-                    render_byte.apply_other(visible);
-                    chunk.model.set_render_byte(x, y, z, render_byte)
                 }
             }
+            build_all(&mut world, missing);
+
+            let states = [BlockState::DIRT, BlockState::AIR, block("glass"), block("leaves")];
+            for _ in 0..300 {
+                // changes gather around the chunk borders at x = 0 and z = 0
+                let pos = BlockPos::new(rng.gen_range(-3..3), rng.gen_range(0..8), rng.gen_range(-3..3));
+                let state = states[rng.gen_range(0..states.len())].clone();
+                world.set_block(pos, state);
+                update_block_models(&mut world, pos, missing);
+            }
+            assert_models_match_full_rebuild(&world, missing);
         }
     }
+
+    #[test]
+    fn border_block_update_dirties_neighbour_chunk() {
+        let mut world = World::new();
+        world.set_block(BlockPos::new(0, 0, 0), BlockState::STONE);
+        world.set_block(BlockPos::new(-1, 0, 0), BlockState::STONE);
+        world.set_block(BlockPos::new(0, 0, 5), BlockState::STONE);
+        build_all(&mut world, MissingNeighbour::Air);
+
+        world.set_block(BlockPos::new(0, 0, 0), BlockState::AIR);
+        update_block_models(&mut world, BlockPos::new(0, 0, 0), MissingNeighbour::Air);
+        let mut dirty = world.take_dirty_chunks();
+        dirty.sort_by_key(|pos| pos.0);
+        assert_eq!(dirty, vec![ChunkPos(-1, 0, 0), ChunkPos(0, 0, 0)]);
+        assert!(world.take_dirty_chunks().is_empty());
+
+        world.set_block(BlockPos::new(0, 0, 5), BlockState::DIRT);
+        update_block_models(&mut world, BlockPos::new(0, 0, 5), MissingNeighbour::Air);
+        assert_eq!(world.take_dirty_chunks(), vec![ChunkPos(0, 0, 0)]);
+        assert_models_match_full_rebuild(&world, MissingNeighbour::Air);
+    }
+
+    #[test]
+    fn update_in_new_chunk_builds_it_whole() {
+        let mut world = World::new();
+        world.set_block(BlockPos::new(3, 20, 3), BlockState::SAND);
+        update_block_models(&mut world, BlockPos::new(3, 20, 3), MissingNeighbour::Air);
+        assert_eq!(world.take_dirty_chunks(), vec![ChunkPos(0, 1, 0)]);
+        assert_models_match_full_rebuild(&world, MissingNeighbour::Air);
+    }
+
+    #[test]
+    fn border_updates_follow_loaded_and_unloaded_neighbours() {
+        let stone = stone_chunk();
+        let mut world = World::new();
+        world.insert_chunk(ChunkPos(0, 0, 0), stone.clone());
+        build_all(&mut world, MissingNeighbour::Air);
+
+        for side_idx in 0..6 {
+            let side = BlockSide::from_position(side_idx);
+            let neighbour = ChunkPos(0, 0, 0).neighbour(side);
+            world.insert_chunk(neighbour, stone.clone());
+            build_world_chunk_model(&mut world, neighbour, MissingNeighbour::Air);
+            update_border_models(&mut world, ChunkPos(0, 0, 0), side, MissingNeighbour::Air);
+            // the earlier neighbours touch it by an edge
+            update_diagonal_border_models(&mut world, neighbour, MissingNeighbour::Air);
+            assert_models_match_full_rebuild(&world, MissingNeighbour::Air);
+        }
+        world.take_dirty_chunks();
+        assert_eq!(visible_faces(&world.get_chunk(ChunkPos(0, 0, 0)).unwrap().model), 0);
+
+        world.remove_chunk(ChunkPos(0, 0, 1));
+        update_border_models(&mut world, ChunkPos(0, 0, 0), BlockSide::Pz, MissingNeighbour::Air);
+        assert_eq!(world.take_dirty_chunks(), vec![ChunkPos(0, 0, 0)]);
+        update_diagonal_border_models(&mut world, ChunkPos(0, 0, 1), MissingNeighbour::Air);
+        assert_models_match_full_rebuild(&world, MissingNeighbour::Air);
+    }
+
+    #[test]
+    fn occlusion_reaches_over_chunk_edges() {
+        let mut world = World::new();
+        let floor = Chunk::from_fn(|_, y, _| if y == 0 { BlockState::STONE } else { BlockState::AIR });
+        world.insert_chunk(ChunkPos(0, 0, 0), floor);
+        build_all(&mut world, MissingNeighbour::Air);
+        // the far corner of the floor top at the +x +z edge of the chunk
+        let far_corner = |world: &World| world.get_chunk(ChunkPos(0, 0, 0)).unwrap().model.get(15, 0, 15).occlusion[5].corner(1);
+        assert_eq!(far_corner(&world), 3);
+
+        // a block in the chunk touching the floor chunk only by an edge
+        let mut pillar = Chunk::EMPTY;
+        pillar.set(0, 1, 0, BlockState::STONE);
+        world.insert_chunk(ChunkPos(1, 0, 1), pillar);
+        build_world_chunk_model(&mut world, ChunkPos(1, 0, 1), MissingNeighbour::Air);
+        update_diagonal_border_models(&mut world, ChunkPos(1, 0, 1), MissingNeighbour::Air);
+        assert_eq!(far_corner(&world), 2);
+        assert_models_match_full_rebuild(&world, MissingNeighbour::Air);
+    }
+
+    #[test]
+    fn empty_connected_chunks_match_plain_model() {
+        let mut chunk = Chunk::from_fn(|x, y, z| {
+            if (x * y + z) % 3 == 0 { BlockState::DIRT } else { BlockState::AIR }
+        });
+        build_chunk_model(&mut chunk);
+        let plain = chunk.model.clone();
+        let layers = neighbour_border_layers([None; 6], MissingNeighbour::Air);
+        build_connected_chunk_model(&mut chunk, &ConnectedChunks::from_layers(&layers), &NO_BORDER_LIGHT, &ChunkShell::empty());
+        assert_eq!(chunk.model, plain);
+    }
 }
@@ -0,0 +1,194 @@
+use super::*;
+
+/// Blocks one step out of the chunk on every side
+const SHELL_SIZE: usize = CHUNK_SIZE_16 + 2;
+const SHELL_WORDS: usize = (SHELL_SIZE * SHELL_SIZE * SHELL_SIZE).div_ceil(64);
+
+/// Ambient occlusion of the 4 face corners in `FaceGeometry::corners` order, 2 bits each.
+/// 0 - the corner is closed in, 3 - nothing shades it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FaceOcclusion(u8);
+
+#[allow(dead_code)]
+impl FaceOcclusion {
+    pub const OPEN: Self = Self(u8::MAX);
+
+    pub fn from_corners(corners: [u8; 4]) -> Self {
+        Self(corners.iter().enumerate().fold(0, |bits, (idx, &corner)| bits | (corner & 0b11) << (idx * 2)))
+    }
+
+    pub fn corner(&self, idx: usize) -> u8 {
+        self.0 >> (idx * 2) & 0b11
+    }
+
+    /// Quads are split along the 0-2 diagonal, the 1-3 one is taken when its corners are brighter.
+    /// Otherwise one dark corner smears along the whole diagonal
+    pub fn flips_diagonal(&self) -> bool {
+        self.corner(0) + self.corner(2) < self.corner(1) + self.corner(3)
+    }
+}
+
+/// Two opaque sides close the corner whatever is on the diagonal
+pub fn corner_occlusion(side1: bool, side2: bool, corner: bool) -> u8 {
+    if side1 && side2 {
+        0
+    } else {
+        3 - side1 as u8 - side2 as u8 - corner as u8
+    }
+}
+
+/// `is_opaque` takes block positions relative to the block of the face,
+/// only the 8 blocks around the one in front of the face are asked
+pub fn face_occlusion(side: BlockSide, is_opaque: impl Fn(BlockPos) -> bool) -> FaceOcclusion {
+    let (_, u, v) = side.axes();
+    let front = side.offset();
+    let front = [front.x, front.y, front.z];
+    FaceOcclusion::from_corners(side.geometry().corners.map(|coef| {
+        let step = |axis: usize| if coef[axis] > 0.5 { 1 } else { -1 };
+        let mut side1 = front;
+        side1[u] += step(u);
+        let mut side2 = front;
+        side2[v] += step(v);
+        let mut corner = side1;
+        corner[v] += step(v);
+        let [side1, side2, corner] = [side1, side2, corner].map(|[x, y, z]| is_opaque(BlockPos::new(x, y, z)));
+        corner_occlusion(side1, side2, corner)
+    }))
+}
+
+/// Opaque blocks right around a chunk, with the edges and corners the border layers don't have.
+/// Blocks of the chunk itself are not kept
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChunkShell(Box<[u64; SHELL_WORDS]>);
+
+#[allow(dead_code)]
+impl ChunkShell {
+    /// Nothing opaque around
+    pub fn empty() -> Self {
+        Self(Box::new([0; SHELL_WORDS]))
+    }
+
+    /// From the loaded chunks around `pos`, not loaded ones are taken as air
+    pub fn around(world: &World, pos: ChunkPos) -> Self {
+        let mut shell = Self::empty();
+        let last = CHUNK_SIZE_16 as i32 - 1;
+        // the neighbour's blocks touching the chunk, by offset of the neighbour
+        let range = |offset: i32| match offset {
+            -1 => last..=last,
+            0 => 0..=last,
+            _ => 0..=0,
+        };
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    if (dx, dy, dz) == (0, 0, 0) {
+                        continue;
+                    }
+                    let Some(chunk) = world.get_chunk(ChunkPos(pos.0 + dx, pos.1 + dy, pos.2 + dz)) else {
+                        continue;
+                    };
+                    for x in range(dx) {
+                        for y in range(dy) {
+                            for z in range(dz) {
+                                if chunk.get(x as usize, y as usize, z as usize).block_type.is_opaque() {
+                                    let size = CHUNK_SIZE_16 as i32;
+                                    shell.set(x + dx * size, y + dy * size, z + dz * size);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        shell
+    }
+
+    /// `x, y, z` - local to the chunk, -1..=16 with at least one of them out of the chunk
+    pub fn is_opaque(&self, x: i32, y: i32, z: i32) -> bool {
+        let idx = Self::index(x, y, z);
+        self.0[idx / 64] >> (idx % 64) & 1 == 1
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, z: i32) {
+        let idx = Self::index(x, y, z);
+        self.0[idx / 64] |= 1 << (idx % 64);
+    }
+
+    fn index(x: i32, y: i32, z: i32) -> usize {
+        let [x, y, z] = [x, y, z].map(|coord| (coord + 1) as usize);
+        debug_assert!(x < SHELL_SIZE && y < SHELL_SIZE && z < SHELL_SIZE);
+        (y * SHELL_SIZE + x) * SHELL_SIZE + z
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn occlusion_with(side: BlockSide, opaque: &[(i32, i32, i32)]) -> [u8; 4] {
+        let occlusion = face_occlusion(side, |pos| opaque.contains(&(pos.x, pos.y, pos.z)));
+        [0, 1, 2, 3].map(|idx| occlusion.corner(idx))
+    }
+
+    #[test]
+    fn corner_rule() {
+        assert_eq!(corner_occlusion(false, false, false), 3);
+        assert_eq!(corner_occlusion(false, false, true), 2);
+        assert_eq!(corner_occlusion(true, false, false), 2);
+        assert_eq!(corner_occlusion(true, false, true), 1);
+        assert_eq!(corner_occlusion(false, true, true), 1);
+        assert_eq!(corner_occlusion(true, true, false), 0);
+        assert_eq!(corner_occlusion(true, true, true), 0);
+    }
+
+    #[test]
+    fn corners_pack() {
+        let occlusion = FaceOcclusion::from_corners([0, 1, 2, 3]);
+        assert_eq!([0, 1, 2, 3].map(|idx| occlusion.corner(idx)), [0, 1, 2, 3]);
+        assert_eq!(FaceOcclusion::from_corners([3; 4]), FaceOcclusion::OPEN);
+    }
+
+    #[test]
+    fn known_configurations() {
+        // top face corners: (0, 1), (1, 1), (1, 0), (0, 0) in x, z
+        assert_eq!(occlusion_with(BlockSide::Py, &[]), [3, 3, 3, 3]);
+        // a block on the floor next to the face darkens the two touching corners
+        assert_eq!(occlusion_with(BlockSide::Py, &[(-1, 1, 0)]), [2, 3, 3, 2]);
+        // only the diagonal block
+        assert_eq!(occlusion_with(BlockSide::Py, &[(1, 1, 1)]), [3, 2, 3, 3]);
+        // inner corner of two walls closes the corner whatever the diagonal is
+        assert_eq!(occlusion_with(BlockSide::Py, &[(-1, 1, 0), (0, 1, -1)]), [2, 3, 2, 0]);
+        // a wall with the diagonal block
+        assert_eq!(occlusion_with(BlockSide::Py, &[(1, 1, 0), (1, 1, 1)]), [3, 1, 2, 3]);
+        // blocks behind the face plane don't matter
+        assert_eq!(occlusion_with(BlockSide::Py, &[(-1, 0, 0), (0, -1, 0)]), [3, 3, 3, 3]);
+        // side face corners: (1, 0), (0, 0), (0, 1), (1, 1) in z, y, the floor in front darkens the bottom
+        assert_eq!(occlusion_with(BlockSide::Px, &[(1, -1, -1), (1, -1, 0), (1, -1, 1)]), [1, 1, 3, 3]);
+    }
+
+    #[test]
+    fn diagonal_follows_brighter_corners() {
+        assert!(!FaceOcclusion::OPEN.flips_diagonal());
+        assert!(FaceOcclusion::from_corners([0, 3, 3, 3]).flips_diagonal());
+        assert!(!FaceOcclusion::from_corners([3, 0, 3, 3]).flips_diagonal());
+        assert!(!FaceOcclusion::from_corners([2, 2, 2, 2]).flips_diagonal());
+    }
+
+    #[test]
+    fn shell_has_edges_and_corners() {
+        let mut world = World::new();
+        let stone = Chunk::from_fn(|_, _, _| BlockState::STONE);
+        for pos in [ChunkPos(1, 0, 0), ChunkPos(1, 1, 0), ChunkPos(-1, -1, -1), ChunkPos(0, 0, 5)] {
+            world.insert_chunk(pos, stone.clone());
+        }
+        let shell = ChunkShell::around(&world, ChunkPos(0, 0, 0));
+        assert!(shell.is_opaque(16, 3, 7));
+        assert!(shell.is_opaque(16, 16, 7));
+        assert!(shell.is_opaque(-1, -1, -1));
+        assert!(!shell.is_opaque(-1, 3, 7));
+        assert!(!shell.is_opaque(16, -1, 7));
+        assert!(!shell.is_opaque(3, 16, 16));
+        let count = (0..SHELL_WORDS).map(|idx| shell.0[idx].count_ones()).sum::<u32>();
+        assert_eq!(count, 16 * 16 + 16 + 1);
+    }
+}
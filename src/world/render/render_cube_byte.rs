@@ -21,18 +21,20 @@ impl RenderCubeByte {
 
     pub fn apply_other_negative(&mut self, other: Self) {
         let neg_other = !other.0 & 0b00111111;
-        self.0 = self.0 & neg_other;
+        self.0 &= neg_other;
     }
+    #[allow(dead_code)]
     pub fn apply_other(&mut self, other: Self) {
-        self.0 = self.0 & other.0;
+        self.0 &= other.0;
     }
 
+    /// All sides for blocks with any texture, nothing for invisible ones
     pub fn from_block_type(bt: BlockType) -> Self {
-        let value = match bt {
-            BlockType::Air => 0,
-            _ => 0b00111111,
-        };
-        Self(value)
+        if bt.get_textures().iter().any(Option::is_some) {
+            Self::ALL
+        } else {
+            Self::NOTHING
+        }
     }
     pub fn set_bit(&mut self, pos: usize, bit: bool) {
         let mask = !(1 << pos);
@@ -0,0 +1,332 @@
+use macroquad::math::Vec3;
+
+use crate::world::render::mesh::ChunkMesh;
+use crate::world::light::{neighbour_border_light, settle_chunk_light};
+use crate::world::render::model::{
+    neighbour_border_layers, update_border_models, update_diagonal_border_models, update_light_models, MissingNeighbour,
+};
+use crate::world::render::occlusion::ChunkShell;
+use crate::world::render::BlockSide;
+use crate::world::region;
+use crate::world::workers::{ChunkJob, ChunkWorkers};
+use crate::world::{BlockPos, ChunkPos, World};
+
+/// Chunks above and below the player chunk that are kept loaded
+const VERTICAL_VIEW_DISTANCE: i32 = 2;
+/// Chunks are unloaded this many chunks past the view distance,
+/// so walking back and forth over a chunk border doesn't reload them
+const UNLOAD_MARGIN: i32 = 1;
+
+/// Keeps the chunks around the player loaded: a circle of `view_distance` chunks
+/// across and `VERTICAL_VIEW_DISTANCE` chunks up and down.
+/// Generating and meshing is done by `workers`, the main thread only applies the results
+pub struct ChunkStreamer {
+    workers: ChunkWorkers,
+    view_distance: i32,
+    /// Jobs in flight and results applied per update at most
+    budget: usize,
+    center: Option<ChunkPos>,
+    /// Missing chunks in view, the nearest last
+    to_load: Vec<ChunkPos>,
+}
+
+/// Changes for the meshes on the GPU
+#[derive(Debug, Default)]
+pub struct StreamUpdate {
+    /// Chunks that left the view, their meshes should be dropped
+    pub unloaded: Vec<ChunkPos>,
+    /// New buffers of loaded chunks, replacing their old meshes
+    pub meshed: Vec<(ChunkPos, ChunkMesh)>,
+}
+
+impl ChunkStreamer {
+    pub fn new(workers: ChunkWorkers, view_distance: i32, budget: usize) -> Self {
+        Self {
+            workers,
+            view_distance: view_distance.max(0),
+            budget: budget.max(1),
+            center: None,
+            to_load: vec![],
+        }
+    }
+
+    pub fn is_in_view(&self, center: ChunkPos, pos: ChunkPos) -> bool {
+        in_range(center, pos, self.view_distance, VERTICAL_VIEW_DISTANCE)
+    }
+
+    /// Chunks left to load or mesh around the last center
+    pub fn pending_count(&self) -> usize {
+        self.to_load.len() + self.workers.pending_count()
+    }
+
+    /// Unloads chunks that left the view and cancels their jobs, applies finished jobs,
+    /// sends dirty chunks to be meshed and requests missing chunks, nearest first
+    pub fn update(&mut self, world: &mut World, player_pos: Vec3, missing: MissingNeighbour) -> StreamUpdate {
+        let center = BlockPos::from(player_pos).chunk_pos();
+        if self.center != Some(center) {
+            self.center = Some(center);
+            self.to_load = self.missing_chunks(world, center);
+        }
+        let mut update = StreamUpdate::default();
+
+        let (horizontal, vertical) = (self.view_distance + UNLOAD_MARGIN, VERTICAL_VIEW_DISTANCE + UNLOAD_MARGIN);
+        update.unloaded = world
+            .chunk_positions()
+            .filter(|&pos| !in_range(center, pos, horizontal, vertical))
+            .collect();
+        let mut modified = vec![];
+        for &pos in &update.unloaded {
+            self.workers.cancel(pos);
+            match world.remove_chunk(pos) {
+                Some(chunk) if chunk.needs_saving() => modified.push((pos, chunk)),
+                _ => {}
+            }
+        }
+        if let Some(dir) = &self.workers.source().save_dir {
+            let chunks = modified.iter().map(|(pos, chunk)| (*pos, chunk));
+            if let Err(err) = region::save_chunks(dir, chunks) {
+                // kept loaded, the next update or autosave tries again
+                eprintln!("Failed to save unloaded chunks, they stay loaded: {}", err);
+                for (pos, chunk) in modified {
+                    world.insert_chunk(pos, chunk);
+                }
+                update.unloaded.retain(|&pos| world.get_chunk(pos).is_none());
+            }
+        }
+        for &pos in &update.unloaded {
+            update_neighbour_borders(world, pos, missing);
+        }
+        let out_of_view: Vec<ChunkPos> = self
+            .workers
+            .pending_positions()
+            .filter(|&pos| world.get_chunk(pos).is_none() && !self.is_in_view(center, pos))
+            .collect();
+        for pos in out_of_view {
+            self.workers.cancel(pos);
+        }
+
+        for result in self.workers.poll(self.budget) {
+            let pos = result.pos;
+            if let Some(chunk) = result.chunk {
+                // edits don't create chunks, see `apply_block_action`, one already here is newer
+                if world.get_chunk(pos).is_some() {
+                    continue;
+                }
+                world.insert_chunk(pos, chunk);
+                let relit = settle_chunk_light(world, pos, &result.border_light.unwrap_or_default());
+                update_light_models(world, &relit, missing);
+                // neighbours may have changed since the job was sent
+                for side_idx in 0..6 {
+                    update_border_models(world, pos, BlockSide::from_position(side_idx), missing);
+                }
+                update_neighbour_borders(world, pos, missing);
+            } else if world.get_chunk(pos).is_none() {
+                continue;
+            }
+            update.meshed.push((pos, result.mesh));
+        }
+
+        for pos in world.take_dirty_chunks() {
+            let model = world.get_chunk(pos).unwrap().model.clone();
+            self.workers.request(pos, ChunkJob::Mesh(Box::new(model)));
+        }
+
+        while self.workers.pending_count() < self.budget {
+            let Some(pos) = self.to_load.pop() else { break };
+            if world.get_chunk(pos).is_some() || self.workers.is_pending(pos) {
+                continue;
+            }
+            let neighbours = std::array::from_fn(|side_idx| world.get_chunk(pos.neighbour(BlockSide::from_position(side_idx))));
+            let layers = neighbour_border_layers(neighbours, missing);
+            let light = neighbour_border_light(neighbours);
+            let shell = ChunkShell::around(world, pos);
+            self.workers.request(pos, ChunkJob::Generate(Box::new(layers), Box::new(light), Box::new(shell)));
+        }
+        update
+    }
+
+    fn missing_chunks(&self, world: &World, center: ChunkPos) -> Vec<ChunkPos> {
+        let (horizontal, vertical) = (self.view_distance, VERTICAL_VIEW_DISTANCE);
+        let mut missing: Vec<ChunkPos> = (-horizontal..=horizontal)
+            .flat_map(|x| (-vertical..=vertical).flat_map(move |y| (-horizontal..=horizontal).map(move |z| (x, y, z))))
+            .map(|(x, y, z)| ChunkPos(center.0 + x, center.1 + y, center.2 + z))
+            .filter(|&pos| self.is_in_view(center, pos) && world.get_chunk(pos).is_none())
+            .collect();
+        missing.sort_by_key(|&pos| std::cmp::Reverse(distance_squared(center, pos)));
+        missing
+    }
+}
+
+fn distance_squared(a: ChunkPos, b: ChunkPos) -> i32 {
+    let (x, y, z) = (a.0 - b.0, a.1 - b.1, a.2 - b.2);
+    x * x + y * y + z * z
+}
+
+fn in_range(center: ChunkPos, pos: ChunkPos, horizontal: i32, vertical: i32) -> bool {
+    let (x, z) = (pos.0 - center.0, pos.2 - center.2);
+    x * x + z * z <= horizontal * horizontal && (pos.1 - center.1).abs() <= vertical
+}
+
+/// The chunks around `pos` cull and shade their borders against it again
+fn update_neighbour_borders(world: &mut World, pos: ChunkPos, missing: MissingNeighbour) {
+    for side_idx in 0..6 {
+        let side = BlockSide::from_position(side_idx);
+        update_border_models(world, pos.neighbour(side), side.opposite(), missing);
+    }
+    update_diagonal_border_models(world, pos, missing);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use macroquad::math::vec3;
+
+    use super::*;
+    use crate::world::render::model::build_world_chunk_model;
+    use crate::world::temp_dir::TempDir;
+    use crate::world::terrain::TerrainGenerator;
+    use crate::world::workers::ChunkSource;
+    use crate::world::BlockState;
+
+    const MISSING: MissingNeighbour = MissingNeighbour::Air;
+
+    fn streamer(view_distance: i32, budget: usize) -> ChunkStreamer {
+        ChunkStreamer::new(ChunkWorkers::new(2, ChunkSource::generated(TerrainGenerator::new(1))), view_distance, budget)
+    }
+
+    /// Updates until nothing is left to do, returns the chunks meshed on the way
+    fn load_all(streamer: &mut ChunkStreamer, world: &mut World, player_pos: Vec3) -> Vec<ChunkPos> {
+        let mut meshed = vec![];
+        loop {
+            let update = streamer.update(world, player_pos, MISSING);
+            meshed.extend(update.meshed.into_iter().map(|(pos, _)| pos));
+            if streamer.pending_count() == 0 {
+                return meshed;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn assert_models_match_full_rebuild(world: &World) {
+        let mut rebuilt = World::new();
+        for (pos, chunk) in world.chunks() {
+            rebuilt.insert_chunk(*pos, chunk.clone());
+        }
+        for pos in world.chunk_positions() {
+            build_world_chunk_model(&mut rebuilt, pos, MISSING);
+            assert_eq!(world.get_chunk(pos).unwrap().model, rebuilt.get_chunk(pos).unwrap().model);
+        }
+    }
+
+    #[test]
+    fn requests_nearest_chunks_within_budget() {
+        let mut world = World::new();
+        let mut streamer = streamer(2, 5);
+        let update = streamer.update(&mut world, vec3(8.0, 8.0, 8.0), MISSING);
+        assert!(update.unloaded.is_empty());
+        assert_eq!(world.chunk_count(), 0);
+        assert_eq!(streamer.workers.pending_count(), 5);
+        assert!(streamer.workers.is_pending(ChunkPos(0, 0, 0)));
+        assert!(streamer.workers.pending_positions().all(|pos| distance_squared(ChunkPos(0, 0, 0), pos) <= 1));
+    }
+
+    #[test]
+    fn loads_and_meshes_whole_view() {
+        let mut world = World::new();
+        let mut streamer = streamer(2, 7);
+        let mut meshed = load_all(&mut streamer, &mut world, vec3(8.0, 8.0, 8.0));
+        // 13 columns in the circle, 5 chunks high
+        assert_eq!(world.chunk_count(), 13 * 5);
+        assert!(world.chunk_positions().all(|pos| streamer.is_in_view(ChunkPos(0, 0, 0), pos)));
+        meshed.sort_by_key(|pos| (pos.0, pos.1, pos.2));
+        meshed.dedup();
+        assert_eq!(meshed.len(), 13 * 5);
+        assert!(world.take_dirty_chunks().is_empty());
+        assert_models_match_full_rebuild(&world);
+    }
+
+    #[test]
+    fn unloads_chunks_and_cancels_jobs_out_of_view() {
+        let mut world = World::new();
+        let mut streamer = streamer(2, 100);
+        load_all(&mut streamer, &mut world, vec3(8.0, 8.0, 8.0));
+
+        // one chunk over stays within the unload margin
+        let update = streamer.update(&mut world, vec3(24.0, 8.0, 8.0), MISSING);
+        assert!(update.unloaded.is_empty());
+        load_all(&mut streamer, &mut world, vec3(24.0, 8.0, 8.0));
+
+        let update = streamer.update(&mut world, vec3(8.0 + 16.0 * 10.0, 8.0, 8.0), MISSING);
+        // the second view was loaded whole too
+        assert_eq!(update.unloaded.len(), 13 * 5 + 5 * 5);
+        assert!(update.unloaded.contains(&ChunkPos(-2, 0, 0)));
+        assert!(update.unloaded.contains(&ChunkPos(3, 0, 0)));
+        assert!(world.chunk_positions().all(|pos| streamer.is_in_view(ChunkPos(10, 0, 0), pos)));
+
+        // jobs sent around x = 10 are dropped when the player is back at the start
+        streamer.update(&mut world, vec3(8.0, 8.0, 8.0), MISSING);
+        assert!(streamer.workers.pending_positions().all(|pos| streamer.is_in_view(ChunkPos(0, 0, 0), pos)));
+        load_all(&mut streamer, &mut world, vec3(8.0, 8.0, 8.0));
+        assert_eq!(world.chunk_count(), 13 * 5);
+    }
+
+    #[test]
+    fn streamed_models_match_full_rebuild_after_moving() {
+        let mut world = World::new();
+        let mut streamer = streamer(2, 3);
+        load_all(&mut streamer, &mut world, vec3(8.0, 8.0, 8.0));
+        load_all(&mut streamer, &mut world, vec3(8.0 + 16.0 * 3.0, 8.0, 8.0));
+        assert_models_match_full_rebuild(&world);
+    }
+
+    #[test]
+    fn modified_chunks_survive_unloading() {
+        let dir = TempDir::new("streaming-modified");
+        let source = ChunkSource { save_dir: Some(dir.path().to_path_buf()), ..ChunkSource::generated(TerrainGenerator::new(1)) };
+        let mut streamer = ChunkStreamer::new(ChunkWorkers::new(2, source), 1, 8);
+        let mut world = World::new();
+        load_all(&mut streamer, &mut world, vec3(8.0, 8.0, 8.0));
+
+        let pos = BlockPos::new(3, 40, 3);
+        world.set_block(pos, BlockState::TILE);
+        load_all(&mut streamer, &mut world, vec3(8.0 + 16.0 * 10.0, 8.0, 8.0));
+        assert!(world.get_chunk(pos.chunk_pos()).is_none());
+        load_all(&mut streamer, &mut world, vec3(8.0, 8.0, 8.0));
+        assert_eq!(*world.get_block(pos), BlockState::TILE);
+    }
+
+    #[test]
+    fn modified_chunks_stay_loaded_when_saving_fails() {
+        let dir = TempDir::new("streaming-unwritable");
+        let source = ChunkSource { save_dir: Some(dir.path().join("world")), ..ChunkSource::generated(TerrainGenerator::new(1)) };
+        let mut streamer = ChunkStreamer::new(ChunkWorkers::new(2, source), 1, 8);
+        let mut world = World::new();
+        load_all(&mut streamer, &mut world, vec3(8.0, 8.0, 8.0));
+        // a file where the save directory should be
+        std::fs::create_dir_all(dir.path()).unwrap();
+        std::fs::write(dir.path().join("world"), b"not a directory").unwrap();
+
+        let pos = BlockPos::new(3, 40, 3);
+        world.set_block(pos, BlockState::TILE);
+        let update = streamer.update(&mut world, vec3(8.0 + 16.0 * 10.0, 8.0, 8.0), MISSING);
+        assert!(!update.unloaded.is_empty());
+        assert!(!update.unloaded.contains(&pos.chunk_pos()));
+        assert_eq!(*world.get_block(pos), BlockState::TILE);
+        assert!(world.get_chunk(pos.chunk_pos()).unwrap().is_modified);
+        assert!(world.get_chunk(ChunkPos(0, 0, 0)).is_none());
+    }
+
+    #[test]
+    fn dirty_chunks_are_meshed_again() {
+        let mut world = World::new();
+        let mut streamer = streamer(1, 8);
+        load_all(&mut streamer, &mut world, vec3(8.0, 8.0, 8.0));
+
+        let pos = BlockPos::new(3, 40, 3);
+        world.set_block(pos, BlockState::STONE);
+        crate::world::render::model::update_block_models(&mut world, pos, MISSING);
+        let meshed = load_all(&mut streamer, &mut world, vec3(8.0, 8.0, 8.0));
+        assert_eq!(meshed, vec![ChunkPos(0, 2, 0)]);
+    }
+}
@@ -0,0 +1,193 @@
+use std::array::from_fn as arr_fn;
+
+use worldgen::noise::perlin::{Frequency, Octaves, PerlinNoise};
+use worldgen::noise::NoiseProvider;
+
+use crate::world::palette::PalettedBlocks;
+use crate::world::{Biome, BlockPos, BlockState, Chunk, ChunkPos, CHUNK_SIZE_16};
+
+/// Surface height of the flattest places
+const BASE_HEIGHT: f64 = 12.0;
+/// Max distance of the surface from `BASE_HEIGHT`
+const HEIGHT_AMPLITUDE: f64 = 10.0;
+/// Blocks of the biome subsurface block between the surface and stone
+const SUBSURFACE_DEPTH: i32 = 3;
+
+/// Noises are offset by these, perlin octaves take the next seeds
+const HEIGHT_SEED: u64 = 0;
+const TEMPERATURE_SEED: u64 = 1000;
+const HUMIDITY_SEED: u64 = 2000;
+
+/// Same seed and chunk pos always give the same chunk
+#[derive(Debug, Clone, Copy)]
+pub struct TerrainGenerator {
+    seed: u64,
+    height_noise: PerlinNoise,
+    biome_noise: PerlinNoise,
+}
+
+impl TerrainGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            height_noise: PerlinNoise::new()
+                .set(Octaves::of(4))
+                .set(Frequency::of(1.0 / 48.0)),
+            biome_noise: PerlinNoise::new()
+                .set(Octaves::of(2))
+                .set(Frequency::of(1.0 / 256.0)),
+        }
+    }
+
+    /// Noise of `provider` normalized to -1.0..1.0
+    fn noise(&self, provider: &PerlinNoise, octaves: u32, x: i32, z: i32, offset: u64) -> f64 {
+        let max = (0..octaves).map(|octave| 0.5f64.powi(octave as i32)).sum::<f64>();
+        provider.generate(x as f64, z as f64, self.seed.wrapping_add(offset)) / max
+    }
+
+    /// Global y of the highest solid block of the column
+    pub fn height_at(&self, x: i32, z: i32) -> i32 {
+        let noise = self.noise(&self.height_noise, 4, x, z, HEIGHT_SEED);
+        (BASE_HEIGHT + noise * HEIGHT_AMPLITUDE).round() as i32
+    }
+
+    pub fn biome_at(&self, x: i32, z: i32) -> Biome {
+        let temperature = self.noise(&self.biome_noise, 2, x, z, TEMPERATURE_SEED);
+        let humidity = self.noise(&self.biome_noise, 2, x, z, HUMIDITY_SEED);
+        match (temperature > 0.15, humidity > 0.0) {
+            (true, false) => Biome::Desert,
+            (true, true) => Biome::Jungle,
+            (false, true) => Biome::Forest,
+            (false, false) => Biome::Plains,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn block_at(&self, pos: BlockPos) -> BlockState {
+        let height = self.height_at(pos.x, pos.z);
+        let biome = self.biome_at(pos.x, pos.z);
+        Self::column_block(&biome, height, pos.y)
+    }
+
+    fn column_block(biome: &Biome, height: i32, y: i32) -> BlockState {
+        if y > height {
+            BlockState::AIR
+        } else if y == height {
+            biome.surface_block()
+        } else if y > height - 1 - SUBSURFACE_DEPTH {
+            biome.subsurface_block()
+        } else {
+            BlockState::STONE
+        }
+    }
+
+    /// Overwrites all blocks of `chunk`, the chunk biome is the one at its center
+    pub fn fill_chunk(&self, chunk: &mut Chunk, pos: ChunkPos) {
+        let origin = pos.origin();
+        let columns: [[(i32, Biome); CHUNK_SIZE_16]; CHUNK_SIZE_16] = arr_fn(|x| {
+            arr_fn(|z| {
+                let (world_x, world_z) = (origin.x + x as i32, origin.z + z as i32);
+                (self.height_at(world_x, world_z), self.biome_at(world_x, world_z))
+            })
+        });
+        chunk.blocks = PalettedBlocks::from_fn(|x, y, z| {
+            let (height, biome) = &columns[x][z];
+            Self::column_block(biome, *height, origin.y + y as i32)
+        });
+        let center = CHUNK_SIZE_16 as i32 / 2;
+        chunk.biome = self.biome_at(origin.x + center, origin.z + center);
+        chunk.pos = pos.world_pos();
+    }
+
+    pub fn generate_chunk(&self, pos: ChunkPos) -> Chunk {
+        let mut chunk = Chunk::EMPTY;
+        self.fill_chunk(&mut chunk, pos);
+        chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::BlockType;
+
+    fn column(chunk: &Chunk, x: usize, z: usize) -> Vec<BlockType> {
+        (0..CHUNK_SIZE_16).map(|y| chunk.get(x, y, z).block_type).collect()
+    }
+
+    #[test]
+    fn same_seed_same_chunk() {
+        let pos = ChunkPos(-3, 0, 7);
+        let first = TerrainGenerator::new(42).generate_chunk(pos);
+        let second = TerrainGenerator::new(42).generate_chunk(pos);
+        assert_eq!(first, second);
+
+        let other_seed = TerrainGenerator::new(43).generate_chunk(pos);
+        assert_ne!(first.blocks, other_seed.blocks);
+    }
+
+    #[test]
+    fn heightmap_snapshot() {
+        let generator = TerrainGenerator::new(42);
+        let heights: Vec<i32> = (-8..8).map(|x| generator.height_at(x * 8, 5)).collect();
+        assert_eq!(heights, vec![14, 14, 13, 12, 12, 14, 11, 10, 11, 11, 10, 9, 8, 8, 7, 7]);
+    }
+
+    #[test]
+    fn chunks_agree_with_block_at() {
+        let generator = TerrainGenerator::new(7);
+        for pos in [ChunkPos(0, 0, 0), ChunkPos(-1, 1, 2)] {
+            let chunk = generator.generate_chunk(pos);
+            let origin = pos.origin();
+            for (x, y, z) in [(0, 0, 0), (15, 3, 9), (4, 15, 15), (8, 8, 8)] {
+                let global = origin + BlockPos::new(x as i32, y as i32, z as i32);
+                assert_eq!(*chunk.get(x, y, z), generator.block_at(global));
+            }
+        }
+    }
+
+    #[test]
+    fn plains_layers() {
+        let block = TerrainGenerator::column_block;
+        let column: Vec<_> = (0..8).map(|y| block(&Biome::Plains, 5, y).block_type).collect();
+        assert_eq!(column, vec![
+            BlockType::STONE,
+            BlockType::STONE,
+            BlockType::DIRT,
+            BlockType::DIRT,
+            BlockType::DIRT,
+            BlockType::GRASS,
+            BlockType::AIR,
+            BlockType::AIR,
+        ]);
+    }
+
+    #[test]
+    fn desert_surface_is_sand() {
+        let generator = TerrainGenerator::new(42);
+        let (x, z) = (-512..512)
+            .step_by(8)
+            .flat_map(|x| (-512..512).step_by(8).map(move |z| (x, z)))
+            .find(|&(x, z)| generator.biome_at(x, z) == Biome::Desert)
+            .expect("no desert around spawn");
+        let height = generator.height_at(x, z);
+        for y in height - SUBSURFACE_DEPTH..=height {
+            assert_eq!(generator.block_at(BlockPos::new(x, y, z)), BlockState::SAND);
+        }
+        assert_eq!(generator.block_at(BlockPos::new(x, height - SUBSURFACE_DEPTH - 1, z)), BlockState::STONE);
+        assert_eq!(generator.block_at(BlockPos::new(x, height + 1, z)), BlockState::AIR);
+    }
+
+    #[test]
+    fn generated_chunk_is_solid_below_surface() {
+        let generator = TerrainGenerator::new(1);
+        let chunk = generator.generate_chunk(ChunkPos(2, 0, -2));
+        for x in 0..CHUNK_SIZE_16 {
+            for z in 0..CHUNK_SIZE_16 {
+                let column = column(&chunk, x, z);
+                let first_air = column.iter().position(BlockType::is_empty).unwrap_or(CHUNK_SIZE_16);
+                assert!(column[first_air..].iter().all(BlockType::is_empty));
+            }
+        }
+    }
+}
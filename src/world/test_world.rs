@@ -0,0 +1,14 @@
+use std::ops::Range;
+
+use crate::world::{BlockPos, BlockState, World};
+
+/// Stone at y = 0 over `xs` by `zs`, its top at y = 1
+pub fn stone_floor(xs: Range<i32>, zs: Range<i32>) -> World {
+    let mut world = World::new();
+    for x in xs {
+        for z in zs.clone() {
+            world.set_block(BlockPos::new(x, 0, z), BlockState::STONE);
+        }
+    }
+    world
+}
@@ -0,0 +1,390 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::world::light::{BorderLight, ChunkLight};
+use crate::world::render::mesh::{build_chunk_mesh, ChunkMesh};
+use crate::world::render::model::build_connected_chunk_model;
+use crate::world::region;
+use crate::world::render::occlusion::ChunkShell;
+use crate::world::render::{ChunkModel, ConnectedChunks, FaceShading};
+use crate::world::terrain::TerrainGenerator;
+use crate::world::{Chunk, ChunkLayer, ChunkPos};
+
+/// Where chunks come from: saved chunks first, the rest is generated
+#[derive(Debug, Clone)]
+pub struct ChunkSource {
+    pub generator: TerrainGenerator,
+    pub save_dir: Option<PathBuf>,
+    /// How the sides of the meshed chunks are shaded
+    pub shading: FaceShading,
+}
+
+impl ChunkSource {
+    #[allow(dead_code)]
+    pub const fn generated(generator: TerrainGenerator) -> Self {
+        Self { generator, save_dir: None, shading: FaceShading::DEFAULT }
+    }
+
+    /// A chunk that fails to load is generated again and marked `load_failed`,
+    /// so the broken save is kept rather than overwritten
+    pub fn load_or_generate(&self, pos: ChunkPos) -> Chunk {
+        if let Some(dir) = &self.save_dir {
+            match region::load_chunk(dir, pos) {
+                Ok(Some(chunk)) => return chunk,
+                Ok(None) => {}
+                Err(err) => {
+                    eprintln!("Failed to load chunk {:?}, it won't be saved: {}", pos, err);
+                    let mut chunk = self.generator.generate_chunk(pos);
+                    chunk.load_failed = true;
+                    return chunk;
+                }
+            }
+        }
+        self.generator.generate_chunk(pos)
+    }
+}
+
+pub enum ChunkJob {
+    /// Generates the chunk, its model is culled against these neighbour border layers,
+    /// in `RenderCubeByte` bits order, see `model::neighbour_border_layers`.
+    /// The chunk is lit with the neighbour border light, see `light::neighbour_border_light`,
+    /// and its border faces are shaded by the blocks around it, see `ChunkShell::around`
+    Generate(Box<[ChunkLayer; 6]>, Box<BorderLight>, Box<ChunkShell>),
+    /// Meshes the model of a loaded chunk
+    Mesh(Box<ChunkModel>),
+}
+
+#[derive(Debug)]
+pub struct ChunkResult {
+    pub pos: ChunkPos,
+    /// Generated chunk with its light and model built, `None` for mesh jobs
+    pub chunk: Option<Chunk>,
+    /// Neighbour border light the generated chunk was lit with, `None` for mesh jobs
+    pub border_light: Option<Box<BorderLight>>,
+    pub mesh: ChunkMesh,
+}
+
+struct Request {
+    id: u64,
+    pos: ChunkPos,
+    job: ChunkJob,
+    cancelled: Arc<AtomicBool>,
+}
+
+struct Response {
+    id: u64,
+    result: ChunkResult,
+}
+
+struct Ticket {
+    id: u64,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// Threads that generate and mesh chunks. Only the latest request of a chunk is answered,
+/// older and cancelled ones are skipped by the workers or dropped on arrival
+pub struct ChunkWorkers {
+    requests: Option<Sender<Request>>,
+    responses: Receiver<Response>,
+    pending: HashMap<ChunkPos, Ticket>,
+    next_id: u64,
+    threads: Vec<JoinHandle<()>>,
+    source: ChunkSource,
+}
+
+impl ChunkWorkers {
+    pub fn new(thread_count: usize, source: ChunkSource) -> Self {
+        let (request_sender, request_receiver) = channel();
+        let request_receiver = Arc::new(Mutex::new(request_receiver));
+        let (response_sender, responses) = channel();
+        let threads = (0..thread_count.max(1))
+            .map(|n| {
+                let requests = Arc::clone(&request_receiver);
+                let responses = response_sender.clone();
+                let source = source.clone();
+                thread::Builder::new()
+                    .name(format!("chunk-worker-{}", n))
+                    .spawn(move || work(source, requests, responses))
+                    .expect("failed to spawn chunk worker")
+            })
+            .collect();
+        Self {
+            requests: Some(request_sender),
+            responses,
+            pending: HashMap::new(),
+            next_id: 0,
+            threads,
+            source,
+        }
+    }
+
+    /// One thread per core, the main thread keeps one for itself
+    pub fn with_available_threads(source: ChunkSource) -> Self {
+        let cores = thread::available_parallelism().map_or(2, |n| n.get());
+        Self::new(cores.saturating_sub(1), source)
+    }
+
+    pub fn source(&self) -> &ChunkSource {
+        &self.source
+    }
+
+    /// Replaces the pending request of the chunk, if there is one
+    pub fn request(&mut self, pos: ChunkPos, job: ChunkJob) {
+        self.cancel(pos);
+        self.next_id += 1;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let request = Request { id: self.next_id, pos, job, cancelled: Arc::clone(&cancelled) };
+        if let Some(requests) = &self.requests {
+            if requests.send(request).is_ok() {
+                self.pending.insert(pos, Ticket { id: self.next_id, cancelled });
+            }
+        }
+    }
+
+    /// Returns false if nothing was pending for the chunk
+    pub fn cancel(&mut self, pos: ChunkPos) -> bool {
+        match self.pending.remove(&pos) {
+            Some(ticket) => {
+                ticket.cancelled.store(true, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_pending(&self, pos: ChunkPos) -> bool {
+        self.pending.contains_key(&pos)
+    }
+
+    pub fn pending_positions(&self) -> impl Iterator<Item = ChunkPos> + '_ {
+        self.pending.keys().copied()
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// At most `max` finished results, doesn't block
+    pub fn poll(&mut self, max: usize) -> Vec<ChunkResult> {
+        let mut results = vec![];
+        while results.len() < max {
+            let Ok(response) = self.responses.try_recv() else { break };
+            results.extend(self.accept(response));
+        }
+        results
+    }
+
+    /// Blocks until a result arrives or `timeout` passes
+    #[allow(dead_code)]
+    pub fn wait(&mut self, timeout: Duration) -> Option<ChunkResult> {
+        let deadline = Instant::now() + timeout;
+        while !self.pending.is_empty() {
+            let left = deadline.saturating_duration_since(Instant::now());
+            match self.responses.recv_timeout(left) {
+                Ok(response) => {
+                    if let Some(result) = self.accept(response) {
+                        return Some(result);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+        None
+    }
+
+    /// Drops responses to replaced or cancelled requests
+    fn accept(&mut self, response: Response) -> Option<ChunkResult> {
+        let pos = response.result.pos;
+        match self.pending.get(&pos) {
+            Some(ticket) if ticket.id == response.id => {
+                self.pending.remove(&pos);
+                Some(response.result)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Drop for ChunkWorkers {
+    fn drop(&mut self) {
+        for ticket in self.pending.values() {
+            ticket.cancelled.store(true, Ordering::Relaxed);
+        }
+        // closing the channel stops the workers
+        self.requests = None;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn work(source: ChunkSource, requests: Arc<Mutex<Receiver<Request>>>, responses: Sender<Response>) {
+    loop {
+        let request = match requests.lock().unwrap().recv() {
+            Ok(request) => request,
+            Err(_) => return,
+        };
+        if request.cancelled.load(Ordering::Relaxed) {
+            continue;
+        }
+        let result = run_job(&source, request.pos, request.job);
+        if request.cancelled.load(Ordering::Relaxed) {
+            continue;
+        }
+        if responses.send(Response { id: request.id, result }).is_err() {
+            return;
+        }
+    }
+}
+
+/// What a worker does for one request
+pub fn run_job(source: &ChunkSource, pos: ChunkPos, job: ChunkJob) -> ChunkResult {
+    match job {
+        ChunkJob::Generate(layers, light, shell) => {
+            let mut chunk = source.load_or_generate(pos);
+            chunk.light = ChunkLight::compute(&chunk, &light);
+            build_connected_chunk_model(&mut chunk, &ConnectedChunks::from_layers(&layers), &light, &shell);
+            chunk.is_dirty = false;
+            let mesh = build_chunk_mesh(&chunk.model, chunk.get_pos(), &source.shading);
+            ChunkResult { pos, chunk: Some(chunk), border_light: Some(light), mesh }
+        }
+        ChunkJob::Mesh(model) => ChunkResult {
+            pos,
+            chunk: None,
+            border_light: None,
+            mesh: build_chunk_mesh(&model, pos.world_pos(), &source.shading),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use macroquad::math::Vec3;
+
+    use super::*;
+    use crate::world::light::NO_BORDER_LIGHT;
+    use crate::world::render::model::{build_chunk_model, neighbour_border_layers, MissingNeighbour};
+    use crate::world::temp_dir::TempDir;
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn generator() -> TerrainGenerator {
+        TerrainGenerator::new(3)
+    }
+
+    fn source() -> ChunkSource {
+        ChunkSource::generated(generator())
+    }
+
+    fn air_layers() -> Box<[ChunkLayer; 6]> {
+        Box::new(neighbour_border_layers([None; 6], MissingNeighbour::Air))
+    }
+
+    fn positions(mesh: &ChunkMesh) -> Vec<Vec3> {
+        mesh.opaque.iter().flatten().flat_map(|buffers| buffers.vertices.iter().map(|vertex| vertex.position)).collect()
+    }
+
+    #[test]
+    fn generated_chunk_matches_main_thread_build() {
+        let pos = ChunkPos(2, 0, -1);
+        let mut workers = ChunkWorkers::new(2, source());
+        workers.request(pos, ChunkJob::Generate(air_layers(), Box::new(NO_BORDER_LIGHT), Box::new(ChunkShell::empty())));
+        let result = workers.wait(TIMEOUT).unwrap();
+        assert_eq!(result.pos, pos);
+        assert_eq!(workers.pending_count(), 0);
+
+        let mut expected = generator().generate_chunk(pos);
+        expected.light = ChunkLight::compute(&expected, &NO_BORDER_LIGHT);
+        build_chunk_model(&mut expected);
+        let chunk = result.chunk.unwrap();
+        assert_eq!(chunk.blocks, expected.blocks);
+        assert_eq!(chunk.light, expected.light);
+        assert_eq!(chunk.model, expected.model);
+        assert!(!chunk.is_dirty);
+
+        let expected_mesh = build_chunk_mesh(&expected.model, expected.get_pos(), &FaceShading::DEFAULT);
+        assert!(result.mesh.quad_count() > 0);
+        assert_eq!(result.mesh.index_count(), expected_mesh.index_count());
+        assert_eq!(positions(&result.mesh), positions(&expected_mesh));
+    }
+
+    #[test]
+    fn saved_chunks_are_loaded_instead_of_generated() {
+        let dir = TempDir::new("workers-saved");
+        let saved = Chunk::from_fn(|_, y, _| if y < 2 { crate::world::BlockState::TILE } else { crate::world::BlockState::AIR });
+        region::save_chunks(dir.path(), [(ChunkPos(0, 0, 0), &saved)]).unwrap();
+
+        let source = ChunkSource { save_dir: Some(dir.path().to_path_buf()), ..source() };
+        let loaded = run_job(&source, ChunkPos(0, 0, 0), ChunkJob::Generate(air_layers(), Box::new(NO_BORDER_LIGHT), Box::new(ChunkShell::empty()))).chunk.unwrap();
+        let generated = run_job(&source, ChunkPos(1, 0, 0), ChunkJob::Generate(air_layers(), Box::new(NO_BORDER_LIGHT), Box::new(ChunkShell::empty()))).chunk.unwrap();
+
+        assert_eq!(loaded.blocks, saved.blocks);
+        assert_eq!(generated.blocks, generator().generate_chunk(ChunkPos(1, 0, 0)).blocks);
+    }
+
+    #[test]
+    fn chunks_failing_to_load_are_never_saved_over() {
+        let dir = TempDir::new("workers-broken");
+        std::fs::create_dir_all(dir.path()).unwrap();
+        let path = region::region_path(dir.path(), ChunkPos(0, 0, 0));
+        std::fs::write(&path, b"broken").unwrap();
+
+        let source = ChunkSource { save_dir: Some(dir.path().to_path_buf()), ..source() };
+        let mut chunk = source.load_or_generate(ChunkPos(0, 0, 0));
+        assert!(chunk.load_failed);
+        assert_eq!(chunk.blocks, generator().generate_chunk(ChunkPos(0, 0, 0)).blocks);
+
+        chunk.is_modified = true;
+        let mut world = crate::world::World::new();
+        world.insert_chunk(ChunkPos(0, 0, 0), chunk);
+        world.save(dir.path()).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"broken");
+    }
+
+    #[test]
+    fn mesh_job_meshes_given_model() {
+        let pos = ChunkPos(0, 0, 0);
+        let mut chunk = Chunk::from_fn(|x, y, z| {
+            if x == y && y == z { crate::world::BlockState::STONE } else { crate::world::BlockState::AIR }
+        });
+        build_chunk_model(&mut chunk);
+        let mut workers = ChunkWorkers::new(1, source());
+        workers.request(pos, ChunkJob::Mesh(Box::new(chunk.model.clone())));
+        let result = workers.wait(TIMEOUT).unwrap();
+        assert!(result.chunk.is_none());
+        assert_eq!(result.mesh.quad_count(), 16 * 6);
+    }
+
+    #[test]
+    fn only_latest_request_is_answered() {
+        let pos = ChunkPos(0, 0, 0);
+        let mut workers = ChunkWorkers::new(2, source());
+        workers.request(pos, ChunkJob::Mesh(Box::default()));
+        workers.request(pos, ChunkJob::Generate(air_layers(), Box::new(NO_BORDER_LIGHT), Box::new(ChunkShell::empty())));
+        assert_eq!(workers.pending_count(), 1);
+        let result = workers.wait(TIMEOUT).unwrap();
+        assert!(result.chunk.is_some());
+        assert!(workers.wait(Duration::from_millis(50)).is_none());
+    }
+
+    #[test]
+    fn cancelled_requests_give_nothing() {
+        let mut workers = ChunkWorkers::new(2, source());
+        for x in 0..16 {
+            workers.request(ChunkPos(x, 0, 0), ChunkJob::Generate(air_layers(), Box::new(NO_BORDER_LIGHT), Box::new(ChunkShell::empty())));
+        }
+        for x in 0..16 {
+            assert!(workers.cancel(ChunkPos(x, 0, 0)));
+        }
+        assert!(!workers.cancel(ChunkPos(0, 0, 0)));
+        assert_eq!(workers.pending_count(), 0);
+        assert!(workers.wait(Duration::from_millis(50)).is_none());
+        thread::sleep(Duration::from_millis(50));
+        assert!(workers.poll(usize::MAX).is_empty());
+    }
+}